@@ -0,0 +1,59 @@
+//! Compares the grid broad phase against the brute-force search it replaced.
+//!
+//! Run with `cargo bench --bench collisions`.
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+use rand::{Rng, SeedableRng};
+
+use ld46_keep_it_alive::collision::{find_collisions, find_collisions_brute_force};
+use ld46_keep_it_alive::ecs::{Collider, Sweep, Transform, World};
+use ld46_keep_it_alive::simulation::GameRng;
+
+/// A field of meteor sized colliders, some of them just past the edges like
+/// objects that are about to wrap around, and every tenth one a fast swept shot.
+fn crowded_world(count: usize) -> World {
+    let mut rng = GameRng::seed_from_u64(count as u64);
+    let mut world = World::new();
+    for i in 0..count {
+        let pos_x = rng.gen_range(-0.02, 1.02);
+        let pos_y = rng.gen_range(-0.02, 1.02);
+        let entity = world.spawn(Transform {
+            pos_x,
+            pos_y,
+            ..Default::default()
+        });
+        let radius = rng.gen_range(0.001, 0.02);
+        world.colliders.insert(entity, Collider::circle(radius));
+        if i % 10 == 0 {
+            let sweep = Sweep {
+                from_x: pos_x - rng.gen_range(-0.03, 0.03),
+                from_y: pos_y - rng.gen_range(-0.03, 0.03),
+            };
+            world.sweeps.insert(entity, sweep);
+        }
+    }
+    world
+}
+
+fn bench_collisions(c: &mut Criterion) {
+    let mut group = c.benchmark_group("find_collisions");
+    // Brute force takes seconds per run at 10k, so keep the sample count low.
+    group.sample_size(10);
+    for &count in &[1_000, 10_000] {
+        let world = crowded_world(count);
+        assert_eq!(find_collisions(&world), find_collisions_brute_force(&world));
+
+        group.bench_with_input(BenchmarkId::new("grid", count), &world, |b, world| {
+            b.iter(|| find_collisions(world))
+        });
+        group.bench_with_input(
+            BenchmarkId::new("brute_force", count),
+            &world,
+            |b, world| b.iter(|| find_collisions_brute_force(world)),
+        );
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_collisions);
+criterion_main!(benches);
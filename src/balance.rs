@@ -0,0 +1,449 @@
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use serde::{Deserialize, Serialize};
+
+use crate::shape::ShapeSettings;
+
+/// File looked up in the resource directory when no other config is given.
+pub const DEFAULT_BALANCE_FILE: &str = "balance.toml";
+
+/// Gameplay tunables. Every value can be overridden from a TOML file; anything
+/// left out keeps the compiled-in default below.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Balance {
+    pub max_acc_x: f32,
+    pub max_acc_y: f32,
+    pub max_speed_x: f32,
+    pub max_speed_y: f32,
+    pub acc_step_x: f32,
+    pub acc_step_y: f32,
+    /// Radians per tick the ship turns with the thrust flight model.
+    pub ship_turn_rate: f32,
+    /// Speed gained per tick of thrust with the thrust flight model.
+    pub ship_thrust: f32,
+    /// Share of its speed the ship loses every tick with the thrust flight model.
+    pub ship_drag: f32,
+
+    pub meteor_base_max_size: f32,
+    pub meteor_base_min_size: f32,
+    pub meteor_destroy_radius: f32,
+    /// Seconds between meteors at the start; shrinks as progress rises.
+    pub meteor_base_spawn_interval: f32,
+    pub meteor_min_velocity: f32,
+    pub meteor_max_velocity: f32,
+    /// Share of the closing speed two meteors keep after bouncing off each other,
+    /// from 0 (they stick) to 1 (perfectly elastic).
+    pub meteor_restitution: f32,
+    /// Impact energy per unit of meteor mass that shatters it completely; weaker
+    /// hits break off a proportional share of its area as fragments.
+    pub meteor_fracture_energy: f32,
+    /// How far noise pushes a meteor's outline in or out, as a share of its radius.
+    pub meteor_roughness: f32,
+    /// Craters pressed into every meteor's outline.
+    pub meteor_crater_count: u32,
+    /// Depth of the deepest craters, as a share of the meteor's radius.
+    pub meteor_crater_depth: f32,
+
+    pub population_start: f32,
+    /// Population growth per tick.
+    pub pop_multi_factor: f32,
+    pub victory_progress_tick: f32,
+
+    pub overpop_limit: f32,
+    pub overpop_warning_number: f32,
+    /// Seconds between overpopulation warnings, at the least.
+    pub overpop_min_warning_interval: f32,
+
+    /// Seconds between shots while the fire button is held.
+    pub shooting_speed: f32,
+    pub projectile_speed: f32,
+    pub projectile_radius: f32,
+
+    /// Population lost on impact: `earth_damage_factor * radius ^ earth_damage_exponent`.
+    pub earth_damage_factor: f32,
+    pub earth_damage_exponent: f32,
+    /// Ship HP lost on impact: `ship_damage_factor * radius ^ ship_damage_exponent`.
+    pub ship_damage_factor: f32,
+    pub ship_damage_exponent: f32,
+
+    /// Strength of gravity in modes that have it. A body pulls with
+    /// `gravity_constant * radius ^ 2 / distance ^ 2`.
+    pub gravity_constant: f32,
+
+    /// Points an endless run scores for every meteor shot apart.
+    pub score_per_meteor: f32,
+    /// Points per million people alive when an endless run ends.
+    pub score_per_million: f32,
+    /// Points per second an endless run lasts.
+    pub score_per_second: f32,
+}
+
+impl Default for Balance {
+    fn default() -> Self {
+        Balance {
+            max_acc_x: 0.00005,
+            max_acc_y: 0.00005,
+            max_speed_x: 0.005,
+            max_speed_y: 0.005,
+            acc_step_x: 0.00001,
+            acc_step_y: 0.00001,
+            ship_turn_rate: 0.07,
+            ship_thrust: 0.00005,
+            ship_drag: 0.01,
+
+            meteor_base_max_size: 0.015,
+            meteor_base_min_size: 0.007,
+            meteor_destroy_radius: 0.001,
+            meteor_base_spawn_interval: 1.8,
+            meteor_min_velocity: 0.0003,
+            meteor_max_velocity: 0.001,
+            meteor_restitution: 0.8,
+            meteor_fracture_energy: 0.000001,
+            meteor_roughness: 0.15,
+            meteor_crater_count: 2,
+            meteor_crater_depth: 0.12,
+
+            population_start: 1200.0,
+            pop_multi_factor: 1.0005,
+            victory_progress_tick: 0.00015,
+
+            overpop_limit: 10000.0,
+            overpop_warning_number: 7000.0,
+            overpop_min_warning_interval: 30.0,
+
+            shooting_speed: 0.15,
+            projectile_speed: 0.01,
+            projectile_radius: 0.001,
+
+            earth_damage_factor: 100.0 * 100.0 * 13.0 * 100.0,
+            earth_damage_exponent: 2.0,
+            ship_damage_factor: 100.0 * 100.0 * 13.0 * 2.0,
+            ship_damage_exponent: 2.0,
+
+            gravity_constant: 0.00002,
+
+            score_per_meteor: 10.0,
+            score_per_million: 0.1,
+            score_per_second: 5.0,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum BalanceError {
+    Io(String),
+    Parse(String),
+    Invalid { key: &'static str, message: String },
+}
+
+impl fmt::Display for BalanceError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            BalanceError::Io(message) => write!(f, "{}", message),
+            BalanceError::Parse(message) => write!(f, "{}", message),
+            BalanceError::Invalid { key, message } => write!(f, "`{}` {}", key, message),
+        }
+    }
+}
+
+impl std::error::Error for BalanceError {}
+
+impl Balance {
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Balance, BalanceError> {
+        let path = path.as_ref();
+        let contents = fs::read_to_string(path)
+            .map_err(|e| BalanceError::Io(format!("{}: {}", path.display(), e)))?;
+        Balance::parse(&contents)
+    }
+
+    pub fn parse(contents: &str) -> Result<Balance, BalanceError> {
+        let balance: Balance =
+            toml::from_str(contents).map_err(|e| BalanceError::Parse(e.to_string()))?;
+        balance.validate()?;
+        Ok(balance)
+    }
+
+    pub fn to_toml(&self) -> String {
+        toml::to_string(self).expect("Balance always serializes")
+    }
+
+    /// Describes every value that differs between `self` and `other`, one line per key.
+    pub fn diff(&self, other: &Balance) -> Vec<String> {
+        let old = toml::Value::try_from(self).expect("Balance always serializes");
+        let new = toml::Value::try_from(other).expect("Balance always serializes");
+        let mut changes = Vec::new();
+        if let (Some(old), Some(new)) = (old.as_table(), new.as_table()) {
+            for (key, new_value) in new {
+                match old.get(key) {
+                    Some(old_value) if old_value == new_value => {}
+                    Some(old_value) => changes.push(format!(
+                        "{}: {} -> {}",
+                        key,
+                        display_value(old_value),
+                        display_value(new_value)
+                    )),
+                    None => changes.push(format!("{}: {}", key, display_value(new_value))),
+                }
+            }
+        }
+        changes
+    }
+
+    pub fn validate(&self) -> Result<(), BalanceError> {
+        for &(key, value) in &[
+            ("max_acc_x", self.max_acc_x),
+            ("max_acc_y", self.max_acc_y),
+            ("max_speed_x", self.max_speed_x),
+            ("max_speed_y", self.max_speed_y),
+            ("acc_step_x", self.acc_step_x),
+            ("acc_step_y", self.acc_step_y),
+            ("ship_turn_rate", self.ship_turn_rate),
+            ("ship_thrust", self.ship_thrust),
+            ("meteor_base_max_size", self.meteor_base_max_size),
+            ("meteor_base_min_size", self.meteor_base_min_size),
+            ("meteor_destroy_radius", self.meteor_destroy_radius),
+            ("meteor_base_spawn_interval", self.meteor_base_spawn_interval),
+            ("meteor_min_velocity", self.meteor_min_velocity),
+            ("meteor_max_velocity", self.meteor_max_velocity),
+            ("meteor_fracture_energy", self.meteor_fracture_energy),
+            ("population_start", self.population_start),
+            ("pop_multi_factor", self.pop_multi_factor),
+            ("overpop_limit", self.overpop_limit),
+            ("shooting_speed", self.shooting_speed),
+            ("projectile_speed", self.projectile_speed),
+            ("projectile_radius", self.projectile_radius),
+        ] {
+            check(key, value, value > 0.0, "must be greater than 0")?;
+        }
+        for &(key, value) in &[
+            ("meteor_roughness", self.meteor_roughness),
+            ("meteor_crater_depth", self.meteor_crater_depth),
+            ("victory_progress_tick", self.victory_progress_tick),
+            ("overpop_warning_number", self.overpop_warning_number),
+            ("overpop_min_warning_interval", self.overpop_min_warning_interval),
+            ("earth_damage_factor", self.earth_damage_factor),
+            ("earth_damage_exponent", self.earth_damage_exponent),
+            ("ship_damage_factor", self.ship_damage_factor),
+            ("ship_damage_exponent", self.ship_damage_exponent),
+            ("gravity_constant", self.gravity_constant),
+            ("score_per_meteor", self.score_per_meteor),
+            ("score_per_million", self.score_per_million),
+            ("score_per_second", self.score_per_second),
+        ] {
+            check(key, value, value >= 0.0, "must not be negative")?;
+        }
+
+        check(
+            "meteor_base_min_size",
+            self.meteor_base_min_size,
+            self.meteor_base_min_size <= self.meteor_base_max_size,
+            "must not be larger than `meteor_base_max_size`",
+        )?;
+        check(
+            "meteor_min_velocity",
+            self.meteor_min_velocity,
+            self.meteor_min_velocity <= self.meteor_max_velocity,
+            "must not be larger than `meteor_max_velocity`",
+        )?;
+        check(
+            "meteor_destroy_radius",
+            self.meteor_destroy_radius,
+            self.meteor_destroy_radius < self.meteor_base_min_size,
+            "must be smaller than `meteor_base_min_size`",
+        )?;
+        check(
+            "ship_drag",
+            self.ship_drag,
+            (0.0..1.0).contains(&self.ship_drag),
+            "must be at least 0 and less than 1",
+        )?;
+        check(
+            "meteor_restitution",
+            self.meteor_restitution,
+            (0.0..=1.0).contains(&self.meteor_restitution),
+            "must be between 0 and 1",
+        )?;
+        check(
+            "population_start",
+            self.population_start,
+            self.population_start < self.overpop_limit,
+            "must be smaller than `overpop_limit`",
+        )?;
+        check(
+            "overpop_warning_number",
+            self.overpop_warning_number,
+            self.overpop_warning_number <= self.overpop_limit,
+            "must not be larger than `overpop_limit`",
+        )?;
+        Ok(())
+    }
+
+    pub fn meteor_shape(&self) -> ShapeSettings {
+        ShapeSettings {
+            roughness: self.meteor_roughness,
+            crater_count: self.meteor_crater_count,
+            crater_depth: self.meteor_crater_depth,
+        }
+    }
+
+    pub fn earth_damage(&self, radius: f32) -> f32 {
+        self.earth_damage_factor * radius.powf(self.earth_damage_exponent)
+    }
+
+    pub fn ship_damage(&self, radius: f32) -> f32 {
+        self.ship_damage_factor * radius.powf(self.ship_damage_exponent)
+    }
+}
+
+/// Notices when a balance file is created or modified so it can be applied to a
+/// running game.
+pub struct BalanceWatcher {
+    path: PathBuf,
+    last_modified: Option<SystemTime>,
+}
+
+impl BalanceWatcher {
+    pub fn new<P: Into<PathBuf>>(path: P) -> BalanceWatcher {
+        let path = path.into();
+        let last_modified = modified_time(&path);
+        BalanceWatcher {
+            path,
+            last_modified,
+        }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Returns the freshly loaded balance if the file changed since the last poll.
+    pub fn poll(&mut self) -> Option<Result<Balance, BalanceError>> {
+        let modified = modified_time(&self.path);
+        if modified.is_none() || modified == self.last_modified {
+            return None;
+        }
+        self.last_modified = modified;
+        Some(Balance::load(&self.path))
+    }
+
+    /// Like `poll`, but swaps a successfully reloaded balance into `balance` and
+    /// returns what changed. A rejected file leaves `balance` as it was.
+    pub fn apply(&mut self, balance: &mut Balance) -> Option<Result<Vec<String>, BalanceError>> {
+        let reloaded = match self.poll()? {
+            Ok(reloaded) => reloaded,
+            Err(e) => return Some(Err(e)),
+        };
+        let changes = balance.diff(&reloaded);
+        *balance = reloaded;
+        Some(Ok(changes))
+    }
+}
+
+// Tunables are f32, so show them as such instead of the widened f64 TOML keeps.
+fn display_value(value: &toml::Value) -> String {
+    match value {
+        toml::Value::Float(f) => format!("{}", *f as f32),
+        other => other.to_string(),
+    }
+}
+
+fn modified_time(path: &Path) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+fn check(key: &'static str, value: f32, ok: bool, message: &str) -> Result<(), BalanceError> {
+    if !value.is_finite() {
+        return Err(BalanceError::Invalid {
+            key,
+            message: "must be a finite number".to_string(),
+        });
+    }
+    if !ok {
+        return Err(BalanceError::Invalid {
+            key,
+            message: format!("{} (got {})", message, value),
+        });
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rejection(contents: &str) -> String {
+        Balance::parse(contents).unwrap_err().to_string()
+    }
+
+    fn temp_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("ld46-balance-{}-{}.toml", name, std::process::id()))
+    }
+
+    #[test]
+    fn shipped_balance_file_is_valid() {
+        let contents = include_str!("../resources/balance.toml");
+        assert!(Balance::parse(contents).is_ok());
+    }
+
+    #[test]
+    fn negative_value_is_rejected_by_name() {
+        let message = rejection("score_per_meteor = -1.0\n");
+        assert!(message.contains("score_per_meteor"), "{}", message);
+    }
+
+    #[test]
+    fn out_of_range_value_is_rejected_by_name() {
+        let message = rejection("meteor_restitution = 1.5\n");
+        assert!(message.contains("meteor_restitution"), "{}", message);
+        let message = rejection("ship_drag = 1.0\n");
+        assert!(message.contains("ship_drag"), "{}", message);
+    }
+
+    #[test]
+    fn unknown_key_is_rejected_by_name() {
+        let message = rejection("meteor_colour = 3.0\n");
+        assert!(message.contains("meteor_colour"), "{}", message);
+    }
+
+    #[test]
+    fn failed_reload_keeps_the_previous_balance() {
+        let path = temp_path("reload");
+        let _ = fs::remove_file(&path);
+        let mut watcher = BalanceWatcher::new(&path);
+        let mut balance = Balance {
+            max_speed_x: 0.004,
+            ..Balance::default()
+        };
+        let previous = balance.clone();
+        assert!(watcher.apply(&mut balance).is_none());
+
+        fs::write(&path, "max_speed_x = 0.006\nmeteor_restitution = -0.5\n").unwrap();
+        let result = watcher.apply(&mut balance);
+        let _ = fs::remove_file(&path);
+
+        let message = result.unwrap().unwrap_err().to_string();
+        assert!(message.contains("meteor_restitution"), "{}", message);
+        assert_eq!(balance, previous);
+    }
+
+    #[test]
+    fn successful_reload_reports_the_changes() {
+        let path = temp_path("apply");
+        let _ = fs::remove_file(&path);
+        let mut watcher = BalanceWatcher::new(&path);
+        let mut balance = Balance::default();
+
+        fs::write(&path, "max_speed_x = 0.006\n").unwrap();
+        let result = watcher.apply(&mut balance);
+        let _ = fs::remove_file(&path);
+
+        let changes = result.unwrap().unwrap();
+        assert_eq!(changes.len(), 1);
+        assert!(changes[0].starts_with("max_speed_x"));
+        assert_eq!(balance.max_speed_x, 0.006);
+    }
+}
@@ -0,0 +1,158 @@
+//! Re-simulates a recorded run without opening a window or an audio device and
+//! prints how it ended as JSON.
+//!
+//! Exit codes: 0 when the simulated outcome matches the one claimed by the replay,
+//! 1 on a mismatch, 2 when the replay can't be read and 3 when the replay claims
+//! no outcome, so there was nothing to check it against.
+
+use std::env;
+use std::process;
+
+use ld46_keep_it_alive::replay::{result_to_str, Replay, ReplayOutcome, ReplayPlayer};
+use ld46_keep_it_alive::simulation::Simulation;
+
+fn json_string(value: Option<&str>) -> String {
+    match value {
+        Some(value) => format!("\"{}\"", value.replace('\\', "\\\\").replace('"', "\\\"")),
+        None => "null".to_string(),
+    }
+}
+
+/// JSON has no NaN or infinity, so those become null.
+fn json_number(value: f32) -> String {
+    if value.is_finite() {
+        format!("{:?}", value)
+    } else {
+        "null".to_string()
+    }
+}
+
+/// The re-simulation has to reproduce the recorded floats exactly; a non-finite
+/// value never counts as a match.
+fn same_float(claimed: f32, simulated: f32) -> bool {
+    claimed.is_finite() && claimed.to_bits() == simulated.to_bits()
+}
+
+fn same_outcome(claimed: &ReplayOutcome, simulated: &ReplayOutcome) -> bool {
+    claimed.result == simulated.result
+        && claimed.ticks == simulated.ticks
+        && same_float(claimed.population_million, simulated.population_million)
+        && same_float(claimed.spaceship_hp, simulated.spaceship_hp)
+        && claimed.score == simulated.score
+}
+
+fn main() {
+    let path = match env::args().nth(1) {
+        Some(path) => path,
+        None => {
+            eprintln!("Usage: verify-replay <replay-file>");
+            process::exit(2);
+        }
+    };
+    let replay = match Replay::load(&path) {
+        Ok(replay) => replay,
+        Err(e) => {
+            eprintln!("Failed to load replay {}: {}", path, e);
+            process::exit(2);
+        }
+    };
+
+    let claimed = replay.outcome.clone();
+    let mut sim = Simulation::new(
+        replay.seed,
+        replay.mode,
+        replay.flight_model,
+        replay.balance.clone(),
+        replay.level.clone(),
+        replay.endless,
+    );
+    // Stop on the tick the game ended, so everything reported is from that tick;
+    // without an ending, it is all from the last one.
+    let mut player = ReplayPlayer::new(replay);
+    while let Some(input) = player.next_input() {
+        sim.tick(input);
+        if sim.victory_result.is_some() {
+            break;
+        }
+    }
+    let simulated = ReplayOutcome::of(&sim);
+    let score = sim.score();
+
+    let matches = match (&claimed, &simulated) {
+        (Some(claimed), Some(simulated)) => Some(same_outcome(claimed, simulated)),
+        (Some(_), None) => Some(false),
+        (None, _) => None,
+    };
+
+    println!("{{");
+    println!("  \"mode\": \"{}\",", sim.mode.name());
+    println!("  \"flight_model\": \"{}\",", sim.flight_model.name());
+    println!(
+        "  \"level\": {},",
+        json_string(sim.level.as_ref().map(|level| level.name.as_str()))
+    );
+    println!("  \"endless\": {},", sim.is_endless());
+    println!(
+        "  \"result\": {},",
+        json_string(simulated.as_ref().map(|o| result_to_str(&o.result)))
+    );
+    println!(
+        "  \"population_million\": {},",
+        json_number(sim.population_million)
+    );
+    println!("  \"spaceship_hp\": {},", json_number(sim.spaceship_hp));
+    println!(
+        "  \"victory_progress\": {},",
+        json_number(sim.victory_progress)
+    );
+    if sim.is_endless() {
+        println!("  \"meteors_destroyed\": {},", score.meteors_destroyed);
+    }
+    println!("  \"score\": {},", score.total());
+    println!("  \"ticks\": {},", sim.ticks);
+    println!(
+        "  \"claimed_result\": {},",
+        json_string(claimed.as_ref().map(|c| result_to_str(&c.result)))
+    );
+    println!(
+        "  \"claimed_ticks\": {},",
+        claimed
+            .as_ref()
+            .map_or("null".to_string(), |c| c.ticks.to_string())
+    );
+    println!(
+        "  \"claimed_population_million\": {},",
+        claimed
+            .as_ref()
+            .map_or("null".to_string(), |c| json_number(c.population_million))
+    );
+    println!(
+        "  \"claimed_spaceship_hp\": {},",
+        claimed
+            .as_ref()
+            .map_or("null".to_string(), |c| json_number(c.spaceship_hp))
+    );
+    println!(
+        "  \"claimed_score\": {},",
+        claimed
+            .as_ref()
+            .map_or("null".to_string(), |c| c.score.to_string())
+    );
+    println!(
+        "  \"matches\": {}",
+        matches.map_or("null".to_string(), |matches| matches.to_string())
+    );
+    println!("}}");
+
+    match matches {
+        Some(true) => {}
+        Some(false) => process::exit(1),
+        None => {
+            eprintln!(
+                "Warning: {} claims no outcome, so nothing was checked",
+                path
+            );
+            process::exit(3);
+        }
+    }
+}
@@ -0,0 +1,1037 @@
+//! Finding which colliders touch. The grid based broad phase only narrows down
+//! the pairs worth testing; the exact test is the same one the brute-force search
+//! uses, so both always report the same pairs in the same order.
+
+use crate::ecs::{ColliderShape, Entity, Transform, World};
+use crate::shape::Outline;
+
+/// Upper bound on the grid resolution, so huge crowds don't allocate huge grids.
+const MAX_CELLS_PER_SIDE: usize = 64;
+
+/// Grows every collider's bounds a little before bucketing, so rounding in the
+/// exact test can never report a pair the grid kept apart.
+const CELL_MARGIN: f32 = 1e-4;
+
+/// Bumpy outlines have no exact time of impact, so they are checked at points
+/// along the move no further apart than this share of the smaller radius.
+const OUTLINE_STEP: f32 = 0.25;
+
+/// Upper bound on those points, for when a tiny part moves a long way.
+const MAX_OUTLINE_STEPS: f32 = 64.0;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Collision {
+    pub first: Entity,
+    pub second: Entity,
+    /// Where the two touched. When either of them is swept this is the first point
+    /// of contact along their paths, which may be well behind where they ended up.
+    pub impact_x: f32,
+    pub impact_y: f32,
+}
+
+pub fn dist_transform(first: &Transform, second: &Transform) -> f32 {
+    let dx = first.pos_x - second.pos_x;
+    let dy = first.pos_y - second.pos_y;
+    (dx * dx + dy * dy).sqrt()
+}
+
+/// Returns every touching pair, ordered by the first entity and then the second.
+pub fn find_collisions(world: &World) -> Vec<Collision> {
+    let cells_per_side =
+        ((world.colliders.len() as f32).sqrt() as usize).clamp(1, MAX_CELLS_PER_SIDE);
+
+    let bodies = bodies(world);
+    let mut bounds = Vec::with_capacity(bodies.len());
+    let mut cells = vec![Vec::<usize>::new(); cells_per_side * cells_per_side];
+    for (index, body) in bodies.iter().enumerate() {
+        let reach = body.reach() + CELL_MARGIN;
+        let cell_bounds = CellBounds {
+            min_x: cell_index(body.from_x.min(body.to.pos_x) - reach, cells_per_side),
+            max_x: cell_index(body.from_x.max(body.to.pos_x) + reach, cells_per_side),
+            min_y: cell_index(body.from_y.min(body.to.pos_y) - reach, cells_per_side),
+            max_y: cell_index(body.from_y.max(body.to.pos_y) + reach, cells_per_side),
+        };
+        for y in cell_bounds.min_y..=cell_bounds.max_y {
+            for x in cell_bounds.min_x..=cell_bounds.max_x {
+                cells[y * cells_per_side + x].push(index);
+            }
+        }
+        bounds.push(cell_bounds);
+    }
+
+    let mut collisions = Vec::new();
+    for (index, cell) in cells.iter().enumerate() {
+        let x = index % cells_per_side;
+        let y = index / cells_per_side;
+        for (i, &first) in cell.iter().enumerate() {
+            for &second in &cell[i + 1..] {
+                // A pair can share several cells; only test it in the first one.
+                if x != bounds[first].min_x.max(bounds[second].min_x)
+                    || y != bounds[first].min_y.max(bounds[second].min_y)
+                {
+                    continue;
+                }
+                if let Some(collision) = test_pair(&bodies[first], &bodies[second]) {
+                    collisions.push(collision);
+                }
+            }
+        }
+    }
+    // Colliders are bucketed in entity order, so `first` is always the smaller one.
+    collisions.sort_unstable_by_key(|collision| (collision.first, collision.second));
+    collisions
+}
+
+/// Tests every collider against every other one. Kept as the reference the grid
+/// search has to agree with.
+pub fn find_collisions_brute_force(world: &World) -> Vec<Collision> {
+    let bodies = bodies(world);
+    let mut collisions = Vec::<Collision>::new();
+    for (i, first) in bodies.iter().enumerate() {
+        for second in &bodies[i + 1..] {
+            if let Some(collision) = test_pair(first, second) {
+                collisions.push(collision);
+            }
+        }
+    }
+
+    collisions
+}
+
+/// A collider as the narrow phase sees it: where it started the tick and where it
+/// is now. Colliders without a sweep start where they end.
+struct Body<'a> {
+    entity: Entity,
+    from_x: f32,
+    from_y: f32,
+    to: &'a Transform,
+    radius: f32,
+    shape: &'a ColliderShape,
+    outline: Option<&'a Outline>,
+    heading: f32,
+    swept: bool,
+}
+
+impl Body<'_> {
+    /// The furthest the body reaches from its centre.
+    fn reach(&self) -> f32 {
+        let shape_reach = match self.shape {
+            ColliderShape::Circle => self.outline.map_or(1.0, Outline::max_factor),
+            ColliderShape::Polygon(points) => points
+                .iter()
+                .map(|&(x, y)| (x * x + y * y).sqrt())
+                .fold(0.0, f32::max),
+            ColliderShape::Compound(circles) => circles
+                .iter()
+                .map(|&(x, y, radius)| (x * x + y * y).sqrt() + radius)
+                .fold(0.0, f32::max),
+        };
+        self.radius * shape_reach
+    }
+
+    fn is_plain_circle(&self) -> bool {
+        self.outline.is_none() && *self.shape == ColliderShape::Circle
+    }
+
+    /// The body's shape placed where it is at `time`, scaled and turned.
+    fn parts(&self, time: f32) -> Vec<Part<'_>> {
+        let (x, y) = position_at(self, time);
+        let (sin, cos) = self.heading.sin_cos();
+        let place = |local_x: f32, local_y: f32| {
+            (
+                x + self.radius * (local_x * cos - local_y * sin),
+                y + self.radius * (local_x * sin + local_y * cos),
+            )
+        };
+        match self.shape {
+            ColliderShape::Circle => vec![Part::Round {
+                x,
+                y,
+                radius: self.radius,
+                outline: self.outline,
+            }],
+            ColliderShape::Polygon(points) => vec![Part::Polygon(
+                points.iter().map(|&(px, py)| place(px, py)).collect(),
+            )],
+            ColliderShape::Compound(circles) => circles
+                .iter()
+                .map(|&(cx, cy, radius)| {
+                    let (x, y) = place(cx, cy);
+                    Part::Round {
+                        x,
+                        y,
+                        radius: self.radius * radius,
+                        outline: None,
+                    }
+                })
+                .collect(),
+        }
+    }
+}
+
+/// A piece of a body's shape, in world coordinates.
+enum Part<'a> {
+    Round {
+        x: f32,
+        y: f32,
+        radius: f32,
+        outline: Option<&'a Outline>,
+    },
+    Polygon(Vec<(f32, f32)>),
+}
+
+impl Part<'_> {
+    /// The same part, `(dx, dy)` further along.
+    fn moved(&self, (dx, dy): (f32, f32)) -> Self {
+        match self {
+            &Part::Round {
+                x,
+                y,
+                radius,
+                outline,
+            } => Part::Round {
+                x: x + dx,
+                y: y + dy,
+                radius,
+                outline,
+            },
+            Part::Polygon(points) => {
+                Part::Polygon(points.iter().map(|&(x, y)| (x + dx, y + dy)).collect())
+            }
+        }
+    }
+}
+
+struct CellBounds {
+    min_x: usize,
+    max_x: usize,
+    min_y: usize,
+    max_y: usize,
+}
+
+fn bodies(world: &World) -> Vec<Body<'_>> {
+    world
+        .colliders
+        .iter()
+        .map(|(&entity, collider)| {
+            let to = &world.transforms[&entity];
+            let sweep = world.sweeps.get(&entity);
+            Body {
+                entity,
+                from_x: sweep.map_or(to.pos_x, |sweep| sweep.from_x),
+                from_y: sweep.map_or(to.pos_y, |sweep| sweep.from_y),
+                to,
+                radius: collider.radius,
+                shape: &collider.shape,
+                outline: world.outlines.get(&entity),
+                heading: world
+                    .headings
+                    .get(&entity)
+                    .map_or(0.0, |heading| heading.angle),
+                swept: sweep.is_some(),
+            }
+        })
+        .collect()
+}
+
+/// Tests the bounding circles first. Unless both bodies are plain circles, the
+/// bounding circles touching only means the shapes might, so the rest of the tick
+/// is searched for when they really do.
+fn test_pair(first: &Body, second: &Body) -> Option<Collision> {
+    let size_dist = first.reach() + second.reach();
+    let overlapping = dist_transform(first.to, second.to) - size_dist <= 0.0;
+    let time = if first.swept || second.swept {
+        time_of_impact(first, second, size_dist)
+    } else {
+        None
+    };
+    let time = match (time, overlapping) {
+        (Some(time), _) => time,
+        (None, true) => 1.0,
+        (None, false) => return None,
+    };
+
+    let (impact_x, impact_y) = if first.is_plain_circle() && second.is_plain_circle() {
+        let (first_x, first_y) = position_at(first, time);
+        let (second_x, second_y) = position_at(second, time);
+        round_contact(
+            (first_x, first_y, first.radius, None),
+            (second_x, second_y, second.radius, None),
+        )
+        .0
+    } else {
+        shape_contact(first, second, time)?
+    };
+    Some(Collision {
+        first: first.entity,
+        second: second.entity,
+        impact_x,
+        impact_y,
+    })
+}
+
+/// Where the shapes of two bodies first touch between `start` and the end of the
+/// tick, if they do. Each pair of parts is tested along the whole way, so a thin
+/// or fast shape can't pass through another.
+fn shape_contact(first: &Body, second: &Body, start: f32) -> Option<(f32, f32)> {
+    let remaining = 1.0 - start;
+    let motion = (
+        ((second.to.pos_x - second.from_x) - (first.to.pos_x - first.from_x)) * remaining,
+        ((second.to.pos_y - second.from_y) - (first.to.pos_y - first.from_y)) * remaining,
+    );
+    let first_parts = first.parts(start);
+    let second_parts = second.parts(start);
+    let (fraction, a, b) = first_parts
+        .iter()
+        .enumerate()
+        .flat_map(|(a, first)| second_parts.iter().enumerate().map(move |b| (a, first, b)))
+        .filter_map(|(a, first, (b, second))| Some((part_impact(first, second, motion)?, a, b)))
+        .min_by(|x, y| x.0.partial_cmp(&y.0).unwrap_or(std::cmp::Ordering::Equal))?;
+
+    let time = start + fraction * remaining;
+    let first_parts = first.parts(time);
+    let second_parts = second.parts(time);
+    Some(touch_point(&first_parts[a], &second_parts[b]))
+}
+
+/// Fraction of `motion` the second part covers, relative to the first, before
+/// the two first touch.
+fn part_impact(first: &Part, second: &Part, motion: (f32, f32)) -> Option<f32> {
+    if part_contact(first, second).is_some() {
+        return Some(0.0);
+    }
+    let (move_x, move_y) = motion;
+    match (first, second) {
+        (
+            &Part::Round {
+                x,
+                y,
+                radius,
+                outline,
+            },
+            &Part::Round {
+                x: other_x,
+                y: other_y,
+                radius: other_radius,
+                outline: other_outline,
+            },
+        ) => {
+            let reach = radius * outline.map_or(1.0, Outline::max_factor)
+                + other_radius * other_outline.map_or(1.0, Outline::max_factor);
+            let from = circle_impact(other_x - x, other_y - y, move_x, move_y, reach)?;
+            if outline.is_none() && other_outline.is_none() {
+                Some(from)
+            } else {
+                outline_impact(first, second, motion, from, radius.min(other_radius))
+            }
+        }
+        (
+            &Part::Round {
+                x,
+                y,
+                radius,
+                outline,
+            },
+            Part::Polygon(points),
+        ) => {
+            let reach = radius * outline.map_or(1.0, Outline::max_factor);
+            let from = polygon_impact(points, x, y, -move_x, -move_y, reach)?;
+            match outline {
+                Some(_) => outline_impact(first, second, motion, from, radius),
+                None => Some(from),
+            }
+        }
+        (
+            Part::Polygon(points),
+            &Part::Round {
+                x,
+                y,
+                radius,
+                outline,
+            },
+        ) => {
+            let reach = radius * outline.map_or(1.0, Outline::max_factor);
+            let from = polygon_impact(points, x, y, move_x, move_y, reach)?;
+            match outline {
+                Some(_) => outline_impact(first, second, motion, from, radius),
+                None => Some(from),
+            }
+        }
+        // Polygons first meet where a corner of one runs into an edge of the other.
+        (Part::Polygon(first), Part::Polygon(second)) => {
+            let forward = second
+                .iter()
+                .map(|&(x, y)| polygon_impact(first, x, y, move_x, move_y, 0.0));
+            let backward = first
+                .iter()
+                .map(|&(x, y)| polygon_impact(second, x, y, -move_x, -move_y, 0.0));
+            forward.chain(backward).fold(None, earliest)
+        }
+    }
+}
+
+/// Steps through the move from `from`, where the bounding circle of a bumpy
+/// outline first touches, until the outline itself does.
+fn outline_impact(
+    first: &Part,
+    second: &Part,
+    (move_x, move_y): (f32, f32),
+    from: f32,
+    size: f32,
+) -> Option<f32> {
+    let travel = (move_x * move_x + move_y * move_y).sqrt() * (1.0 - from);
+    let steps = (travel / (OUTLINE_STEP * size))
+        .ceil()
+        .clamp(1.0, MAX_OUTLINE_STEPS) as u32;
+    (0..=steps)
+        .map(|step| from + (1.0 - from) * step as f32 / steps as f32)
+        .find(|&fraction| {
+            let second = second.moved((move_x * fraction, move_y * fraction));
+            part_contact(first, &second).is_some()
+        })
+}
+
+/// Fraction of `(move_x, move_y)` a circle of `radius` at `(x, y)` covers before
+/// it first touches the polygon's edges, if it does.
+fn polygon_impact(
+    points: &[(f32, f32)],
+    x: f32,
+    y: f32,
+    move_x: f32,
+    move_y: f32,
+    radius: f32,
+) -> Option<f32> {
+    edges(points)
+        .map(|(start, end)| edge_impact(start, end, (x, y), (move_x, move_y), radius))
+        .fold(None, earliest)
+}
+
+/// The same for a single edge: the first time the circle touches either of its
+/// ends, or crosses into reach of it from the side.
+fn edge_impact(
+    start: (f32, f32),
+    end: (f32, f32),
+    (x, y): (f32, f32),
+    (move_x, move_y): (f32, f32),
+    radius: f32,
+) -> Option<f32> {
+    let ends = earliest(
+        circle_impact(x - start.0, y - start.1, move_x, move_y, radius),
+        circle_impact(x - end.0, y - end.1, move_x, move_y, radius),
+    );
+    let edge_x = end.0 - start.0;
+    let edge_y = end.1 - start.1;
+    let length = (edge_x * edge_x + edge_y * edge_y).sqrt();
+    if length == 0.0 {
+        return ends;
+    }
+    let normal_x = -edge_y / length;
+    let normal_y = edge_x / length;
+    let height = (x - start.0) * normal_x + (y - start.1) * normal_y;
+    let closing = move_x * normal_x + move_y * normal_y;
+    // Only a circle that is out of reach of the edge's line and moving towards it
+    // can come in from the side; anything else meets an end first, if at all.
+    if height.abs() <= radius || height * closing >= 0.0 {
+        return ends;
+    }
+    let time = (radius.copysign(height) - height) / closing;
+    let along = ((x + move_x * time - start.0) * edge_x + (y + move_y * time - start.1) * edge_y)
+        / (length * length);
+    if (0.0..=1.0).contains(&time) && (0.0..=1.0).contains(&along) {
+        earliest(ends, Some(time))
+    } else {
+        ends
+    }
+}
+
+fn earliest(first: Option<f32>, second: Option<f32>) -> Option<f32> {
+    match (first, second) {
+        (Some(first), Some(second)) => Some(first.min(second)),
+        (first, None) => first,
+        (None, second) => second,
+    }
+}
+
+/// Where two parts that were found to meet touch. Falls back to the nearest
+/// point when rounding leaves them a hair apart.
+fn touch_point(first: &Part, second: &Part) -> (f32, f32) {
+    if let Some(point) = part_contact(first, second) {
+        return point;
+    }
+    match (first, second) {
+        (
+            &Part::Round {
+                x,
+                y,
+                radius,
+                outline,
+            },
+            &Part::Round {
+                x: other_x,
+                y: other_y,
+                radius: other_radius,
+                outline: other_outline,
+            },
+        ) => {
+            round_contact(
+                (x, y, radius, outline),
+                (other_x, other_y, other_radius, other_outline),
+            )
+            .0
+        }
+        (&Part::Round { x, y, .. }, Part::Polygon(points))
+        | (Part::Polygon(points), &Part::Round { x, y, .. }) => {
+            closest_on_polygon(points, x, y).unwrap_or((x, y))
+        }
+        (Part::Polygon(first), Part::Polygon(second)) => second
+            .iter()
+            .filter_map(|&(x, y)| closest_on_polygon(first, x, y))
+            .min_by(|a, b| {
+                let a_dist = (a.0 - second[0].0).hypot(a.1 - second[0].1);
+                let b_dist = (b.0 - second[0].0).hypot(b.1 - second[0].1);
+                a_dist
+                    .partial_cmp(&b_dist)
+                    .unwrap_or(std::cmp::Ordering::Equal)
+            })
+            .unwrap_or(second[0]),
+    }
+}
+
+fn part_contact(first: &Part, second: &Part) -> Option<(f32, f32)> {
+    match (first, second) {
+        (
+            &Part::Round {
+                x,
+                y,
+                radius,
+                outline,
+            },
+            &Part::Round {
+                x: other_x,
+                y: other_y,
+                radius: other_radius,
+                outline: other_outline,
+            },
+        ) => {
+            let (point, touching) = round_contact(
+                (x, y, radius, outline),
+                (other_x, other_y, other_radius, other_outline),
+            );
+            if touching {
+                Some(point)
+            } else {
+                None
+            }
+        }
+        (
+            &Part::Round {
+                x,
+                y,
+                radius,
+                outline,
+            },
+            Part::Polygon(points),
+        )
+        | (
+            Part::Polygon(points),
+            &Part::Round {
+                x,
+                y,
+                radius,
+                outline,
+            },
+        ) => round_polygon_contact(x, y, radius, outline, points),
+        (Part::Polygon(first), Part::Polygon(second)) => polygon_contact(first, second),
+    }
+}
+
+/// The point between two round parts where their edges meet on the line between
+/// their centres, and whether they reach it.
+fn round_contact(
+    (first_x, first_y, first_radius, first_outline): (f32, f32, f32, Option<&Outline>),
+    (second_x, second_y, second_radius, second_outline): (f32, f32, f32, Option<&Outline>),
+) -> ((f32, f32), bool) {
+    let dx = second_x - first_x;
+    let dy = second_y - first_y;
+    let first_extent =
+        first_radius * first_outline.map_or(1.0, |outline| outline.factor_towards(dx, dy));
+    let second_extent =
+        second_radius * second_outline.map_or(1.0, |outline| outline.factor_towards(-dx, -dy));
+    let extents = first_extent + second_extent;
+    let contact = if extents > 0.0 {
+        first_extent / extents
+    } else {
+        0.5
+    };
+    let point = (first_x + dx * contact, first_y + dy * contact);
+    (point, dx * dx + dy * dy <= extents * extents)
+}
+
+fn round_polygon_contact(
+    x: f32,
+    y: f32,
+    radius: f32,
+    outline: Option<&Outline>,
+    points: &[(f32, f32)],
+) -> Option<(f32, f32)> {
+    if polygon_contains(points, x, y) {
+        return Some((x, y));
+    }
+    let (closest_x, closest_y) = closest_on_polygon(points, x, y)?;
+    let dx = closest_x - x;
+    let dy = closest_y - y;
+    let extent = radius * outline.map_or(1.0, |outline| outline.factor_towards(dx, dy));
+    if dx * dx + dy * dy <= extent * extent {
+        Some((closest_x, closest_y))
+    } else {
+        None
+    }
+}
+
+fn polygon_contact(first: &[(f32, f32)], second: &[(f32, f32)]) -> Option<(f32, f32)> {
+    for (a_start, a_end) in edges(first) {
+        for (b_start, b_end) in edges(second) {
+            if let Some(point) = segment_intersection(a_start, a_end, b_start, b_end) {
+                return Some(point);
+            }
+        }
+    }
+    // No edges cross, so either one is inside the other or they are apart.
+    match (first.first(), second.first()) {
+        (Some(&(x, y)), _) if polygon_contains(second, x, y) => Some((x, y)),
+        (_, Some(&(x, y))) if polygon_contains(first, x, y) => Some((x, y)),
+        _ => None,
+    }
+}
+
+fn edges(points: &[(f32, f32)]) -> impl Iterator<Item = ((f32, f32), (f32, f32))> + '_ {
+    points
+        .iter()
+        .enumerate()
+        .map(move |(i, &start)| (start, points[(i + 1) % points.len()]))
+}
+
+/// Even-odd test, so it works for concave polygons too.
+fn polygon_contains(points: &[(f32, f32)], x: f32, y: f32) -> bool {
+    let mut inside = false;
+    for ((start_x, start_y), (end_x, end_y)) in edges(points) {
+        if (start_y > y) != (end_y > y)
+            && x < start_x + (y - start_y) / (end_y - start_y) * (end_x - start_x)
+        {
+            inside = !inside;
+        }
+    }
+    inside
+}
+
+/// The point on the polygon's edges nearest to `(x, y)`.
+fn closest_on_polygon(points: &[(f32, f32)], x: f32, y: f32) -> Option<(f32, f32)> {
+    edges(points)
+        .map(|(start, end)| closest_on_segment(start, end, x, y))
+        .min_by(|a, b| {
+            let a_dist = (a.0 - x) * (a.0 - x) + (a.1 - y) * (a.1 - y);
+            let b_dist = (b.0 - x) * (b.0 - x) + (b.1 - y) * (b.1 - y);
+            a_dist
+                .partial_cmp(&b_dist)
+                .unwrap_or(std::cmp::Ordering::Equal)
+        })
+}
+
+fn closest_on_segment(start: (f32, f32), end: (f32, f32), x: f32, y: f32) -> (f32, f32) {
+    let edge_x = end.0 - start.0;
+    let edge_y = end.1 - start.1;
+    let length = edge_x * edge_x + edge_y * edge_y;
+    if length == 0.0 {
+        return start;
+    }
+    let t = (((x - start.0) * edge_x + (y - start.1) * edge_y) / length).clamp(0.0, 1.0);
+    (start.0 + edge_x * t, start.1 + edge_y * t)
+}
+
+fn segment_intersection(
+    a_start: (f32, f32),
+    a_end: (f32, f32),
+    b_start: (f32, f32),
+    b_end: (f32, f32),
+) -> Option<(f32, f32)> {
+    let a_x = a_end.0 - a_start.0;
+    let a_y = a_end.1 - a_start.1;
+    let b_x = b_end.0 - b_start.0;
+    let b_y = b_end.1 - b_start.1;
+    let denominator = a_x * b_y - a_y * b_x;
+    if denominator == 0.0 {
+        return None;
+    }
+    let start_x = b_start.0 - a_start.0;
+    let start_y = b_start.1 - a_start.1;
+    let t = (start_x * b_y - start_y * b_x) / denominator;
+    let u = (start_x * a_y - start_y * a_x) / denominator;
+    if (0.0..=1.0).contains(&t) && (0.0..=1.0).contains(&u) {
+        Some((a_start.0 + a_x * t, a_start.1 + a_y * t))
+    } else {
+        None
+    }
+}
+
+/// Fraction of the tick at which two moving circles first touch, if they do.
+fn time_of_impact(first: &Body, second: &Body, size_dist: f32) -> Option<f32> {
+    let start_x = first.from_x - second.from_x;
+    let start_y = first.from_y - second.from_y;
+    let move_x = (first.to.pos_x - second.to.pos_x) - start_x;
+    let move_y = (first.to.pos_y - second.to.pos_y) - start_y;
+    circle_impact(start_x, start_y, move_x, move_y, size_dist)
+}
+
+/// Fraction of `(move_x, move_y)` an offset starting at `(start_x, start_y)`
+/// covers before it first comes within `reach`, if it does.
+fn circle_impact(start_x: f32, start_y: f32, move_x: f32, move_y: f32, reach: f32) -> Option<f32> {
+    let c = start_x * start_x + start_y * start_y - reach * reach;
+    if c <= 0.0 {
+        return Some(0.0);
+    }
+    let a = move_x * move_x + move_y * move_y;
+    if a == 0.0 {
+        return None;
+    }
+    let b = 2.0 * (start_x * move_x + start_y * move_y);
+    let discriminant = b * b - 4.0 * a * c;
+    if discriminant < 0.0 {
+        return None;
+    }
+    let time = (-b - discriminant.sqrt()) / (2.0 * a);
+    if (0.0..=1.0).contains(&time) {
+        Some(time)
+    } else {
+        None
+    }
+}
+
+fn position_at(body: &Body, time: f32) -> (f32, f32) {
+    (
+        body.from_x + (body.to.pos_x - body.from_x) * time,
+        body.from_y + (body.to.pos_y - body.from_y) * time,
+    )
+}
+
+// Things that wrapped just past an edge land in the border cells.
+fn cell_index(coord: f32, cells_per_side: usize) -> usize {
+    ((coord * cells_per_side as f32).floor().max(0.0) as usize).min(cells_per_side - 1)
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::{Rng, SeedableRng};
+
+    use super::*;
+    use crate::ecs::{Collider, Heading, Sweep};
+    use crate::shape::ShapeSettings;
+    use crate::simulation::GameRng;
+
+    /// Concave, like the ship: the notch at the back is open.
+    const ARROW: [(f32, f32); 4] = [(0.0, -1.0), (0.8, 1.0), (0.0, 0.3), (-0.8, 1.0)];
+
+    struct Crowd {
+        count: usize,
+        /// How far past each edge positions may go, like things about to wrap.
+        overhang: f32,
+        /// Longest distance a swept body covers in its tick.
+        max_sweep: f32,
+        /// Whether to mix in polygons and bumpy outlines.
+        shapes: bool,
+    }
+
+    fn random_world(seed: u64, crowd: &Crowd) -> World {
+        let mut rng = GameRng::seed_from_u64(seed);
+        let settings = ShapeSettings {
+            roughness: 0.3,
+            crater_count: 2,
+            crater_depth: 0.2,
+        };
+        let mut world = World::new();
+        for i in 0..crowd.count {
+            let pos_x = rng.gen_range(-crowd.overhang, 1.0 + crowd.overhang);
+            let pos_y = rng.gen_range(-crowd.overhang, 1.0 + crowd.overhang);
+            let entity = world.spawn(Transform {
+                pos_x,
+                pos_y,
+                ..Default::default()
+            });
+            let radius = rng.gen_range(0.005, 0.04);
+            let collider = if crowd.shapes && i % 3 == 0 {
+                world.headings.insert(
+                    entity,
+                    Heading {
+                        angle: rng.gen_range(0.0, 2.0 * std::f32::consts::PI),
+                    },
+                );
+                Collider {
+                    radius,
+                    shape: ColliderShape::Polygon(ARROW.to_vec()),
+                }
+            } else {
+                if crowd.shapes && i % 3 == 1 {
+                    world
+                        .outlines
+                        .insert(entity, Outline::generate(rng.gen(), &settings));
+                }
+                Collider::circle(radius)
+            };
+            world.colliders.insert(entity, collider);
+            if crowd.max_sweep > 0.0 && i % 4 == 0 {
+                let sweep = Sweep {
+                    from_x: pos_x - rng.gen_range(-crowd.max_sweep, crowd.max_sweep),
+                    from_y: pos_y - rng.gen_range(-crowd.max_sweep, crowd.max_sweep),
+                };
+                world.sweeps.insert(entity, sweep);
+            }
+        }
+        world
+    }
+
+    /// Checks a few seeds, and that at least one of the pairs found shows the case
+    /// the caller is about, so the comparison isn't vacuous.
+    fn assert_grid_matches_brute_force(crowd: &Crowd, covers: impl Fn(&World, &Collision) -> bool) {
+        let mut covered = false;
+        for seed in 0..8 {
+            let world = random_world(seed, crowd);
+            let collisions = find_collisions(&world);
+            assert_eq!(
+                collisions,
+                find_collisions_brute_force(&world),
+                "seed {}",
+                seed
+            );
+            covered |= collisions.iter().any(|collision| covers(&world, collision));
+        }
+        assert!(covered);
+    }
+
+    fn either(collision: &Collision, test: impl Fn(Entity) -> bool) -> bool {
+        test(collision.first) || test(collision.second)
+    }
+
+    fn outside_the_screen(transform: &Transform) -> bool {
+        !(0.0..=1.0).contains(&transform.pos_x) || !(0.0..=1.0).contains(&transform.pos_y)
+    }
+
+    #[test]
+    fn grid_matches_brute_force_across_the_wrap_edge() {
+        let crowd = Crowd {
+            count: 400,
+            overhang: 0.05,
+            max_sweep: 0.0,
+            shapes: false,
+        };
+        assert_grid_matches_brute_force(&crowd, |world, collision| {
+            either(collision, |entity| {
+                outside_the_screen(&world.transforms[&entity])
+            })
+        });
+    }
+
+    #[test]
+    fn grid_matches_brute_force_for_fast_swept_bodies() {
+        // With 400 colliders the cells are 0.05 wide, so a sweep this long crosses
+        // several of them.
+        let crowd = Crowd {
+            count: 400,
+            overhang: 0.05,
+            max_sweep: 0.3,
+            shapes: false,
+        };
+        assert_grid_matches_brute_force(&crowd, |world, collision| {
+            either(collision, |entity| {
+                let to = &world.transforms[&entity];
+                world.sweeps.get(&entity).into_iter().any(|sweep| {
+                    (to.pos_x - sweep.from_x)
+                        .abs()
+                        .max((to.pos_y - sweep.from_y).abs())
+                        > 0.15
+                })
+            })
+        });
+    }
+
+    #[test]
+    fn grid_matches_brute_force_for_polygons_and_outlines() {
+        let crowd = Crowd {
+            count: 400,
+            overhang: 0.05,
+            max_sweep: 0.1,
+            shapes: true,
+        };
+        assert_grid_matches_brute_force(&crowd, |world, collision| {
+            either(collision, |entity| {
+                world.colliders[&entity].shape != ColliderShape::Circle
+            })
+        });
+        assert_grid_matches_brute_force(&crowd, |world, collision| {
+            either(collision, |entity| world.outlines.contains_key(&entity))
+        });
+    }
+
+    /// An arrow of radius 0.1 in the middle of the screen, and a small circle.
+    fn arrow_and_circle(heading: f32, x: f32, y: f32, radius: f32) -> World {
+        let mut world = World::new();
+        let arrow = world.spawn(Transform {
+            pos_x: 0.5,
+            pos_y: 0.5,
+            ..Default::default()
+        });
+        let collider = Collider {
+            radius: 0.1,
+            shape: ColliderShape::Polygon(ARROW.to_vec()),
+        };
+        world.colliders.insert(arrow, collider);
+        world.headings.insert(arrow, Heading { angle: heading });
+        let circle = world.spawn(Transform {
+            pos_x: x,
+            pos_y: y,
+            ..Default::default()
+        });
+        world.colliders.insert(circle, Collider::circle(radius));
+        world
+    }
+
+    fn touching(world: &World) -> bool {
+        let collisions = find_collisions(world);
+        assert_eq!(collisions, find_collisions_brute_force(world));
+        !collisions.is_empty()
+    }
+
+    #[test]
+    fn circle_inside_a_polygon_touches_it() {
+        assert!(touching(&arrow_and_circle(0.0, 0.5, 0.45, 0.005)));
+    }
+
+    #[test]
+    fn circle_reaching_over_an_edge_touches_it() {
+        // The right edge runs from the tip at (0, -1) to (0.8, 1), so it crosses
+        // the middle at x = 0.54. A point `d` to the right of that is 0.93 * d
+        // away from the edge.
+        assert!(touching(&arrow_and_circle(0.0, 0.548, 0.5, 0.01)));
+        assert!(!touching(&arrow_and_circle(0.0, 0.555, 0.5, 0.01)));
+    }
+
+    #[test]
+    fn circle_in_the_notch_does_not_touch() {
+        // Inside the bounding circle, but in the notch at the back of the arrow,
+        // over 0.03 away from either side of it.
+        assert!(!touching(&arrow_and_circle(0.0, 0.5, 0.575, 0.01)));
+        // The same spot is solid once the arrow points the other way.
+        assert!(touching(&arrow_and_circle(
+            std::f32::consts::PI,
+            0.5,
+            0.575,
+            0.01
+        )));
+    }
+
+    #[test]
+    fn circle_beside_the_tip_does_not_touch() {
+        assert!(!touching(&arrow_and_circle(0.0, 0.53, 0.41, 0.01)));
+    }
+
+    /// A big sub-circle left of the middle and a small one right of it, with a
+    /// small circle `gap` below the top of one of them.
+    fn dumbbell_and_circle(heading: f32, big: bool, gap: f32) -> World {
+        let mut world = World::new();
+        let dumbbell = world.spawn(Transform {
+            pos_x: 0.5,
+            pos_y: 0.5,
+            ..Default::default()
+        });
+        let collider = Collider {
+            radius: 0.1,
+            shape: ColliderShape::Compound(vec![(-0.6, 0.0, 0.4), (0.5, 0.0, 0.2)]),
+        };
+        world.colliders.insert(dumbbell, collider);
+        world.headings.insert(dumbbell, Heading { angle: heading });
+        let (x, radius) = if big { (0.44, 0.04) } else { (0.55, 0.02) };
+        let circle = world.spawn(Transform {
+            pos_x: x,
+            pos_y: 0.5 - radius - gap,
+            ..Default::default()
+        });
+        world.colliders.insert(circle, Collider::circle(0.01));
+        world
+    }
+
+    #[test]
+    fn compound_colliders_touch_on_each_sub_circle() {
+        assert!(touching(&dumbbell_and_circle(0.0, true, 0.005)));
+        assert!(touching(&dumbbell_and_circle(0.0, false, 0.005)));
+        // Out of reach of the small one, though inside the bounding circle.
+        assert!(!touching(&dumbbell_and_circle(0.0, false, 0.015)));
+        // Turned round, the small one is where the big one was.
+        assert!(!touching(&dumbbell_and_circle(
+            std::f32::consts::PI,
+            true,
+            0.005
+        )));
+    }
+
+    /// A bar 0.01 wide and 0.2 tall sweeping from x = 0.2 to 0.8 in one tick,
+    /// past a small circle at x = 0.535.
+    fn bar_sweeping_past(y: f32) -> World {
+        let mut world = World::new();
+        let bar = world.spawn(Transform {
+            pos_x: 0.8,
+            pos_y: 0.5,
+            ..Default::default()
+        });
+        let collider = Collider {
+            radius: 0.1,
+            shape: ColliderShape::Polygon(vec![
+                (-0.05, -1.0),
+                (0.05, -1.0),
+                (0.05, 1.0),
+                (-0.05, 1.0),
+            ]),
+        };
+        world.colliders.insert(bar, collider);
+        world.sweeps.insert(
+            bar,
+            Sweep {
+                from_x: 0.2,
+                from_y: 0.5,
+            },
+        );
+        let circle = world.spawn(Transform {
+            pos_x: 0.535,
+            pos_y: y,
+            ..Default::default()
+        });
+        world.colliders.insert(circle, Collider::circle(0.01));
+        world
+    }
+
+    #[test]
+    fn fast_thin_polygon_cannot_pass_through_a_circle() {
+        // Checking eight points after the bounding circles meet would see the bar
+        // at x = 0.519 and 0.566, either side of the circle.
+        let world = bar_sweeping_past(0.5);
+        assert!(touching(&world));
+        let collision = &find_collisions(&world)[0];
+        assert!((collision.impact_x - 0.525).abs() < 1e-4);
+        assert!((collision.impact_y - 0.5).abs() < 1e-4);
+
+        // Its corner clips the circle on the way past.
+        assert!(touching(&bar_sweeping_past(0.605)));
+        assert!(!touching(&bar_sweeping_past(0.62)));
+    }
+
+    #[test]
+    fn fast_thin_polygon_hits_another_polygon() {
+        let mut world = bar_sweeping_past(0.5);
+        let circle = *world.colliders.keys().last().unwrap();
+        world.colliders.insert(
+            circle,
+            Collider {
+                radius: 0.01,
+                shape: ColliderShape::Polygon(ARROW.to_vec()),
+            },
+        );
+        assert!(touching(&world));
+    }
+}
@@ -0,0 +1,320 @@
+//! Entities and the components they can carry. An entity is only an id; what it is
+//! and how it behaves comes from which components are attached to it, and the
+//! systems in `simulation` (and the renderer) pick entities by component.
+
+use std::collections::BTreeMap;
+
+use crate::meteor::{HomingRule, MeteorKind, TrailRule};
+use crate::shape::Outline;
+
+pub type Entity = usize;
+
+/// RGBA color, kept as plain floats so the simulation doesn't depend on ggez.
+pub type Color = [f32; 4];
+
+#[derive(Clone, Debug, Default)]
+pub struct Transform {
+    pub pos_x: f32,
+    pub pos_y: f32,
+    pub vel_x: f32,
+    pub vel_y: f32,
+    pub acc_x: f32,
+    pub acc_y: f32,
+}
+
+/// Which way the entity faces, in radians clockwise from straight up.
+#[derive(Clone, Debug, Default)]
+pub struct Heading {
+    pub angle: f32,
+}
+
+impl Heading {
+    /// Unit vector pointing where the entity faces, in screen coordinates.
+    pub fn direction(&self) -> (f32, f32) {
+        (self.angle.sin(), -self.angle.cos())
+    }
+}
+
+/// Makes an entity take part in collisions. The radius is the entity's size: its
+/// shape is scaled by it, and mass and damage go by it whatever the shape.
+#[derive(Clone, Debug)]
+pub struct Collider {
+    pub radius: f32,
+    pub shape: ColliderShape,
+}
+
+impl Collider {
+    pub fn circle(radius: f32) -> Collider {
+        Collider {
+            radius,
+            shape: ColliderShape::Circle,
+        }
+    }
+}
+
+/// Coordinates are in units of the collider's radius, in screen orientation, and
+/// turn with the entity's heading if it has one.
+#[derive(Clone, Debug, PartialEq)]
+pub enum ColliderShape {
+    Circle,
+    /// A simple polygon, which may be concave.
+    Polygon(Vec<(f32, f32)>),
+    /// Several circles, each given as its centre and radius.
+    Compound(Vec<(f32, f32, f32)>),
+}
+
+/// Where the entity started the tick. Colliders with a sweep are tested along the
+/// whole path they covered, so fast movers can't skip past thin targets.
+#[derive(Clone, Debug, Default)]
+pub struct Sweep {
+    pub from_x: f32,
+    pub from_y: f32,
+}
+
+/// Pulls other colliders towards the entity when the game mode has gravity.
+#[derive(Clone, Debug)]
+pub struct GravityWell {
+    pub mass: f32,
+}
+
+/// Which side of the collision rules an entity is on.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Faction {
+    Ship,
+    Earth,
+    Meteor,
+    Projectile,
+}
+
+/// What kind of meteor the entity is, and how many more hits it takes to break.
+#[derive(Clone, Debug)]
+pub struct Meteor {
+    pub kind: MeteorKind,
+    pub hits_left: u32,
+}
+
+/// Steers the entity towards the ship.
+#[derive(Clone, Debug)]
+pub struct Homing {
+    pub rule: HomingRule,
+}
+
+/// Makes the entity leave slowing patches behind.
+#[derive(Clone, Debug)]
+pub struct TrailEmitter {
+    pub rule: TrailRule,
+    pub ticks_to_next: f32,
+}
+
+/// Slows the ship down while it is within `radius`.
+#[derive(Clone, Debug)]
+pub struct SlowField {
+    pub radius: f32,
+    /// Share of its speed the ship loses every tick it spends inside.
+    pub slowdown: f32,
+}
+
+/// Art the renderer wraps around a circle.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Texture {
+    Earth,
+    Clouds,
+    Meteor,
+    IronMeteor,
+    Comet,
+    ExplosiveMeteor,
+    Shard,
+    Ship,
+}
+
+impl Texture {
+    pub fn is_meteor(self) -> bool {
+        match self {
+            Texture::Meteor
+            | Texture::IronMeteor
+            | Texture::Comet
+            | Texture::ExplosiveMeteor
+            | Texture::Shard => true,
+            Texture::Earth | Texture::Clouds | Texture::Ship => false,
+        }
+    }
+}
+
+/// Scrolling texture coordinates, used to make the planet and clouds spin.
+#[derive(Clone, Debug, Default)]
+pub struct RenderCoords {
+    pub pos_x: f32,
+    pub pos_y: f32,
+    pub vel_x: f32,
+    pub vel_y: f32,
+}
+
+#[derive(Clone, Debug)]
+pub struct Circle {
+    pub radius: f32,
+    pub color: Color,
+    pub texture: Option<Texture>,
+    pub render_coords: RenderCoords,
+}
+
+#[derive(Clone, Debug)]
+pub struct TextLabel {
+    pub text: String,
+    pub font_size: f32,
+    pub color: Color,
+}
+
+/// Removes the entity once the given number of ticks has passed.
+#[derive(Clone, Debug)]
+pub struct Lifetime {
+    pub ticks_left: f32,
+}
+
+/// Makes the entity burn up: it shrinks every tick and is removed once small enough.
+#[derive(Clone, Debug, Default)]
+pub struct Burning;
+
+#[derive(Default)]
+pub struct World {
+    next_entity: Entity,
+    pub transforms: BTreeMap<Entity, Transform>,
+    pub headings: BTreeMap<Entity, Heading>,
+    pub colliders: BTreeMap<Entity, Collider>,
+    /// Bumpy edges for circle colliders. Drawn and collided with alike.
+    pub outlines: BTreeMap<Entity, Outline>,
+    pub sweeps: BTreeMap<Entity, Sweep>,
+    pub factions: BTreeMap<Entity, Faction>,
+    pub circles: BTreeMap<Entity, Circle>,
+    pub labels: BTreeMap<Entity, TextLabel>,
+    pub lifetimes: BTreeMap<Entity, Lifetime>,
+    pub gravity_wells: BTreeMap<Entity, GravityWell>,
+    pub burning: BTreeMap<Entity, Burning>,
+    pub meteors: BTreeMap<Entity, Meteor>,
+    pub homing: BTreeMap<Entity, Homing>,
+    pub trail_emitters: BTreeMap<Entity, TrailEmitter>,
+    pub slow_fields: BTreeMap<Entity, SlowField>,
+}
+
+impl World {
+    pub fn new() -> World {
+        Default::default()
+    }
+
+    /// Creates an entity at the given position. Every entity has a transform; all
+    /// other components are added by inserting into the matching map.
+    pub fn spawn(&mut self, transform: Transform) -> Entity {
+        self.next_entity += 1;
+        let entity = self.next_entity;
+        self.transforms.insert(entity, transform);
+        entity
+    }
+
+    pub fn despawn(&mut self, entity: Entity) {
+        self.transforms.remove(&entity);
+        self.headings.remove(&entity);
+        self.colliders.remove(&entity);
+        self.outlines.remove(&entity);
+        self.sweeps.remove(&entity);
+        self.factions.remove(&entity);
+        self.circles.remove(&entity);
+        self.labels.remove(&entity);
+        self.lifetimes.remove(&entity);
+        self.gravity_wells.remove(&entity);
+        self.burning.remove(&entity);
+        self.meteors.remove(&entity);
+        self.homing.remove(&entity);
+        self.trail_emitters.remove(&entity);
+        self.slow_fields.remove(&entity);
+    }
+
+    pub fn contains(&self, entity: Entity) -> bool {
+        self.transforms.contains_key(&entity)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.transforms.is_empty()
+    }
+
+    pub fn len(&self) -> usize {
+        self.transforms.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(pos_x: f32, pos_y: f32) -> Transform {
+        Transform {
+            pos_x,
+            pos_y,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn spawned_entities_get_ids_that_are_never_reused() {
+        let mut world = World::new();
+        let first = world.spawn(at(0.1, 0.1));
+        let second = world.spawn(at(0.2, 0.2));
+        assert_ne!(first, second);
+
+        world.despawn(first);
+        let third = world.spawn(at(0.3, 0.3));
+        assert!(third != first && third != second);
+        assert!(!world.contains(first));
+        assert!(world.contains(second) && world.contains(third));
+        assert_eq!(world.len(), 2);
+    }
+
+    #[test]
+    fn despawn_removes_every_component() {
+        let mut world = World::new();
+        let entity = world.spawn(at(0.5, 0.5));
+        let other = world.spawn(at(0.2, 0.2));
+        world.headings.insert(entity, Heading { angle: 1.0 });
+        world.colliders.insert(entity, Collider::circle(0.1));
+        world.sweeps.insert(entity, Sweep::default());
+        world.factions.insert(entity, Faction::Meteor);
+        world.lifetimes.insert(entity, Lifetime { ticks_left: 5.0 });
+        world.burning.insert(entity, Burning);
+        world
+            .gravity_wells
+            .insert(entity, GravityWell { mass: 1.0 });
+        world.colliders.insert(other, Collider::circle(0.1));
+
+        world.despawn(entity);
+        assert!(!world.contains(entity));
+        assert!(!world.headings.contains_key(&entity));
+        assert!(!world.colliders.contains_key(&entity));
+        assert!(!world.sweeps.contains_key(&entity));
+        assert!(!world.factions.contains_key(&entity));
+        assert!(!world.lifetimes.contains_key(&entity));
+        assert!(!world.burning.contains_key(&entity));
+        assert!(!world.gravity_wells.contains_key(&entity));
+        // Nobody else loses anything.
+        assert!(world.colliders.contains_key(&other));
+
+        world.despawn(other);
+        assert!(world.is_empty());
+    }
+
+    #[test]
+    fn heading_faces_up_at_zero_and_turns_clockwise() {
+        let (x, y) = Heading { angle: 0.0 }.direction();
+        assert!(x.abs() < 1e-6 && (y + 1.0).abs() < 1e-6);
+        let (x, y) = Heading {
+            angle: std::f32::consts::FRAC_PI_2,
+        }
+        .direction();
+        assert!((x - 1.0).abs() < 1e-6 && y.abs() < 1e-6);
+    }
+
+    #[test]
+    fn only_meteor_textures_are_meteors() {
+        assert!(Texture::Meteor.is_meteor());
+        assert!(Texture::Shard.is_meteor());
+        assert!(!Texture::Earth.is_meteor());
+        assert!(!Texture::Ship.is_meteor());
+    }
+}
@@ -0,0 +1,616 @@
+//! The level editor: the level being made and the edits the designer can make to
+//! it. Input is turned into these calls, and the result drawn, by the game.
+
+use std::path::{Path, PathBuf};
+
+use ld46_keep_it_alive::level::{Edge, Goal, Level, LevelError, PlacedMeteor, Position, Wave};
+use ld46_keep_it_alive::meteor::{MeteorKind, METEOR_TYPES};
+use ld46_keep_it_alive::simulation::{EARTH_POSITION, EARTH_RADIUS, SHIP_RADIUS, SHIP_START};
+
+/// Top of the strip along the bottom of the screen that lays the waves out over
+/// time, in world coordinates.
+pub const TIMELINE_TOP: f32 = 0.9;
+/// The timeline always shows at least this many seconds, and a little past the
+/// end of the last wave.
+const TIMELINE_MIN_SECONDS: f32 = 60.0;
+const TIMELINE_MARGIN_SECONDS: f32 = 10.0;
+/// Narrowest a wave is drawn on the timeline, so even a burst can be clicked.
+const TIMELINE_MIN_WIDTH: f32 = 0.01;
+
+/// Meteor velocity per unit of distance dragged. Dragging a quarter of the screen
+/// gives about the speed of a slow meteor.
+pub const VELOCITY_PER_DRAG: f32 = 0.002;
+const NEW_METEOR_RADIUS: f32 = 0.012;
+const MIN_METEOR_RADIUS: f32 = 0.004;
+const MAX_METEOR_RADIUS: f32 = 0.05;
+const RADIUS_STEP: f32 = 1.15;
+/// Small things can be picked from this far away.
+const PICK_DISTANCE: f32 = 0.02;
+const NEW_WAVE_COUNT: u32 = 5;
+const WAVE_START_STEP: f32 = 1.0;
+const WAVE_INTERVAL_STEP: f32 = 0.25;
+const SURVIVE_SECONDS: f32 = 60.0;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Selection {
+    Earth,
+    Ship,
+    Meteor(usize),
+    Wave(usize),
+}
+
+/// What moving the mouse does while a button is held.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Drag {
+    /// Moves the selection, keeping the spot that was grabbed under the mouse.
+    Move { offset_x: f32, offset_y: f32 },
+    /// Aims the selected meteor, from its centre towards the mouse.
+    Velocity,
+    /// Slides the selected wave along the timeline.
+    WaveStart { offset: f32 },
+}
+
+pub struct Editor {
+    pub level: Level,
+    pub path: PathBuf,
+    pub selection: Option<Selection>,
+    drag: Option<Drag>,
+    /// Point on the timeline new waves start at, in seconds.
+    pub cursor: f32,
+    /// Kind of the next meteor placed.
+    pub meteor_kind: MeteorKind,
+    /// Set by every edit, so the preview knows to catch up.
+    pub changed: bool,
+    /// The last thing worth telling the designer, such as where the level went.
+    pub status: String,
+}
+
+impl Editor {
+    /// Opens the level at `path` for editing, or a new one if there is no file yet.
+    pub fn open(path: &Path) -> Result<Editor, LevelError> {
+        let level = if path.exists() {
+            Level::load(path)?
+        } else {
+            let name = path.file_stem().map_or("New level".to_string(), |stem| {
+                stem.to_string_lossy().to_string()
+            });
+            Level::new(&name)
+        };
+        Ok(Editor {
+            level,
+            path: path.to_path_buf(),
+            selection: None,
+            drag: None,
+            cursor: 0.0,
+            meteor_kind: MeteorKind::Rock,
+            changed: true,
+            status: format!("Editing {}", path.display()),
+        })
+    }
+
+    pub fn ship(&self) -> Position {
+        self.level.ship.unwrap_or(SHIP_START)
+    }
+
+    pub fn earth(&self) -> Position {
+        self.level.earth.unwrap_or(EARTH_POSITION)
+    }
+
+    /// Seconds the whole width of the timeline stands for.
+    pub fn timeline_seconds(&self) -> f32 {
+        let last_end = self
+            .level
+            .waves
+            .iter()
+            .map(|wave| wave_span(wave).1)
+            .fold(0.0, f32::max);
+        TIMELINE_MIN_SECONDS.max(last_end + TIMELINE_MARGIN_SECONDS)
+    }
+
+    /// Where `seconds` falls on the timeline, from 0 to 1 across the screen.
+    pub fn timeline_x(&self, seconds: f32) -> f32 {
+        seconds / self.timeline_seconds()
+    }
+
+    /// Left and right end of a wave on the timeline.
+    pub fn wave_bounds(&self, wave: &Wave) -> (f32, f32) {
+        let (start, end) = wave_span(wave);
+        let left = self.timeline_x(start);
+        (left, self.timeline_x(end).max(left + TIMELINE_MIN_WIDTH))
+    }
+
+    /// What is under the point, topmost first.
+    pub fn pick(&self, x: f32, y: f32) -> Option<Selection> {
+        if y >= TIMELINE_TOP {
+            return (0..self.level.waves.len()).rev().find_map(|index| {
+                let (left, right) = self.wave_bounds(&self.level.waves[index]);
+                if x >= left && x <= right {
+                    Some(Selection::Wave(index))
+                } else {
+                    None
+                }
+            });
+        }
+        let near = |position: Position, radius: f32| {
+            let (dx, dy) = (x - position.x, y - position.y);
+            (dx * dx + dy * dy).sqrt() <= radius.max(PICK_DISTANCE)
+        };
+        let meteor = (0..self.level.meteors.len()).rev().find(|&index| {
+            let meteor = &self.level.meteors[index];
+            near(
+                Position {
+                    x: meteor.x,
+                    y: meteor.y,
+                },
+                meteor.radius,
+            )
+        });
+        if let Some(index) = meteor {
+            Some(Selection::Meteor(index))
+        } else if near(self.ship(), SHIP_RADIUS) {
+            Some(Selection::Ship)
+        } else if near(self.earth(), EARTH_RADIUS) {
+            Some(Selection::Earth)
+        } else {
+            None
+        }
+    }
+
+    /// A button went down at the point. The main button picks things up, places
+    /// a meteor on empty space and moves the timeline cursor; the other aims the
+    /// meteor under the point. Clicks off the screen, in the letterbox, do nothing.
+    pub fn press(&mut self, x: f32, y: f32, aim: bool) {
+        if !(0.0..=1.0).contains(&x) || !(0.0..=1.0).contains(&y) {
+            return;
+        }
+        let picked = self.pick(x, y);
+        if aim {
+            if let Some(Selection::Meteor(index)) = picked {
+                self.selection = picked;
+                self.drag = Some(Drag::Velocity);
+                self.aim_meteor(index, x, y);
+            }
+            return;
+        }
+        self.selection = picked;
+        self.drag = match picked {
+            Some(Selection::Wave(index)) => Some(Drag::WaveStart {
+                offset: self.timeline_seconds_at(x) - self.level.waves[index].start,
+            }),
+            Some(selection) => {
+                let position = self.position(selection);
+                Some(Drag::Move {
+                    offset_x: position.x - x,
+                    offset_y: position.y - y,
+                })
+            }
+            None if y >= TIMELINE_TOP => {
+                self.cursor = round_tenth(self.timeline_seconds_at(x));
+                None
+            }
+            None => {
+                self.level.meteors.push(PlacedMeteor {
+                    x,
+                    y,
+                    vel_x: 0.0,
+                    vel_y: 0.0,
+                    radius: NEW_METEOR_RADIUS,
+                    kind: self.meteor_kind,
+                });
+                self.selection = Some(Selection::Meteor(self.level.meteors.len() - 1));
+                self.changed = true;
+                Some(Drag::Move {
+                    offset_x: 0.0,
+                    offset_y: 0.0,
+                })
+            }
+        };
+    }
+
+    pub fn drag_to(&mut self, x: f32, y: f32) {
+        let (drag, selection) = match (self.drag, self.selection) {
+            (Some(drag), Some(selection)) => (drag, selection),
+            _ => return,
+        };
+        match (drag, selection) {
+            (Drag::Move { offset_x, offset_y }, _) => {
+                let position = Position {
+                    x: clamp_unit(x + offset_x),
+                    y: clamp_unit(y + offset_y),
+                };
+                self.set_position(selection, position);
+            }
+            (Drag::Velocity, Selection::Meteor(index)) => self.aim_meteor(index, x, y),
+            (Drag::WaveStart { offset }, Selection::Wave(index)) => {
+                let start = round_tenth(self.timeline_seconds_at(x) - offset).max(0.0);
+                self.level.waves[index].start = start;
+                self.changed = true;
+            }
+            _ => {}
+        }
+    }
+
+    pub fn release(&mut self) {
+        self.drag = None;
+    }
+
+    /// Changes the kind of the selected meteor or wave, or of the next meteor
+    /// placed. A wave goes through every kind alone and then all of them mixed.
+    pub fn cycle_kind(&mut self) {
+        match self.selection {
+            Some(Selection::Meteor(index)) => {
+                let meteor = &mut self.level.meteors[index];
+                meteor.kind = next_kind(meteor.kind);
+                self.meteor_kind = meteor.kind;
+            }
+            Some(Selection::Wave(index)) => {
+                let wave = &mut self.level.waves[index];
+                wave.kinds = match wave.kinds.as_slice() {
+                    [kind] if next_kind(*kind) == MeteorKind::Rock => {
+                        METEOR_TYPES.iter().map(|t| t.kind).collect()
+                    }
+                    [kind] => vec![next_kind(*kind)],
+                    _ => vec![MeteorKind::Rock],
+                };
+            }
+            _ => self.meteor_kind = next_kind(self.meteor_kind),
+        }
+        self.changed = true;
+    }
+
+    /// Makes the selected meteor bigger or smaller, or sends more or fewer
+    /// meteors in the selected wave.
+    pub fn grow(&mut self, bigger: bool) {
+        match self.selection {
+            Some(Selection::Meteor(index)) => {
+                let meteor = &mut self.level.meteors[index];
+                let radius = if bigger {
+                    meteor.radius * RADIUS_STEP
+                } else {
+                    meteor.radius / RADIUS_STEP
+                };
+                meteor.radius = radius.clamp(MIN_METEOR_RADIUS, MAX_METEOR_RADIUS);
+            }
+            Some(Selection::Wave(index)) => {
+                let wave = &mut self.level.waves[index];
+                wave.count = if bigger {
+                    wave.count + 1
+                } else {
+                    (wave.count - 1).max(1)
+                };
+            }
+            _ => return,
+        }
+        self.changed = true;
+    }
+
+    /// Moves the selected wave, or the cursor when no wave is selected, along the
+    /// timeline.
+    pub fn shift(&mut self, later: bool) {
+        let step = if later {
+            WAVE_START_STEP
+        } else {
+            -WAVE_START_STEP
+        };
+        match self.selection {
+            Some(Selection::Wave(index)) => {
+                let wave = &mut self.level.waves[index];
+                wave.start = (wave.start + step).max(0.0);
+                self.changed = true;
+            }
+            _ => self.cursor = (self.cursor + step).max(0.0),
+        }
+    }
+
+    pub fn change_interval(&mut self, longer: bool) {
+        if let Some(Selection::Wave(index)) = self.selection {
+            let wave = &mut self.level.waves[index];
+            let step = if longer {
+                WAVE_INTERVAL_STEP
+            } else {
+                -WAVE_INTERVAL_STEP
+            };
+            wave.interval = (wave.interval + step).max(0.0);
+            self.changed = true;
+        }
+    }
+
+    /// Sends the selected wave in from all edges, then each edge alone.
+    pub fn cycle_edges(&mut self) {
+        if let Some(Selection::Wave(index)) = self.selection {
+            let wave = &mut self.level.waves[index];
+            wave.edges = match wave.edges.as_slice() {
+                [Edge::Top] => vec![Edge::Bottom],
+                [Edge::Bottom] => vec![Edge::Left],
+                [Edge::Left] => vec![Edge::Right],
+                [Edge::Right] => Wave::default().edges,
+                _ => vec![Edge::Top],
+            };
+            self.changed = true;
+        }
+    }
+
+    pub fn add_wave(&mut self) {
+        self.level.waves.push(Wave {
+            start: self.cursor,
+            count: NEW_WAVE_COUNT,
+            ..Default::default()
+        });
+        self.selection = Some(Selection::Wave(self.level.waves.len() - 1));
+        self.changed = true;
+    }
+
+    /// Removes the selected meteor or wave. The Earth and the ship go back to
+    /// where they are by default.
+    pub fn delete_selected(&mut self) {
+        match self.selection.take() {
+            Some(Selection::Meteor(index)) => {
+                self.level.meteors.remove(index);
+            }
+            Some(Selection::Wave(index)) => {
+                self.level.waves.remove(index);
+            }
+            Some(Selection::Ship) => self.level.ship = None,
+            Some(Selection::Earth) => self.level.earth = None,
+            None => return,
+        }
+        self.changed = true;
+    }
+
+    pub fn cycle_goal(&mut self) {
+        self.level.win = match self.level.win {
+            Goal::ClearWaves => Goal::Survive {
+                seconds: SURVIVE_SECONDS,
+            },
+            Goal::Survive { .. } => Goal::Progress,
+            Goal::Progress => Goal::ClearWaves,
+        };
+        self.changed = true;
+    }
+
+    pub fn save(&mut self) {
+        self.status = match self.level.save(&self.path) {
+            Ok(()) => format!("Saved to {}", self.path.display()),
+            Err(e) => format!("Not saved: {}", e),
+        };
+    }
+
+    /// The selection in words, for the line under the help text.
+    pub fn describe_selection(&self) -> String {
+        match self.selection {
+            Some(Selection::Earth) => "Earth".to_string(),
+            Some(Selection::Ship) => "Ship start".to_string(),
+            Some(Selection::Meteor(index)) => {
+                let meteor = &self.level.meteors[index];
+                format!(
+                    "Meteor {}: {:?}, radius {:.3}, velocity ({:.4}, {:.4})",
+                    index + 1,
+                    meteor.kind,
+                    meteor.radius,
+                    meteor.vel_x,
+                    meteor.vel_y
+                )
+            }
+            Some(Selection::Wave(index)) => {
+                let wave = &self.level.waves[index];
+                format!(
+                    "Wave {}: {} x {:?} from {:?}, at {:.1}s, every {:.2}s",
+                    index + 1,
+                    wave.count,
+                    wave.kinds,
+                    wave.edges,
+                    wave.start,
+                    wave.interval
+                )
+            }
+            None => format!(
+                "Next meteor: {:?}, cursor at {:.1}s",
+                self.meteor_kind, self.cursor
+            ),
+        }
+    }
+
+    pub fn describe_goal(&self) -> String {
+        match self.level.win {
+            Goal::ClearWaves => "Win: clear every wave".to_string(),
+            Goal::Survive { seconds } => format!("Win: survive {:.0}s", seconds),
+            Goal::Progress => "Win: fill space age progress".to_string(),
+        }
+    }
+
+    /// Where the selection sits and how big it is, unless it is a wave.
+    pub fn selected_circle(&self) -> Option<(Position, f32)> {
+        let radius = match self.selection? {
+            Selection::Earth => EARTH_RADIUS,
+            Selection::Ship => SHIP_RADIUS,
+            Selection::Meteor(index) => self.level.meteors[index].radius,
+            Selection::Wave(_) => return None,
+        };
+        Some((self.position(self.selection?), radius))
+    }
+
+    fn timeline_seconds_at(&self, x: f32) -> f32 {
+        x * self.timeline_seconds()
+    }
+
+    fn position(&self, selection: Selection) -> Position {
+        match selection {
+            Selection::Earth => self.earth(),
+            Selection::Ship => self.ship(),
+            Selection::Meteor(index) => {
+                let meteor = &self.level.meteors[index];
+                Position {
+                    x: meteor.x,
+                    y: meteor.y,
+                }
+            }
+            Selection::Wave(_) => Position { x: 0.0, y: 0.0 },
+        }
+    }
+
+    fn set_position(&mut self, selection: Selection, position: Position) {
+        match selection {
+            Selection::Earth => self.level.earth = Some(position),
+            Selection::Ship => self.level.ship = Some(position),
+            Selection::Meteor(index) => {
+                let meteor = &mut self.level.meteors[index];
+                meteor.x = position.x;
+                meteor.y = position.y;
+            }
+            Selection::Wave(_) => return,
+        }
+        self.changed = true;
+    }
+
+    fn aim_meteor(&mut self, index: usize, x: f32, y: f32) {
+        let meteor = &mut self.level.meteors[index];
+        meteor.vel_x = (x - meteor.x) * VELOCITY_PER_DRAG;
+        meteor.vel_y = (y - meteor.y) * VELOCITY_PER_DRAG;
+        self.changed = true;
+    }
+}
+
+/// First and last second a wave sends a meteor at.
+fn wave_span(wave: &Wave) -> (f32, f32) {
+    let last = wave.count.saturating_sub(1) as f32;
+    (wave.start, wave.start + wave.interval * last)
+}
+
+fn next_kind(kind: MeteorKind) -> MeteorKind {
+    METEOR_TYPES[(kind as usize + 1) % METEOR_TYPES.len()].kind
+}
+
+fn clamp_unit(value: f32) -> f32 {
+    value.clamp(0.0, 1.0)
+}
+
+fn round_tenth(value: f32) -> f32 {
+    (value * 10.0).round() / 10.0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("ld46-editor-{}-{}.toml", name, std::process::id()))
+    }
+
+    fn new_editor(name: &str) -> Editor {
+        Editor::open(&temp_path(name)).unwrap()
+    }
+
+    #[test]
+    fn clicking_empty_space_places_a_meteor() {
+        let mut editor = new_editor("place");
+        editor.meteor_kind = MeteorKind::Iron;
+        editor.press(0.7, 0.2, false);
+        editor.release();
+
+        assert_eq!(editor.level.meteors.len(), 1);
+        let meteor = &editor.level.meteors[0];
+        assert_eq!((meteor.x, meteor.y), (0.7, 0.2));
+        assert_eq!(meteor.kind, MeteorKind::Iron);
+        assert_eq!(editor.selection, Some(Selection::Meteor(0)));
+    }
+
+    #[test]
+    fn clicking_the_letterbox_does_nothing() {
+        let mut editor = new_editor("letterbox");
+        for &(x, y) in &[(-0.1, 0.5), (1.2, 0.5), (0.5, -0.05), (0.5, 1.1)] {
+            editor.press(x, y, false);
+            editor.drag_to(0.5, 0.5);
+            editor.release();
+        }
+        assert!(editor.level.meteors.is_empty());
+        assert_eq!(editor.selection, None);
+    }
+
+    #[test]
+    fn dragging_keeps_the_grabbed_spot_under_the_mouse() {
+        let mut editor = new_editor("drag");
+        editor.press(0.7, 0.2, false);
+        editor.release();
+
+        // Grabbed a little off centre.
+        editor.press(0.705, 0.2, false);
+        editor.drag_to(0.505, 0.4);
+        editor.release();
+        let meteor = &editor.level.meteors[0];
+        assert!((meteor.x - 0.5).abs() < 1e-6, "{}", meteor.x);
+        assert!((meteor.y - 0.4).abs() < 1e-6, "{}", meteor.y);
+
+        // Moving on after letting go drags nothing.
+        editor.drag_to(0.1, 0.1);
+        assert!((editor.level.meteors[0].x - 0.5).abs() < 1e-6);
+    }
+
+    #[test]
+    fn dragging_stops_at_the_edge_of_the_screen() {
+        let mut editor = new_editor("drag-edge");
+        editor.press(0.7, 0.2, false);
+        editor.drag_to(1.3, -0.2);
+        editor.release();
+        let meteor = &editor.level.meteors[0];
+        assert_eq!((meteor.x, meteor.y), (1.0, 0.0));
+        assert!(editor.level.validate().is_ok());
+    }
+
+    #[test]
+    fn dragging_the_other_button_aims_a_meteor() {
+        let mut editor = new_editor("aim");
+        editor.press(0.5, 0.2, false);
+        editor.release();
+        editor.press(0.5, 0.2, true);
+        editor.drag_to(0.75, 0.2);
+        editor.release();
+        let meteor = &editor.level.meteors[0];
+        assert!((meteor.vel_x - 0.25 * VELOCITY_PER_DRAG).abs() < 1e-9);
+        assert_eq!(meteor.vel_y, 0.0);
+        assert_eq!((meteor.x, meteor.y), (0.5, 0.2));
+    }
+
+    #[test]
+    fn dragging_a_wave_moves_its_start() {
+        let mut editor = new_editor("wave");
+        editor.cursor = 6.0;
+        editor.add_wave();
+        let seconds = editor.timeline_seconds();
+        let x = editor.timeline_x(6.0) + 0.001;
+        editor.press(x, 0.95, false);
+        assert_eq!(editor.selection, Some(Selection::Wave(0)));
+        editor.drag_to(x + 12.0 / seconds, 0.95);
+        editor.release();
+        assert_eq!(editor.level.waves[0].start, 18.0);
+    }
+
+    #[test]
+    fn saved_level_opens_the_same() {
+        let path = temp_path("save");
+        let mut editor = Editor::open(&path).unwrap();
+        editor.press(0.7, 0.2, false);
+        editor.drag_to(0.6, 0.3);
+        editor.release();
+        editor.press(0.6, 0.3, true);
+        editor.drag_to(0.8, 0.3);
+        editor.release();
+        editor.cursor = 4.0;
+        editor.add_wave();
+        editor.cycle_goal();
+        editor.save();
+        assert!(editor.status.starts_with("Saved"), "{}", editor.status);
+
+        let reopened = Editor::open(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(reopened.level, editor.level);
+    }
+
+    #[test]
+    fn empty_level_is_not_saved() {
+        let path = temp_path("empty");
+        let mut editor = Editor::open(&path).unwrap();
+        editor.save();
+        assert!(editor.status.starts_with("Not saved"), "{}", editor.status);
+        assert!(!path.exists());
+    }
+}
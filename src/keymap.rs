@@ -0,0 +1,421 @@
+//! Turns keyboard, mouse and gamepad buttons into the actions of the keymap kept
+//! in the settings, and back into the names the keymap stores.
+
+use ggez::event::{Button, KeyCode, MouseButton};
+
+use ld46_keep_it_alive::settings::{Action, Keymap};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Input {
+    Key(KeyCode),
+    Mouse(MouseButton),
+    Pad(Button),
+}
+
+/// Keys that can be bound. Anything missing here could not be saved by name.
+const KEYS: &[KeyCode] = &[
+    KeyCode::A,
+    KeyCode::B,
+    KeyCode::C,
+    KeyCode::D,
+    KeyCode::E,
+    KeyCode::F,
+    KeyCode::G,
+    KeyCode::H,
+    KeyCode::I,
+    KeyCode::J,
+    KeyCode::K,
+    KeyCode::L,
+    KeyCode::M,
+    KeyCode::N,
+    KeyCode::O,
+    KeyCode::P,
+    KeyCode::Q,
+    KeyCode::R,
+    KeyCode::S,
+    KeyCode::T,
+    KeyCode::U,
+    KeyCode::V,
+    KeyCode::W,
+    KeyCode::X,
+    KeyCode::Y,
+    KeyCode::Z,
+    KeyCode::Key0,
+    KeyCode::Key1,
+    KeyCode::Key2,
+    KeyCode::Key3,
+    KeyCode::Key4,
+    KeyCode::Key5,
+    KeyCode::Key6,
+    KeyCode::Key7,
+    KeyCode::Key8,
+    KeyCode::Key9,
+    KeyCode::F1,
+    KeyCode::F2,
+    KeyCode::F3,
+    KeyCode::F4,
+    KeyCode::F5,
+    KeyCode::F6,
+    KeyCode::F7,
+    KeyCode::F8,
+    KeyCode::F9,
+    KeyCode::F10,
+    KeyCode::F11,
+    KeyCode::F12,
+    KeyCode::Up,
+    KeyCode::Down,
+    KeyCode::Left,
+    KeyCode::Right,
+    KeyCode::Escape,
+    KeyCode::Space,
+    KeyCode::Return,
+    KeyCode::Tab,
+    KeyCode::Back,
+    KeyCode::Delete,
+    KeyCode::Insert,
+    KeyCode::Home,
+    KeyCode::End,
+    KeyCode::PageUp,
+    KeyCode::PageDown,
+    KeyCode::LShift,
+    KeyCode::RShift,
+    KeyCode::LControl,
+    KeyCode::RControl,
+    KeyCode::LAlt,
+    KeyCode::RAlt,
+    KeyCode::Numpad0,
+    KeyCode::Numpad1,
+    KeyCode::Numpad2,
+    KeyCode::Numpad3,
+    KeyCode::Numpad4,
+    KeyCode::Numpad5,
+    KeyCode::Numpad6,
+    KeyCode::Numpad7,
+    KeyCode::Numpad8,
+    KeyCode::Numpad9,
+    KeyCode::NumpadEnter,
+    KeyCode::Add,
+    KeyCode::Subtract,
+    KeyCode::Multiply,
+    KeyCode::Divide,
+    KeyCode::Decimal,
+    KeyCode::Comma,
+    KeyCode::Period,
+    KeyCode::Semicolon,
+    KeyCode::Apostrophe,
+    KeyCode::Slash,
+    KeyCode::Backslash,
+    KeyCode::Minus,
+    KeyCode::Equals,
+    KeyCode::LBracket,
+    KeyCode::RBracket,
+    KeyCode::Grave,
+];
+
+const MOUSE_BUTTONS: &[MouseButton] = &[MouseButton::Left, MouseButton::Right, MouseButton::Middle];
+
+const PAD_BUTTONS: &[Button] = &[
+    Button::South,
+    Button::East,
+    Button::North,
+    Button::West,
+    Button::LeftTrigger,
+    Button::LeftTrigger2,
+    Button::RightTrigger,
+    Button::RightTrigger2,
+    Button::Select,
+    Button::Start,
+    Button::Mode,
+    Button::LeftThumb,
+    Button::RightThumb,
+    Button::DPadUp,
+    Button::DPadDown,
+    Button::DPadLeft,
+    Button::DPadRight,
+];
+
+impl Input {
+    /// The name the input is saved under, e.g. `W`, `Mouse Left` or `Pad South`.
+    pub fn name(self) -> String {
+        match self {
+            Input::Key(key) => format!("{:?}", key),
+            Input::Mouse(button) => format!("Mouse {:?}", button),
+            Input::Pad(button) => format!("Pad {:?}", button),
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Input> {
+        KEYS.iter()
+            .map(|&key| Input::Key(key))
+            .chain(MOUSE_BUTTONS.iter().map(|&button| Input::Mouse(button)))
+            .chain(PAD_BUTTONS.iter().map(|&button| Input::Pad(button)))
+            .find(|input| input.name() == name)
+    }
+
+    /// Whether the input has a name, and so can be stored in the keymap.
+    pub fn is_bindable(self) -> bool {
+        match self {
+            Input::Key(key) => KEYS.contains(&key),
+            Input::Mouse(button) => MOUSE_BUTTONS.contains(&button),
+            Input::Pad(button) => PAD_BUTTONS.contains(&button),
+        }
+    }
+}
+
+/// Stick deflection below this, in any direction, is treated as the stick resting
+/// in the middle.
+const STICK_DEAD_ZONE: f32 = 0.2;
+
+/// Where a stick reported at `(x, y)` points, in world coordinates with y
+/// pointing down, or nothing while it rests in the dead zone. The dead zone is
+/// round, so a diagonal keeps its angle right up to the edge of it.
+pub fn stick_direction(x: f32, y: f32) -> Option<(f32, f32)> {
+    if (x * x + y * y).sqrt() < STICK_DEAD_ZONE {
+        return None;
+    }
+    // Sticks report up as positive.
+    Some((x, -y))
+}
+
+/// What a fixed menu key does. Menus don't go through the keymap, so they keep
+/// working however the actions are bound.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MenuCommand {
+    Previous,
+    Next,
+    Confirm,
+    Clear,
+    Back,
+}
+
+impl MenuCommand {
+    pub fn from_input(input: Input) -> Option<MenuCommand> {
+        match input {
+            Input::Key(KeyCode::Up) | Input::Pad(Button::DPadUp) => Some(MenuCommand::Previous),
+            Input::Key(KeyCode::Down) | Input::Pad(Button::DPadDown) => Some(MenuCommand::Next),
+            Input::Key(KeyCode::Return)
+            | Input::Key(KeyCode::NumpadEnter)
+            | Input::Pad(Button::South) => Some(MenuCommand::Confirm),
+            Input::Key(KeyCode::Back) | Input::Key(KeyCode::Delete) | Input::Pad(Button::West) => {
+                Some(MenuCommand::Clear)
+            }
+            Input::Key(KeyCode::Escape) | Input::Pad(Button::East) => Some(MenuCommand::Back),
+            _ => None,
+        }
+    }
+}
+
+/// A keymap with every name resolved, ready to look inputs up in.
+#[derive(Default)]
+pub struct Bindings {
+    bound: Vec<(Input, Action)>,
+}
+
+impl Bindings {
+    /// Resolves the names in `keymap`. Names that mean nothing are returned
+    /// alongside, so they can be reported.
+    pub fn new(keymap: &Keymap) -> (Bindings, Vec<String>) {
+        let mut bindings = Bindings::default();
+        let mut unknown = Vec::new();
+        for &action in Action::ALL.iter() {
+            for name in keymap.bindings(action) {
+                match Input::from_name(name) {
+                    Some(input) => bindings.bound.push((input, action)),
+                    None => unknown.push(name.clone()),
+                }
+            }
+        }
+        (bindings, unknown)
+    }
+
+    pub fn actions(&self, input: Input) -> impl Iterator<Item = Action> + '_ {
+        self.bound
+            .iter()
+            .filter(move |(bound, _)| *bound == input)
+            .map(|&(_, action)| action)
+    }
+}
+
+/// Where the player is on the screen for changing bindings.
+#[derive(Default)]
+pub struct BindingsScreen {
+    /// Index into `Action::ALL`.
+    pub selected: usize,
+    /// Set while waiting for the input to bind to the selected action.
+    pub capturing: bool,
+}
+
+impl BindingsScreen {
+    pub fn selected_action(&self) -> Action {
+        Action::ALL[self.selected]
+    }
+
+    pub fn select_previous(&mut self) {
+        self.selected = (self.selected + Action::ALL.len() - 1) % Action::ALL.len();
+    }
+
+    pub fn select_next(&mut self) {
+        self.selected = (self.selected + 1) % Action::ALL.len();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ld46_keep_it_alive::settings::Settings;
+
+    /// The game's actions bound to `input`, leaving out the editor's.
+    fn actions(bindings: &Bindings, input: Input) -> Vec<Action> {
+        bindings
+            .actions(input)
+            .filter(|action| !action.in_editor())
+            .collect()
+    }
+
+    fn editor_actions(bindings: &Bindings, input: Input) -> Vec<Action> {
+        bindings
+            .actions(input)
+            .filter(|action| action.in_editor())
+            .collect()
+    }
+
+    #[test]
+    fn resting_stick_points_nowhere() {
+        assert_eq!(stick_direction(0.0, 0.0), None);
+        assert_eq!(stick_direction(0.1, -0.1), None);
+        assert_eq!(stick_direction(-0.19, 0.0), None);
+    }
+
+    #[test]
+    fn stick_up_points_up_the_screen() {
+        assert_eq!(stick_direction(0.0, 1.0), Some((0.0, -1.0)));
+        assert_eq!(stick_direction(-0.5, 0.0), Some((-0.5, 0.0)));
+    }
+
+    #[test]
+    fn diagonal_near_the_dead_zone_keeps_its_angle() {
+        // Each axis alone is inside the dead zone, together they are outside it.
+        let (x, y) = stick_direction(0.15, 0.15).unwrap();
+        assert_eq!((x, y), (0.15, -0.15));
+        // Just outside on one axis doesn't snap the other one to 0.
+        let (x, y) = stick_direction(0.25, 0.1).unwrap();
+        assert_eq!((x, y), (0.25, -0.1));
+    }
+
+    #[test]
+    fn every_default_binding_has_a_name() {
+        let (_, unknown) = Bindings::new(&Keymap::default());
+        assert!(unknown.is_empty(), "{:?}", unknown);
+    }
+
+    #[test]
+    fn title_screen_keys_go_through_the_keymap() {
+        let (bindings, _) = Bindings::new(&Keymap::default());
+        let key = |key| actions(&bindings, Input::Key(key));
+        assert_eq!(key(KeyCode::F), vec![Action::ToggleFlightModel]);
+        assert_eq!(key(KeyCode::B), vec![Action::EditBindings]);
+        assert_eq!(key(KeyCode::E), vec![Action::OpenEditor]);
+    }
+
+    #[test]
+    fn rebinding_an_input_takes_it_from_its_old_action() {
+        let mut keymap = Keymap::default();
+        keymap.bind(Action::Shoot, "F");
+        keymap.bind(Action::ToggleFlightModel, "G");
+        let (bindings, _) = Bindings::new(&keymap);
+        assert_eq!(
+            actions(&bindings, Input::Key(KeyCode::F)),
+            vec![Action::Shoot]
+        );
+        assert_eq!(
+            actions(&bindings, Input::Key(KeyCode::G)),
+            vec![Action::ToggleFlightModel]
+        );
+        // The shot keeps the inputs it already had.
+        assert_eq!(
+            actions(&bindings, Input::Mouse(MouseButton::Left)),
+            vec![Action::Shoot]
+        );
+    }
+
+    #[test]
+    fn editor_keys_go_through_the_keymap() {
+        let (bindings, _) = Bindings::new(&Keymap::default());
+        let key = |key| editor_actions(&bindings, Input::Key(key));
+        assert_eq!(key(KeyCode::S), vec![Action::EditorSave]);
+        assert_eq!(key(KeyCode::T), vec![Action::EditorTest]);
+        assert_eq!(key(KeyCode::LBracket), vec![Action::EditorShrink]);
+        assert_eq!(key(KeyCode::Back), vec![Action::EditorDelete]);
+        assert_eq!(key(KeyCode::Delete), vec![Action::EditorDelete]);
+        // The same key means something else outside the editor.
+        assert_eq!(key(KeyCode::E), vec![Action::EditorEdges]);
+        assert_eq!(
+            actions(&bindings, Input::Key(KeyCode::E)),
+            vec![Action::OpenEditor]
+        );
+    }
+
+    #[test]
+    fn rebinding_an_editor_action_leaves_the_game_alone() {
+        let mut keymap = Keymap::default();
+        keymap.bind(Action::EditorSave, "W");
+        keymap.bind(Action::EditorGoal, "S");
+        let (bindings, _) = Bindings::new(&keymap);
+        assert_eq!(
+            editor_actions(&bindings, Input::Key(KeyCode::W)),
+            vec![Action::EditorSave]
+        );
+        assert_eq!(
+            editor_actions(&bindings, Input::Key(KeyCode::S)),
+            vec![Action::EditorGoal]
+        );
+        // Taken from the other editor action, but not from moving.
+        assert_eq!(
+            actions(&bindings, Input::Key(KeyCode::W)),
+            vec![Action::MoveUp]
+        );
+        assert_eq!(
+            actions(&bindings, Input::Key(KeyCode::S)),
+            vec![Action::MoveDown]
+        );
+    }
+
+    #[test]
+    fn unknown_names_are_reported() {
+        let mut keymap = Keymap::default();
+        keymap.bind(Action::Pause, "Pad Nonsense");
+        let (bindings, unknown) = Bindings::new(&keymap);
+        assert_eq!(unknown, vec!["Pad Nonsense".to_string()]);
+        assert_eq!(
+            actions(&bindings, Input::Key(KeyCode::P)),
+            vec![Action::Pause]
+        );
+    }
+
+    #[test]
+    fn rebound_keymap_survives_save_and_load() {
+        let mut settings = Settings::default();
+        settings.keymap.bind(Action::OpenEditor, "Pad North");
+        settings.keymap.bind(Action::MoveUp, "Mouse Right");
+        settings.keymap.bindings_mut(Action::Quit).clear();
+        settings.keymap.bind(Action::EditorDelete, "X");
+        let path = std::env::temp_dir().join(format!("ld46-keymap-{}.toml", std::process::id()));
+        settings.save(&path).unwrap();
+        let loaded = Settings::load(&path);
+        let _ = std::fs::remove_file(&path);
+
+        let loaded = loaded.unwrap();
+        assert_eq!(loaded, settings);
+        let (bindings, unknown) = Bindings::new(&loaded.keymap);
+        assert!(unknown.is_empty());
+        assert_eq!(
+            actions(&bindings, Input::Pad(Button::North)),
+            vec![Action::OpenEditor]
+        );
+        assert!(actions(&bindings, Input::Key(KeyCode::Escape)).is_empty());
+        assert_eq!(
+            editor_actions(&bindings, Input::Key(KeyCode::X)),
+            vec![Action::EditorDelete]
+        );
+    }
+}
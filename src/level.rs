@@ -0,0 +1,476 @@
+//! Scripted levels. A level is a TOML file listing waves of meteors, where things
+//! start out and the conditions for winning and losing it; without one the game
+//! falls back to the endless stream paced by the balance.
+
+use std::fmt;
+use std::fs;
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+use crate::meteor::MeteorKind;
+use crate::simulation::Direction;
+
+/// Directory in the resources that holds the campaign levels.
+pub const LEVELS_DIR: &str = "levels";
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Level {
+    pub name: String,
+    #[serde(default)]
+    pub description: String,
+    #[serde(default)]
+    pub win: Goal,
+    #[serde(default)]
+    pub lose: LoseRules,
+    /// Where the ship starts. Left out, it starts where it does in the endless game.
+    #[serde(default)]
+    pub ship: Option<Position>,
+    /// Where the Earth sits. Left out, it is in the middle of the screen.
+    #[serde(default)]
+    pub earth: Option<Position>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub waves: Vec<Wave>,
+    /// Meteors already on the screen when the level starts.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub meteors: Vec<PlacedMeteor>,
+}
+
+/// A point in world coordinates, where the screen spans 0 to 1 on both axes.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Position {
+    pub x: f32,
+    pub y: f32,
+}
+
+/// A meteor that is there from the start. Without a velocity it sits still.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct PlacedMeteor {
+    pub x: f32,
+    pub y: f32,
+    #[serde(default)]
+    pub vel_x: f32,
+    #[serde(default)]
+    pub vel_y: f32,
+    pub radius: f32,
+    #[serde(default = "rock")]
+    pub kind: MeteorKind,
+}
+
+/// What the player has to do to win a level.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+pub enum Goal {
+    /// Every wave has spawned and every meteor is gone.
+    #[default]
+    ClearWaves,
+    /// The Earth is still around after the given number of seconds.
+    Survive { seconds: f32 },
+    /// Space age progress fills up, as in the endless game.
+    Progress,
+}
+
+/// Ways to lose on top of the ones every game has.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LoseRules {
+    /// Seconds the player has to reach the goal.
+    pub time_limit: Option<f32>,
+    /// Meteors that may hit the Earth; one more ends the game.
+    pub max_earth_hits: Option<u32>,
+}
+
+/// A group of meteors sent in one after another.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Wave {
+    /// Seconds from the start of the level until the first meteor.
+    pub start: f32,
+    pub count: u32,
+    /// Seconds between meteors of the wave.
+    #[serde(default = "default_interval")]
+    pub interval: f32,
+    /// Edges of the screen the meteors come in from, picked at random.
+    #[serde(default = "all_edges")]
+    pub edges: Vec<Edge>,
+    /// Kinds of meteor, picked at random.
+    #[serde(default = "only_rocks")]
+    pub kinds: Vec<MeteorKind>,
+    /// Ranges for every new meteor. Left out, they come from the balance.
+    #[serde(default)]
+    pub min_size: Option<f32>,
+    #[serde(default)]
+    pub max_size: Option<f32>,
+    #[serde(default)]
+    pub min_velocity: Option<f32>,
+    #[serde(default)]
+    pub max_velocity: Option<f32>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Edge {
+    Top,
+    Bottom,
+    Left,
+    Right,
+}
+
+impl Edge {
+    /// The side a meteor enters from, as the spawner names it.
+    pub fn direction(self) -> Direction {
+        match self {
+            Edge::Top => Direction::Up,
+            Edge::Bottom => Direction::Down,
+            Edge::Left => Direction::Left,
+            Edge::Right => Direction::Right,
+        }
+    }
+}
+
+fn default_interval() -> f32 {
+    1.0
+}
+
+fn all_edges() -> Vec<Edge> {
+    vec![Edge::Top, Edge::Bottom, Edge::Left, Edge::Right]
+}
+
+fn only_rocks() -> Vec<MeteorKind> {
+    vec![MeteorKind::Rock]
+}
+
+fn rock() -> MeteorKind {
+    MeteorKind::Rock
+}
+
+impl Default for Wave {
+    fn default() -> Self {
+        Wave {
+            start: 0.0,
+            count: 1,
+            interval: default_interval(),
+            edges: all_edges(),
+            kinds: only_rocks(),
+            min_size: None,
+            max_size: None,
+            min_velocity: None,
+            max_velocity: None,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum LevelError {
+    Io(String),
+    Parse(String),
+    Invalid { key: String, message: String },
+}
+
+impl fmt::Display for LevelError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            LevelError::Io(message) => write!(f, "{}", message),
+            LevelError::Parse(message) => write!(f, "{}", message),
+            LevelError::Invalid { key, message } => write!(f, "`{}` {}", key, message),
+        }
+    }
+}
+
+impl std::error::Error for LevelError {}
+
+impl LevelError {
+    /// Names the file the error came from, for levels read from disk.
+    fn in_file(self, path: &Path) -> LevelError {
+        match self {
+            LevelError::Io(message) => LevelError::Io(message),
+            LevelError::Parse(message) => {
+                LevelError::Parse(format!("{}: {}", path.display(), message))
+            }
+            LevelError::Invalid { key, message } => LevelError::Invalid {
+                key,
+                message: format!("{} in {}", message, path.display()),
+            },
+        }
+    }
+}
+
+impl Level {
+    /// A level with nothing in it yet, to start editing from.
+    pub fn new(name: &str) -> Level {
+        Level {
+            name: name.to_string(),
+            description: String::new(),
+            win: Goal::default(),
+            lose: LoseRules::default(),
+            ship: None,
+            earth: None,
+            waves: Vec::new(),
+            meteors: Vec::new(),
+        }
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Level, LevelError> {
+        let path = path.as_ref();
+        let contents = fs::read_to_string(path)
+            .map_err(|e| LevelError::Io(format!("{}: {}", path.display(), e)))?;
+        Level::parse(&contents).map_err(|e| e.in_file(path))
+    }
+
+    pub fn parse(contents: &str) -> Result<Level, LevelError> {
+        let level: Level =
+            toml::from_str(contents).map_err(|e| LevelError::Parse(e.to_string()))?;
+        level.validate()?;
+        Ok(level)
+    }
+
+    /// Checks the level first, so a file that wouldn't load again is never written.
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), LevelError> {
+        self.validate()?;
+        let path = path.as_ref();
+        fs::write(path, self.to_toml())
+            .map_err(|e| LevelError::Io(format!("{}: {}", path.display(), e)))
+    }
+
+    pub fn to_toml(&self) -> String {
+        toml::to_string(self).expect("Level always serializes")
+    }
+
+    pub fn validate(&self) -> Result<(), LevelError> {
+        if self.waves.is_empty() && self.meteors.is_empty() {
+            return Err(invalid(
+                "waves",
+                "must not be empty when there are no `meteors`",
+            ));
+        }
+        for &(key, position) in &[("ship", self.ship), ("earth", self.earth)] {
+            if let Some(position) = position {
+                check_on_screen(key, position.x, position.y)?;
+            }
+        }
+        for (index, meteor) in self.meteors.iter().enumerate() {
+            let key = |field: &str| format!("meteors[{}].{}", index, field);
+            check_on_screen(&key("x"), meteor.x, meteor.y)?;
+            check_positive(&key("radius"), meteor.radius)?;
+            if !meteor.vel_x.is_finite() || !meteor.vel_y.is_finite() {
+                return Err(invalid(&key("vel_x"), "must be a finite number"));
+            }
+        }
+        if let Goal::Survive { seconds } = self.win {
+            check_positive("win.seconds", seconds)?;
+        }
+        if let Some(time_limit) = self.lose.time_limit {
+            check_positive("lose.time_limit", time_limit)?;
+        }
+        for (index, wave) in self.waves.iter().enumerate() {
+            let key = |field: &str| format!("waves[{}].{}", index, field);
+            if !wave.start.is_finite() || wave.start < 0.0 {
+                return Err(invalid(&key("start"), "must not be negative"));
+            }
+            if wave.count == 0 {
+                return Err(invalid(&key("count"), "must be greater than 0"));
+            }
+            if !wave.interval.is_finite() || wave.interval < 0.0 {
+                return Err(invalid(&key("interval"), "must not be negative"));
+            }
+            if wave.edges.is_empty() {
+                return Err(invalid(&key("edges"), "must not be empty"));
+            }
+            if wave.kinds.is_empty() {
+                return Err(invalid(&key("kinds"), "must not be empty"));
+            }
+            for &(field, value) in &[
+                ("min_size", wave.min_size),
+                ("max_size", wave.max_size),
+                ("min_velocity", wave.min_velocity),
+                ("max_velocity", wave.max_velocity),
+            ] {
+                if let Some(value) = value {
+                    check_positive(&key(field), value)?;
+                }
+            }
+            if let (Some(min), Some(max)) = (wave.min_size, wave.max_size) {
+                if min > max {
+                    return Err(invalid(
+                        &key("min_size"),
+                        "must not be larger than `max_size`",
+                    ));
+                }
+            }
+            if let (Some(min), Some(max)) = (wave.min_velocity, wave.max_velocity) {
+                if min > max {
+                    return Err(invalid(
+                        &key("min_velocity"),
+                        "must not be larger than `max_velocity`",
+                    ));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Meteors the level sends in all together, placed ones included but not
+    /// counting fragments.
+    pub fn total_meteors(&self) -> u32 {
+        let from_waves: u32 = self.waves.iter().map(|wave| wave.count).sum();
+        from_waves + self.meteors.len() as u32
+    }
+}
+
+/// Reads every `.toml` file in `dir`, ordered by file name. Files that fail to
+/// load are returned separately, so one broken level doesn't hide the others;
+/// each error names its file.
+pub fn load_levels<P: AsRef<Path>>(dir: P) -> (Vec<Level>, Vec<LevelError>) {
+    let dir = dir.as_ref();
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) => {
+            let error = LevelError::Io(format!("{}: {}", dir.display(), e));
+            return (Vec::new(), vec![error]);
+        }
+    };
+    let mut paths: Vec<_> = entries
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| path.extension().and_then(|ext| ext.to_str()) == Some("toml"))
+        .collect();
+    paths.sort();
+
+    let mut levels = Vec::new();
+    let mut errors = Vec::new();
+    for path in paths {
+        match Level::load(&path) {
+            Ok(level) => levels.push(level),
+            Err(e) => errors.push(e),
+        }
+    }
+    (levels, errors)
+}
+
+fn invalid(key: &str, message: &str) -> LevelError {
+    LevelError::Invalid {
+        key: key.to_string(),
+        message: message.to_string(),
+    }
+}
+
+fn check_on_screen(key: &str, x: f32, y: f32) -> Result<(), LevelError> {
+    if !(0.0..=1.0).contains(&x) || !(0.0..=1.0).contains(&y) {
+        return Err(LevelError::Invalid {
+            key: key.to_string(),
+            message: format!("must be on the screen, from 0 to 1 (got {}, {})", x, y),
+        });
+    }
+    Ok(())
+}
+
+fn check_positive(key: &str, value: f32) -> Result<(), LevelError> {
+    if !value.is_finite() || value <= 0.0 {
+        return Err(LevelError::Invalid {
+            key: key.to_string(),
+            message: format!("must be greater than 0 (got {})", value),
+        });
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    /// Writes `contents` to a file of its own and loads it as a level.
+    fn load_from(name: &str, contents: &str) -> (PathBuf, Result<Level, LevelError>) {
+        let path = std::env::temp_dir().join(format!("ld46-{}-{}.toml", name, std::process::id()));
+        fs::write(&path, contents).unwrap();
+        let level = Level::load(&path);
+        fs::remove_file(&path).unwrap();
+        (path, level)
+    }
+
+    const ONE_METEOR: &str = r#"
+name = "One meteor"
+
+[win]
+type = "survive"
+seconds = 30.0
+
+[lose]
+max_earth_hits = 2
+
+[[meteors]]
+x = 0.25
+y = 0.75
+radius = 0.03
+kind = "iron"
+"#;
+
+    #[test]
+    fn shipped_levels_are_valid() {
+        let shipped = [
+            include_str!("../resources/levels/01-first-light.toml"),
+            include_str!("../resources/levels/02-crossfire.toml"),
+            include_str!("../resources/levels/03-iron-rain.toml"),
+            include_str!("../resources/levels/04-shrapnel.toml"),
+            include_str!("../resources/levels/05-the-long-night.toml"),
+        ];
+        for contents in &shipped {
+            Level::parse(contents).unwrap();
+        }
+    }
+
+    #[test]
+    fn valid_level_loads() {
+        let (_, level) = load_from("valid-level", ONE_METEOR);
+        let level = level.unwrap();
+        assert_eq!(level.win, Goal::Survive { seconds: 30.0 });
+        assert_eq!(level.lose.max_earth_hits, Some(2));
+        assert_eq!(level.lose.time_limit, None);
+        assert_eq!(level.meteors.len(), 1);
+        assert_eq!(level.meteors[0].kind, MeteorKind::Iron);
+        assert_eq!(level.meteors[0].vel_x, 0.0);
+        assert_eq!(level.total_meteors(), 1);
+    }
+
+    #[test]
+    fn level_without_meteors_is_rejected_with_its_file_name() {
+        let (path, level) = load_from("no-meteors", "name = \"Empty\"\n");
+        let message = level.unwrap_err().to_string();
+        assert!(message.contains("`waves`"), "{}", message);
+        assert!(message.contains(&path.display().to_string()), "{}", message);
+    }
+
+    #[test]
+    fn meteor_off_the_screen_is_rejected_with_its_file_name() {
+        let contents = ONE_METEOR.replace("x = 0.25", "x = 1.5");
+        let (path, level) = load_from("off-screen", &contents);
+        let message = level.unwrap_err().to_string();
+        assert!(message.contains("`meteors[0].x`"), "{}", message);
+        assert!(message.contains("(got 1.5, 0.75)"), "{}", message);
+        assert!(message.contains(&path.display().to_string()), "{}", message);
+    }
+
+    #[test]
+    fn unreadable_level_names_its_file() {
+        let (path, level) = load_from("broken", "name = ");
+        let message = level.unwrap_err().to_string();
+        assert!(message.contains(&path.display().to_string()), "{}", message);
+
+        let missing = std::env::temp_dir().join("ld46-no-such-level.toml");
+        let message = Level::load(&missing).unwrap_err().to_string();
+        assert!(
+            message.contains(&missing.display().to_string()),
+            "{}",
+            message
+        );
+    }
+
+    #[test]
+    fn saved_level_loads_the_same() {
+        let level = Level::parse(ONE_METEOR).unwrap();
+        let (_, loaded) = load_from("saved", &level.to_toml());
+        assert_eq!(loaded.unwrap(), level);
+    }
+}
@@ -0,0 +1,10 @@
+pub mod balance;
+pub mod collision;
+pub mod ecs;
+pub mod level;
+pub mod meteor;
+pub mod render;
+pub mod replay;
+pub mod settings;
+pub mod shape;
+pub mod simulation;
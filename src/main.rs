@@ -1,57 +1,124 @@
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")]
 
 use std::collections::BTreeMap;
-use std::collections::HashMap;
-use std::collections::HashSet;
 use std::env;
 use std::path;
 
-use rand::distributions::StandardNormal;
-use rand::prelude::*;
-
 use ggez::audio;
 use ggez::audio::SoundSource;
 use ggez::conf;
-use ggez::event::{self, EventHandler, KeyCode, KeyMods, MouseButton};
+use ggez::event::{self, Axis, Button, EventHandler, KeyCode, KeyMods, MouseButton};
+use ggez::input::gamepad::GamepadId;
 use ggez::nalgebra as na;
-use ggez::{graphics, Context, ContextBuilder, GameResult};
+use ggez::{graphics, Context, ContextBuilder, GameError, GameResult};
 
-use rand::{
-    distributions::{Distribution, Standard},
-    Rng,
+use ld46_keep_it_alive::balance::{Balance, BalanceWatcher, DEFAULT_BALANCE_FILE};
+use ld46_keep_it_alive::ecs::{Circle, Entity, Heading, TextLabel, Texture, Transform, World};
+use ld46_keep_it_alive::level::{load_levels, Level, LEVELS_DIR};
+use ld46_keep_it_alive::render::{
+    interpolate_angle, interpolate_axis, record_positions, MeshCache, MeshKey,
 };
+use ld46_keep_it_alive::replay::{Replay, ReplayPlayer};
+use ld46_keep_it_alive::settings::{Action, Settings};
+use ld46_keep_it_alive::simulation::*;
 
+mod editor;
+mod keymap;
 mod render_util;
+mod state;
+use editor::{Editor, Selection, TIMELINE_TOP, VELOCITY_PER_DRAG};
+use keymap::{stick_direction, Bindings, BindingsScreen, Input, MenuCommand};
 use render_util::*;
+use state::{GameState, LevelMenu, MenuEntry, PauseMenu, PauseOption, StateEvent};
 
-const MAX_ACC_X: f32 = 0.00005;
-const MAX_ACC_Y: f32 = 0.00005;
-const MAX_SPEED_X: f32 = 0.005;
-const MAX_SPEED_Y: f32 = 0.005;
-const ACC_STEP_X: f32 = 0.00001;
-const ACC_STEP_Y: f32 = 0.00001;
-
-const METEOR_BASE_MAX_SIZE: f32 = 0.015;
-const METEOR_BASE_MIN_SIZE: f32 = 0.007;
-const METEOR_DESTROY_RADIUS: f32 = 0.001;
-const METEOR_BASE_SPAWN_INTERVAL: f32 = 1.8;
-
-const POPULATION_START: f32 = 1200.0;
-const POP_MULTI_FACTOR: f32 = 1.0005;
-const VICTORY_PROGRESS_TICK: f32 = 0.00015;
-
-const OVERPOP_LIMIT: f32 = 10000.0;
-const OVERPOP_WARNING_NUMBER: f32 = 7000.0;
-const OVERPOP_MIN_WARNING_INTERVAL: f32 = 30.0;
 const OVERPOP_WARNING_TTL: f32 = 400.0;
+const WAVE_TEXT_TTL: f32 = 180.0;
+
+const DEFAULT_REPLAY_PATH: &str = "last-run.replay";
+const SETTINGS_PATH: &str = "settings.toml";
+const DEFAULT_EDIT_PATH: &str = "custom-level.toml";
+// The editor preview always rolls the same stars and meteor outlines, so they
+// don't jump around with every edit.
+const EDITOR_PREVIEW_SEED: u64 = 1;
+
+const BALANCE_POLL_INTERVAL: f32 = 0.5;
+const MAX_TICKS_PER_FRAME: u32 = 4;
+const TOAST_TTL: f32 = 300.0;
+
+#[derive(Default)]
+struct Options {
+    seed: Option<u64>,
+    mode: GameMode,
+    flight_model: Option<FlightModel>,
+    config_path: Option<path::PathBuf>,
+    record_path: Option<path::PathBuf>,
+    replay_path: Option<path::PathBuf>,
+    edit_path: Option<path::PathBuf>,
+    endless: bool,
+}
 
-const STARS_COUNT: usize = 200;
-const STAR_MIN_SIZE: f32 = 0.0001;
-const STAR_MAX_SIZE: f32 = 0.0005;
-
-const SHOOTING_SPEED: f32 = 0.15;
+fn parse_args() -> Result<Options, String> {
+    let mut options = Options::default();
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--seed" => {
+                let value = args.next().ok_or("--seed expects a value")?;
+                let seed = value
+                    .parse::<u64>()
+                    .map_err(|_| format!("Invalid seed: {}", value))?;
+                options.seed = Some(seed);
+            }
+            "--mode" => {
+                let value = args.next().ok_or("--mode expects a value")?;
+                options.mode = GameMode::from_name(&value)
+                    .ok_or_else(|| format!("Unknown mode: {}", value))?;
+            }
+            "--flight" => {
+                let value = args.next().ok_or("--flight expects a value")?;
+                let flight_model = FlightModel::from_name(&value)
+                    .ok_or_else(|| format!("Unknown flight model: {}", value))?;
+                options.flight_model = Some(flight_model);
+            }
+            "--config" => {
+                let value = args.next().ok_or("--config expects a path")?;
+                options.config_path = Some(path::PathBuf::from(value));
+            }
+            "--record" => {
+                let value = args.next().ok_or("--record expects a path")?;
+                options.record_path = Some(path::PathBuf::from(value));
+            }
+            "--replay" => {
+                let value = args.next().ok_or("--replay expects a path")?;
+                options.replay_path = Some(path::PathBuf::from(value));
+            }
+            "--endless" => options.endless = true,
+            "--edit" => {
+                let value = args.next().ok_or("--edit expects a path")?;
+                options.edit_path = Some(path::PathBuf::from(value));
+            }
+            _ => return Err(format!("Unknown argument: {}", arg)),
+        }
+    }
+    Ok(options)
+}
 
 fn main() -> GameResult {
+    let options = parse_args().map_err(GameError::ConfigError)?;
+    let playback = match &options.replay_path {
+        Some(replay_path) => {
+            let replay = Replay::load(replay_path).map_err(|e| {
+                GameError::ResourceLoadError(format!(
+                    "Failed to load replay {}: {}",
+                    replay_path.display(),
+                    e
+                ))
+            })?;
+            Some(ReplayPlayer::new(replay))
+        }
+        None => None,
+    };
+
     let resource_dir = if let Ok(manifest_dir) = env::var("CARGO_MANIFEST_DIR") {
         let mut path = path::PathBuf::from(manifest_dir);
         path.push("resources");
@@ -60,6 +127,36 @@ fn main() -> GameResult {
         path::PathBuf::from("./resources")
     };
 
+    let balance_path = match &options.config_path {
+        Some(config_path) => config_path.clone(),
+        None => resource_dir.join(DEFAULT_BALANCE_FILE),
+    };
+    let balance = if options.config_path.is_some() || balance_path.exists() {
+        Balance::load(&balance_path)
+            .map_err(|e| GameError::ConfigError(format!("Invalid balance config: {}", e)))?
+    } else {
+        Balance::default()
+    };
+    // A replay carries its own balance, so only live sessions follow the file.
+    let balance_watcher = if playback.is_none() {
+        Some(BalanceWatcher::new(balance_path))
+    } else {
+        None
+    };
+
+    let mut settings = Settings::load(SETTINGS_PATH).unwrap_or_else(|e| {
+        println!("Ignoring settings {}: {}", SETTINGS_PATH, e);
+        Settings::default()
+    });
+    if let Some(flight_model) = options.flight_model {
+        settings.flight_model = flight_model;
+    }
+
+    let (levels, level_errors) = load_levels(resource_dir.join(LEVELS_DIR));
+    for e in level_errors {
+        println!("Ignoring level: {}", e);
+    }
+
     // Make a Context.
     let (mut ctx, mut event_loop) = ContextBuilder::new("save_the_pink_skins", "gajop")
         .window_setup(conf::WindowSetup::default().title("Save The Pink Skins!"))
@@ -79,7 +176,22 @@ fn main() -> GameResult {
         .build()
         .expect("Failed to create create ggez context. Please report this error");
 
-    let mut my_game = SaveThePinkSkin::new(&mut ctx)?;
+    let record_path = options
+        .record_path
+        .unwrap_or_else(|| path::PathBuf::from(DEFAULT_REPLAY_PATH));
+    let launch = Launch {
+        fixed_seed: options.seed,
+        mode: options.mode,
+        settings,
+        balance,
+        balance_watcher,
+        levels,
+        record_path,
+        playback,
+        edit_path: options.edit_path,
+        endless: options.endless,
+    };
+    let mut my_game = SaveThePinkSkin::new(&mut ctx, launch)?;
 
     match event::run(&mut ctx, &mut event_loop, &mut my_game) {
         Ok(_) => println!("Exited cleanly."),
@@ -89,29 +201,68 @@ fn main() -> GameResult {
     Ok(())
 }
 
+/// What the game is launched with, gathered from the command line and files.
+struct Launch {
+    fixed_seed: Option<u64>,
+    mode: GameMode,
+    settings: Settings,
+    balance: Balance,
+    balance_watcher: Option<BalanceWatcher>,
+    levels: Vec<Level>,
+    record_path: path::PathBuf,
+    playback: Option<ReplayPlayer>,
+    // Given on the command line, the game opens straight into the editor.
+    edit_path: Option<path::PathBuf>,
+    endless: bool,
+}
+
 struct SaveThePinkSkin {
-    // spaceship: GameObject,
-    // earth: GameObject,
-    started: bool,
-    id_generator: usize,
-    objects: BTreeMap<usize, GameObject>,
-    spaceship_id: Option<usize>,
-    earth_id: Option<usize>,
+    state: GameState,
+    pause_menu: PauseMenu,
+    // When set from the command line, every restart replays the same seed.
+    fixed_seed: Option<u64>,
+    mode: GameMode,
+    settings: Settings,
+    bindings: Bindings,
+    // Open while the player is changing bindings from the title screen.
+    bindings_screen: Option<BindingsScreen>,
+    balance: Balance,
+    balance_watcher: Option<BalanceWatcher>,
+    next_balance_poll: f32,
+    // A reloaded balance waiting to be handed to the simulation on the next tick.
+    pending_balance: Option<Balance>,
+    levels: Vec<Level>,
+    level_menu: LevelMenu,
+    // The level live sessions play, or none for the endless stream.
+    level: Option<Level>,
+    // Whether the endless stream is played for a score instead of a victory.
+    endless: bool,
+    // Kept while playing a test run, so the edits are still there afterwards.
+    editor: Option<Editor>,
+    edit_path: path::PathBuf,
+    // Set while playing the level being edited, so leaving goes back to the editor.
+    testing: bool,
+    sim: Simulation,
+    // The world as it was before the last tick, so drawing can blend towards the
+    // current one between ticks.
+    previous_positions: BTreeMap<Entity, (f32, f32)>,
+    previous_headings: BTreeMap<Entity, Heading>,
+    // Every session is recorded so a finished run can be sent in and reproduced.
+    recording: Replay,
+    record_path: path::PathBuf,
+    // When set, ticks are driven by a recorded session instead of live input.
+    playback: Option<ReplayPlayer>,
     controls: Controls,
-    rng: ThreadRng,
-    next_meteor_spawn: Option<f32>,
+    // Last reported stick positions, as the axes arrive one event at a time.
+    move_stick: (f32, f32),
+    aim_stick: (f32, f32),
     game_resources: GameResources,
-    victory_result: Option<GameVictoryResult>,
-    text_population_id: Option<usize>,
-    text_spaceship_hp_id: Option<usize>,
-    text_victory_progress_id: Option<usize>,
-    population_million: f32,
-    victory_progress: f32,
-    spaceship_hp: f32,
-    next_overpop_warning: f32,
-    next_overpop_warning_enabled: bool,
-    next_shooting_time: f32,
-    stars: Vec<GameObject>,
+    mesh_cache: MeshCache<graphics::Mesh>,
+    // All the stars in one mesh, built on the first frame that draws them.
+    stars_mesh: Option<graphics::Mesh>,
+    text_population_id: Option<Entity>,
+    text_spaceship_hp_id: Option<Entity>,
+    text_victory_progress_id: Option<Entity>,
     window_width: f32,
     window_height: f32,
     draw_size: f32,
@@ -133,93 +284,17 @@ struct GameResources {
     victory_sound: audio::Source,
     earth_image: graphics::Image,
     meteor_image: graphics::Image,
+    iron_meteor_image: graphics::Image,
+    comet_image: graphics::Image,
+    explosive_meteor_image: graphics::Image,
+    shard_image: graphics::Image,
     ship_image: graphics::Image,
     clouds_image: graphics::Image,
     instructions_image: graphics::Image,
 }
 
-#[derive(Clone, Debug)]
-enum GameVictoryResult {
-    ShipDestroyed,
-    EveryoneDead,
-    OverPopulation,
-    Victory,
-}
-
-#[derive(Clone, Debug)]
-enum Shape {
-    Circle,
-    Text,
-}
-
-#[derive(Clone, Debug)]
-struct CircleData {
-    radius: f32,
-    color: graphics::Color,
-}
-
-#[derive(Clone, Debug)]
-struct TextData {
-    text: graphics::Text,
-    expiration_time: Option<i32>,
-    font_size: f32,
-    color: graphics::Color,
-}
-
-#[derive(Clone, Debug, PartialEq)]
-enum ObjType {
-    Ship,
-    Earth,
-    Meteor,
-    Projectile,
-    Clouds,
-    Asthetics,
-    UI,
-}
-
-#[derive(Clone, Debug)]
-struct GameObject {
-    id: usize,
-    transform: Transform,
-    render_coords: RenderCoords,
-
-    // FIXME: there must be a better way...
-    object_type: ObjType,
-    shape: Shape,
-    circle_data: Option<CircleData>,
-    text_data: Option<TextData>,
-    ttl: Option<f32>,
-
-    collidable: bool,
-}
-
-#[derive(Clone, Debug, Default)]
-struct Transform {
-    pos_x: f32,
-    pos_y: f32,
-    vel_x: f32,
-    vel_y: f32,
-    acc_x: f32,
-    acc_y: f32,
-}
-
-#[derive(Clone, Debug, Default)]
-struct RenderCoords {
-    pos_x: f32,
-    pos_y: f32,
-    vel_x: f32,
-    vel_y: f32,
-}
-
-#[derive(Default, Clone, Debug)]
-struct Controls {
-    left_right: Option<Direction>,
-    up_down: Option<Direction>,
-    shooting: bool,
-}
-
 impl SaveThePinkSkin {
-    pub fn new(ctx: &mut Context) -> GameResult<SaveThePinkSkin> {
+    pub fn new(ctx: &mut Context, launch: Launch) -> GameResult<SaveThePinkSkin> {
         // Load/create resources such as images here.
         let font = graphics::Font::new(ctx, "/PixelEmulator-xq08.ttf")?;
         let death_sound = audio::Source::new(ctx, "/death.wav")?;
@@ -236,253 +311,208 @@ impl SaveThePinkSkin {
         earth_image.set_wrap(graphics::WrapMode::Tile, graphics::WrapMode::Tile);
         let mut meteor_image = graphics::Image::new(ctx, "/meteor.png")?;
         meteor_image.set_wrap(graphics::WrapMode::Tile, graphics::WrapMode::Tile);
+        let mut iron_meteor_image = graphics::Image::new(ctx, "/iron-meteor.png")?;
+        iron_meteor_image.set_wrap(graphics::WrapMode::Tile, graphics::WrapMode::Tile);
+        let mut comet_image = graphics::Image::new(ctx, "/comet.png")?;
+        comet_image.set_wrap(graphics::WrapMode::Tile, graphics::WrapMode::Tile);
+        let mut explosive_meteor_image = graphics::Image::new(ctx, "/explosive-meteor.png")?;
+        explosive_meteor_image.set_wrap(graphics::WrapMode::Tile, graphics::WrapMode::Tile);
+        let mut shard_image = graphics::Image::new(ctx, "/shard.png")?;
+        shard_image.set_wrap(graphics::WrapMode::Tile, graphics::WrapMode::Tile);
         let ship_image = graphics::Image::new(ctx, "/ship.png")?;
         let mut clouds_image = graphics::Image::new(ctx, "/clouds.png")?;
         clouds_image.set_wrap(graphics::WrapMode::Tile, graphics::WrapMode::Tile);
         let instructions_image = graphics::Image::new(ctx, "/instructions.png")?;
 
-        let game = SaveThePinkSkin::init(GameResources {
-            font,
-            death_sound,
-            earth_meteor_sound,
-            earth_end_sound,
-            meteor_bounce_sound,
-            meteor_explosion_sound,
-            overpopulation_warning_sound,
-            overpopulation_end_sound,
-            ship_meteor_sound,
-            shoot_sound,
-            victory_sound,
-            earth_image,
-            meteor_image,
-            ship_image,
-            clouds_image,
-            instructions_image,
-        });
+        let game = SaveThePinkSkin::init(
+            GameResources {
+                font,
+                death_sound,
+                earth_meteor_sound,
+                earth_end_sound,
+                meteor_bounce_sound,
+                meteor_explosion_sound,
+                overpopulation_warning_sound,
+                overpopulation_end_sound,
+                ship_meteor_sound,
+                shoot_sound,
+                victory_sound,
+                earth_image,
+                meteor_image,
+                iron_meteor_image,
+                comet_image,
+                explosive_meteor_image,
+                shard_image,
+                ship_image,
+                clouds_image,
+                instructions_image,
+            },
+            launch,
+        );
 
         Ok(game)
     }
 
-    fn init(game_resources: GameResources) -> SaveThePinkSkin {
+    fn init(game_resources: GameResources, launch: Launch) -> SaveThePinkSkin {
+        let Launch {
+            fixed_seed,
+            mode,
+            settings,
+            balance,
+            balance_watcher,
+            levels,
+            record_path,
+            playback,
+            edit_path,
+            endless,
+        } = launch;
+        let (seed, session_mode, flight_model, session_balance, session_level, session_endless) =
+            match &playback {
+                Some(playback) => {
+                    let replay = playback.replay();
+                    (
+                        replay.seed,
+                        replay.mode,
+                        replay.flight_model,
+                        replay.balance.clone(),
+                        replay.level.clone(),
+                        replay.endless,
+                    )
+                }
+                None => (
+                    fixed_seed.unwrap_or_else(rand::random),
+                    mode,
+                    settings.flight_model,
+                    balance.clone(),
+                    None,
+                    endless,
+                ),
+            };
+        let (bindings, unknown) = Bindings::new(&settings.keymap);
+        for name in unknown {
+            println!("Ignoring unknown binding: {}", name);
+        }
         let mut game = SaveThePinkSkin {
-            started: false,
-            id_generator: 0,
-            objects: BTreeMap::new(),
+            state: if playback.is_some() {
+                GameState::Playing
+            } else {
+                GameState::Title
+            },
+            pause_menu: PauseMenu::default(),
+            fixed_seed,
+            mode,
+            settings,
+            bindings,
+            bindings_screen: None,
+            balance,
+            balance_watcher,
+            next_balance_poll: 0.0,
+            pending_balance: None,
+            levels,
+            level_menu: LevelMenu::default(),
+            level: None,
+            endless,
+            editor: None,
+            edit_path: edit_path
+                .clone()
+                .unwrap_or_else(|| path::PathBuf::from(DEFAULT_EDIT_PATH)),
+            testing: false,
+            sim: Simulation::new(
+                seed,
+                session_mode,
+                flight_model,
+                session_balance.clone(),
+                session_level.clone(),
+                session_endless,
+            ),
+            previous_positions: BTreeMap::new(),
+            previous_headings: BTreeMap::new(),
+            recording: Replay::new(
+                seed,
+                session_mode,
+                flight_model,
+                session_balance,
+                session_level,
+                session_endless,
+            ),
+            record_path,
+            playback,
             controls: Default::default(),
-            spaceship_id: None,
-            earth_id: None,
-            rng: rand::thread_rng(),
-            next_meteor_spawn: None,
+            move_stick: (0.0, 0.0),
+            aim_stick: (0.0, 0.0),
             game_resources,
-            victory_result: None,
+            mesh_cache: Default::default(),
+            stars_mesh: None,
             text_population_id: None,
             text_spaceship_hp_id: None,
             text_victory_progress_id: None,
-            population_million: POPULATION_START,
-            spaceship_hp: 100.0,
-            victory_progress: 0.0,
-            next_overpop_warning: 0.0,
-            next_overpop_warning_enabled: true,
-            next_shooting_time: 0.0,
-            stars: Vec::new(),
             window_width: 1000.0,
             window_height: 1000.0,
             draw_size: 1000.0,
             offset_x: 0.0,
             offset_y: 0.0,
         };
-        game.add_spaceship();
-        game.add_earth();
         game.reset_text();
-        game.add_stars();
+        if edit_path.is_some() && game.playback.is_none() {
+            game.open_editor();
+        }
 
         game
     }
 
     fn restart(&mut self) {
-        // *self = SaveThePinkSkin::init(self.game_resources);
-        self.id_generator = 0;
-        self.objects = BTreeMap::new();
+        let (seed, mode, flight_model, balance, level, endless) = match &mut self.playback {
+            Some(playback) => {
+                playback.rewind();
+                let replay = playback.replay();
+                (
+                    replay.seed,
+                    replay.mode,
+                    replay.flight_model,
+                    replay.balance.clone(),
+                    replay.level.clone(),
+                    replay.endless,
+                )
+            }
+            None => (
+                self.fixed_seed.unwrap_or_else(rand::random),
+                self.mode,
+                self.settings.flight_model,
+                self.balance.clone(),
+                self.level.clone(),
+                self.endless,
+            ),
+        };
+        self.sim = Simulation::new(
+            seed,
+            mode,
+            flight_model,
+            balance.clone(),
+            level.clone(),
+            endless,
+        );
+        self.previous_positions.clear();
+        self.previous_headings.clear();
+        self.mesh_cache.clear();
+        self.stars_mesh = None;
+        self.recording = Replay::new(seed, mode, flight_model, balance, level, endless);
+        self.pending_balance = None;
         self.controls = Default::default();
-        self.spaceship_id = None;
-        self.earth_id = None;
-        self.rng = rand::thread_rng();
-        self.next_meteor_spawn = None;
-        self.victory_result = None;
         self.text_population_id = None;
         self.text_spaceship_hp_id = None;
-        self.population_million = POPULATION_START;
-        self.spaceship_hp = 100.0;
-        self.victory_progress = 0.0;
-        self.next_overpop_warning = 0.0;
-        self.next_overpop_warning_enabled = true;
-        self.stars = Vec::new();
-        self.add_spaceship();
-        self.add_earth();
+        self.text_victory_progress_id = None;
         self.reset_text();
-        self.add_stars();
-    }
-
-    fn make_object(
-        &mut self,
-        transform: Transform,
-        object_type: ObjType,
-        shape: Shape,
-        circle_data: Option<CircleData>,
-        text_data: Option<TextData>,
-    ) -> usize {
-        self.id_generator += 1;
-        let id = self.id_generator;
-        let ttl = if object_type == ObjType::Projectile {
-            Some(50.0)
-        } else {
-            None
-        };
-        self.objects.insert(
-            id,
-            GameObject {
-                id,
-                transform,
-                render_coords: Default::default(),
-                shape,
-                object_type,
-                circle_data: circle_data,
-                text_data: text_data,
-                ttl,
-                collidable: true,
-            },
-        );
-        id
-    }
-
-    fn add_spaceship(&mut self) {
-        let id = self.make_object(
-            Transform {
-                pos_x: 0.1,
-                pos_y: 0.3,
-                vel_x: 0.0,
-                vel_y: 0.0,
-                acc_x: 0.0,
-                acc_y: 0.0,
-            },
-            ObjType::Ship,
-            Shape::Circle,
-            Some(CircleData {
-                radius: 0.02,
-                color: graphics::Color::new(0.5, 0.5, 0.7, 1.0),
-            }),
-            None,
-        );
-        self.spaceship_id = Some(id);
-    }
-
-    fn add_earth(&mut self) {
-        let id = self.make_object(
-            Transform {
-                pos_x: 0.5,
-                pos_y: 0.5,
-                vel_x: 0.0,
-                vel_y: 0.0,
-                acc_x: 0.0,
-                acc_y: 0.0,
-            },
-            ObjType::Earth,
-            Shape::Circle,
-            Some(CircleData {
-                radius: 0.1,
-                color: graphics::Color::new(0.3, 0.7, 0.3, 1.0),
-            }),
-            None,
-        );
-        let object = self.get_mut(id);
-        object.render_coords.vel_x = 0.0002;
-        object.render_coords.vel_y = 0.0001;
-        self.earth_id = Some(id);
-
-        // atmosphere
-        let id = self.make_object(
-            Transform {
-                pos_x: 0.5,
-                pos_y: 0.5,
-                vel_x: 0.0,
-                vel_y: 0.0,
-                acc_x: 0.0,
-                acc_y: 0.0,
-            },
-            ObjType::Asthetics,
-            Shape::Circle,
-            Some(CircleData {
-                radius: 0.108,
-                color: graphics::Color::new(0.0, 0.0, 0.0, 0.0),
-            }),
-            None,
-        );
-        let object = self.get_mut(id);
-        object.collidable = false;
-
-        // clouds
-        let id = self.make_object(
-            Transform {
-                pos_x: 0.5,
-                pos_y: 0.5,
-                vel_x: 0.0,
-                vel_y: 0.0,
-                acc_x: 0.0,
-                acc_y: 0.0,
-            },
-            ObjType::Clouds,
-            Shape::Circle,
-            Some(CircleData {
-                radius: 0.103,
-                color: graphics::Color::new(0.15, 0.15, 0.3, 0.3),
-            }),
-            None,
-        );
-        let object = self.get_mut(id);
-        object.render_coords.vel_x = 0.0007;
-        object.render_coords.vel_y = -0.0001;
-        object.collidable = false;
-    }
-
-    fn add_stars(&mut self) {
-        for _ in 0..STARS_COUNT {
-            let pos_x = 0.5
-                + self.rng.gen_range(0.1, 0.5) * (self.rng.gen_range(-1.0, 1.0) as f32).signum();
-            let pos_y = 0.5
-                + self.rng.gen_range(0.1, 0.5) * (self.rng.gen_range(-1.0, 1.0) as f32).signum();
-            self.stars.push(GameObject {
-                id: 0,
-                transform: Transform {
-                    pos_x: pos_x,
-                    pos_y: pos_y,
-                    vel_x: 0.0,
-                    vel_y: 0.0,
-                    acc_x: 0.0,
-                    acc_y: 0.0,
-                },
-                render_coords: Default::default(),
-                shape: Shape::Circle,
-                object_type: ObjType::Asthetics,
-                circle_data: Some(CircleData {
-                    radius: self.rng.gen_range(STAR_MIN_SIZE, STAR_MAX_SIZE),
-                    color: graphics::Color::new(0.9, 0.9, 0.9, 0.5),
-                }),
-                text_data: None,
-                ttl: None,
-                collidable: false,
-            })
-        }
     }
 
     fn reset_text(&mut self) {
         if let Some(id) = self.text_population_id {
-            self.remove_object(id);
+            self.sim.despawn(id);
         }
         if let Some(id) = self.text_spaceship_hp_id {
-            self.remove_object(id);
+            self.sim.despawn(id);
         }
         if let Some(id) = self.text_victory_progress_id {
-            self.remove_object(id);
+            self.sim.despawn(id);
         }
         self.text_population_id = None;
         self.text_spaceship_hp_id = None;
@@ -493,7 +523,7 @@ impl SaveThePinkSkin {
     }
 
     fn add_text_population(&mut self) {
-        let id = self.make_object(
+        let id = self.sim.add_label(
             Transform {
                 pos_x: 0.2,
                 pos_y: 0.0,
@@ -502,22 +532,18 @@ impl SaveThePinkSkin {
                 acc_x: 0.0,
                 acc_y: 0.0,
             },
-            ObjType::UI,
-            Shape::Text,
-            None,
-            Some(TextData {
-                text: graphics::Text::default(),
-                expiration_time: None,
+            TextLabel {
+                text: String::new(),
                 font_size: 32.0,
-                color: graphics::WHITE,
-            }),
+                color: [1.0, 1.0, 1.0, 1.0],
+            },
+            None,
         );
-        self.get_mut(id).collidable = false;
         self.text_population_id = Some(id);
     }
 
     fn add_text_spaceship_hp(&mut self) {
-        let id = self.make_object(
+        let id = self.sim.add_label(
             Transform {
                 pos_x: 0.4,
                 pos_y: 1.0 - 26.0 / self.draw_size,
@@ -526,35 +552,42 @@ impl SaveThePinkSkin {
                 acc_x: 0.0,
                 acc_y: 0.0,
             },
-            ObjType::UI,
-            Shape::Text,
-            None,
-            Some(TextData {
-                text: graphics::Text::default(),
-                expiration_time: None,
+            TextLabel {
+                text: String::new(),
                 font_size: 26.0,
-                color: graphics::WHITE,
-            }),
+                color: [1.0, 1.0, 1.0, 1.0],
+            },
+            None,
         );
-        self.get_mut(id).collidable = false;
         self.text_spaceship_hp_id = Some(id);
     }
 
     fn add_text_victory_result(&mut self) {
-        let end_text = match self.victory_result {
+        let end_text = match self.sim.victory_result {
             Some(GameVictoryResult::EveryoneDead) => "Catastrophic event.",
             Some(GameVictoryResult::OverPopulation) => "Overpopulation:\nFamine and War.",
             Some(GameVictoryResult::ShipDestroyed) => "You have died.",
+            Some(GameVictoryResult::TimeUp) => "Out of time.",
+            Some(GameVictoryResult::EarthOverrun) => "Too many impacts:\nThe Earth is lost.",
+            Some(GameVictoryResult::Victory) if self.sim.level.is_some() => "Level cleared!",
             Some(GameVictoryResult::Victory) => {
                 "Nursery finished.\nReady for space travel.\n\n\n Thanks for playing!"
             }
             None => "Well that didn't work",
         };
-        let end_text_full = match self.victory_result {
-            Some(GameVictoryResult::Victory) => format!("{}", end_text),
+        let end_text_full = match self.sim.victory_result {
+            Some(GameVictoryResult::Victory) if self.sim.level.is_none() => end_text.to_string(),
             _ => format!("{}\n{}", end_text, "R to Restart"),
         };
-        let id = self.make_object(
+        let end_text_full = if self.testing {
+            format!("{}\nEscape for editor", end_text_full)
+        } else if self.can_select_level() {
+            format!("{}\nEscape for levels", end_text_full)
+        } else {
+            end_text_full
+        };
+        let end_text_full = format!("{}\n\nSeed: {}", end_text_full, self.sim.seed);
+        self.sim.add_label(
             Transform {
                 pos_x: 0.35,
                 pos_y: 0.35,
@@ -563,21 +596,57 @@ impl SaveThePinkSkin {
                 acc_x: 0.0,
                 acc_y: 0.0,
             },
-            ObjType::UI,
-            Shape::Text,
+            TextLabel {
+                text: end_text_full,
+                font_size: 34.0,
+                color: [1.0, 1.0, 1.0, 1.0],
+            },
+            None,
+        );
+    }
+
+    /// Lists what an endless run scored for, above the end text.
+    fn add_text_score_breakdown(&mut self) {
+        let score = self.sim.score();
+        let seconds = score.seconds as u32;
+        let lines = [
+            format!(
+                "Meteors destroyed: {} (+{})",
+                score.meteors_destroyed, score.meteor_points
+            ),
+            format!(
+                "Population saved: {} (+{})",
+                population_to_string(score.population_million),
+                score.population_points
+            ),
+            format!(
+                "Time survived: {}:{:02} (+{})",
+                seconds / 60,
+                seconds % 60,
+                score.time_points
+            ),
+            format!("Score: {}", score.total()),
+        ];
+        self.sim.add_label(
+            Transform {
+                pos_x: 0.35,
+                pos_y: 0.12,
+                vel_x: 0.0,
+                vel_y: 0.0,
+                acc_x: 0.0,
+                acc_y: 0.0,
+            },
+            TextLabel {
+                text: lines.join("\n"),
+                font_size: 24.0,
+                color: [1.0, 1.0, 0.6, 1.0],
+            },
             None,
-            Some(TextData {
-                text: graphics::Text::new((end_text_full, self.game_resources.font, 34.0)),
-                expiration_time: None,
-                font_size: 26.0,
-                color: graphics::WHITE,
-            }),
         );
-        self.get_mut(id).collidable = false;
     }
 
     fn add_text_victory_progress(&mut self) {
-        let id = self.make_object(
+        let id = self.sim.add_label(
             Transform {
                 pos_x: 0.2,
                 pos_y: 0.0 + 34.0 / self.draw_size,
@@ -586,26 +655,21 @@ impl SaveThePinkSkin {
                 acc_x: 0.0,
                 acc_y: 0.0,
             },
-            ObjType::UI,
-            Shape::Text,
-            None,
-            Some(TextData {
-                text: graphics::Text::default(),
-                expiration_time: None,
+            TextLabel {
+                text: String::new(),
                 font_size: 16.0,
-                color: graphics::WHITE,
-            }),
+                color: [1.0, 1.0, 1.0, 1.0],
+            },
+            None,
         );
-        self.get_mut(id).collidable = false;
         self.text_victory_progress_id = Some(id);
     }
 
     fn add_meteor_impact_text(&mut self, pos_x: f32, pos_y: f32, damage: f32) {
-        let damage = self.population_million.min(damage);
         if damage == 0.0 {
             return;
         }
-        let id = self.make_object(
+        self.sim.add_label(
             Transform {
                 pos_x: pos_x - 0.1,
                 pos_y: pos_y - 13.0 / self.draw_size,
@@ -614,35 +678,17 @@ impl SaveThePinkSkin {
                 acc_x: 0.0,
                 acc_y: 0.0,
             },
-            ObjType::UI,
-            Shape::Text,
-            None,
-            Some(TextData {
-                text: graphics::Text::new((
-                    format!("{} dead", population_to_string(damage)),
-                    self.game_resources.font,
-                    13.0,
-                )),
-                expiration_time: None,
+            TextLabel {
+                text: format!("{} dead", population_to_string(damage)),
                 font_size: 13.0,
-                color: graphics::Color::new(1.0, 0.2, 0.2, 1.0),
-            }),
+                color: [1.0, 0.2, 0.2, 1.0],
+            },
+            Some(300.0),
         );
-        let object = self.get_mut(id);
-        object.ttl = Some(300.0);
-        self.get_mut(id).collidable = false;
     }
 
-    fn maybe_make_overpopulation_warning(&mut self, time: f32) {
-        if time < self.next_overpop_warning || !self.next_overpop_warning_enabled {
-            return;
-        }
-
-        self.next_overpop_warning_enabled = false;
-
-        let _ = self.game_resources.overpopulation_warning_sound.play();
-        self.next_overpop_warning += OVERPOP_MIN_WARNING_INTERVAL;
-        let id = self.make_object(
+    fn add_overpopulation_warning_text(&mut self) {
+        self.sim.add_label(
             Transform {
                 pos_x: 0.25,
                 pos_y: 0.3,
@@ -651,699 +697,925 @@ impl SaveThePinkSkin {
                 acc_x: 0.0,
                 acc_y: 0.0,
             },
-            ObjType::UI,
-            Shape::Text,
-            None,
-            Some(TextData {
-                text: graphics::Text::new((
-                    "Overpopulation imminent",
-                    self.game_resources.font,
-                    26.0,
-                )),
-                expiration_time: None,
+            TextLabel {
+                text: "Overpopulation imminent".to_string(),
                 font_size: 26.0,
-                color: graphics::Color::new(1.0, 0.2, 0.2, 1.0),
-            }),
+                color: [1.0, 0.2, 0.2, 1.0],
+            },
+            Some(OVERPOP_WARNING_TTL),
         );
-        let object = self.get_mut(id);
-        object.ttl = Some(OVERPOP_WARNING_TTL);
-        self.get_mut(id).collidable = false;
-    }
-
-    fn generate_meteor(&mut self) {
-        const MAX_VELOCITY: f32 = 0.001;
-        const MIN_VELOCITY: f32 = 0.0003;
-
-        let mut meteor = Transform {
-            pos_x: 0.0,
-            pos_y: 0.0,
-            vel_x: self.rng.gen_range(MIN_VELOCITY, MAX_VELOCITY),
-            vel_y: self.rng.gen_range(MIN_VELOCITY, MAX_VELOCITY),
-            acc_x: 0.0,
-            acc_y: 0.0,
-        };
+    }
 
-        let dir: Direction = rand::random();
-        let pos: f32 = self.rng.gen();
+    fn add_wave_text(&mut self, index: usize) {
+        self.sim.add_label(
+            Transform {
+                pos_x: 0.42,
+                pos_y: 0.2,
+                vel_x: 0.0,
+                vel_y: -0.00001,
+                acc_x: 0.0,
+                acc_y: 0.0,
+            },
+            TextLabel {
+                text: format!("Wave {}", index + 1),
+                font_size: 26.0,
+                color: [1.0, 1.0, 1.0, 1.0],
+            },
+            Some(WAVE_TEXT_TTL),
+        );
+    }
 
-        let radius = self.rng.gen_range(
-            METEOR_BASE_MIN_SIZE * self.progress_difficulty_factor(),
-            METEOR_BASE_MAX_SIZE * self.progress_difficulty_factor(),
+    fn add_toast(&mut self, text: String, color: [f32; 4]) {
+        self.sim.add_label(
+            Transform {
+                pos_x: 0.02,
+                pos_y: 0.85,
+                vel_x: 0.0,
+                vel_y: -0.00002,
+                acc_x: 0.0,
+                acc_y: 0.0,
+            },
+            TextLabel {
+                text,
+                font_size: 14.0,
+                color,
+            },
+            Some(TOAST_TTL),
         );
+    }
 
-        match dir {
-            Direction::Up => {
-                meteor.pos_x = pos;
-                meteor.pos_y = 0.0 - radius;
-                if self.rng.gen::<f32>() > 0.5 {
-                    meteor.vel_x *= -1.0;
-                }
+    fn poll_balance(&mut self, time: f32) {
+        if time < self.next_balance_poll {
+            return;
+        }
+        self.next_balance_poll = time + BALANCE_POLL_INTERVAL;
+
+        let reloaded = match &mut self.balance_watcher {
+            Some(balance_watcher) => balance_watcher.apply(&mut self.balance),
+            None => None,
+        };
+        match reloaded {
+            Some(Ok(changes)) => {
+                let text = if changes.is_empty() {
+                    "Balance reloaded: no changes".to_string()
+                } else {
+                    format!("Balance reloaded:\n{}", changes.join("\n"))
+                };
+                self.add_toast(text, [0.6, 1.0, 0.6, 1.0]);
+                self.pending_balance = Some(self.balance.clone());
+            }
+            Some(Err(e)) => {
+                self.add_toast(
+                    format!("Balance reload rejected:\n{}", e),
+                    [1.0, 0.4, 0.4, 1.0],
+                );
             }
-            Direction::Down => {
-                meteor.pos_x = pos;
-                meteor.pos_y = 1.0 + radius;
-                meteor.vel_y *= -1.0;
-                if self.rng.gen::<f32>() > 0.5 {
-                    meteor.vel_x *= -1.0;
+            None => {}
+        }
+    }
+
+    fn handle_events(&mut self, events: Vec<SimEvent>) {
+        for event in events {
+            match event {
+                SimEvent::Shot => {
+                    let _ = self.game_resources.shoot_sound.play();
+                }
+                SimEvent::ShipHit { .. } => {
+                    let _ = self.game_resources.ship_meteor_sound.play();
+                }
+                SimEvent::EarthHit {
+                    pos_x,
+                    pos_y,
+                    damage,
+                } => {
+                    let _ = self.game_resources.earth_meteor_sound.play();
+                    self.add_meteor_impact_text(pos_x, pos_y, damage);
+                }
+                SimEvent::MeteorExploded { .. } => {
+                    let _ = self.game_resources.meteor_explosion_sound.play();
+                }
+                SimEvent::MeteorDented { .. } => {
+                    let _ = self.game_resources.meteor_bounce_sound.play();
+                }
+                SimEvent::MeteorBounced => {
+                    let _ = self.game_resources.meteor_bounce_sound.play();
+                }
+                SimEvent::OverpopulationWarning => {
+                    let _ = self.game_resources.overpopulation_warning_sound.play();
+                    self.add_overpopulation_warning_text();
+                }
+                SimEvent::WaveStarted(index) => self.add_wave_text(index),
+                SimEvent::Finished(victory_result) => {
+                    if self.playback.is_none() {
+                        self.recording.finish(&self.sim);
+                        self.save_recording();
+                    }
+                    let _ = match victory_result {
+                        GameVictoryResult::EveryoneDead => {
+                            self.game_resources.earth_end_sound.play()
+                        }
+                        GameVictoryResult::ShipDestroyed => self.game_resources.death_sound.play(),
+                        GameVictoryResult::OverPopulation => {
+                            self.game_resources.overpopulation_end_sound.play()
+                        }
+                        GameVictoryResult::Victory => self.game_resources.victory_sound.play(),
+                        GameVictoryResult::TimeUp => self.game_resources.death_sound.play(),
+                        GameVictoryResult::EarthOverrun => {
+                            self.game_resources.earth_end_sound.play()
+                        }
+                    };
+                    self.set_state(GameState::from_result(&victory_result));
                 }
             }
-            Direction::Left => {
-                meteor.pos_x = 0.0 - radius;
-                meteor.pos_y = pos;
-                if self.rng.gen::<f32>() > 0.5 {
-                    meteor.vel_y *= -1.0;
+        }
+    }
+
+    /// Leaves the current state for `state`, running the exit hook of the one
+    /// and the entry hook of the other.
+    fn set_state(&mut self, state: GameState) {
+        if state == self.state {
+            return;
+        }
+        self.exit_state(self.state);
+        self.state = state;
+        self.enter_state(state);
+    }
+
+    fn enter_state(&mut self, state: GameState) {
+        match state {
+            GameState::Title => {}
+            GameState::LevelSelect => {}
+            GameState::Editor => {
+                self.testing = false;
+                // The world still holds whatever ran last, so show the level again.
+                if let Some(editor) = &mut self.editor {
+                    editor.changed = true;
                 }
             }
-            Direction::Right => {
-                meteor.pos_x = 1.0 + radius;
-                meteor.pos_y = pos;
-                meteor.vel_x *= -1.0;
-                if self.rng.gen::<f32>() > 0.5 {
-                    meteor.vel_y *= -1.0;
+            GameState::Playing => {}
+            GameState::Paused => self.pause_menu = PauseMenu::default(),
+            GameState::GameOver | GameState::Victory => {
+                self.add_text_victory_result();
+                if self.sim.is_endless() {
+                    self.add_text_score_breakdown();
                 }
             }
-        };
-        self.add_meteor(meteor, radius);
-    }
-
-    fn add_meteor(&mut self, transform: Transform, radius: f32) {
-        self.make_object(
-            transform,
-            ObjType::Meteor,
-            Shape::Circle,
-            Some(CircleData {
-                radius: radius,
-                color: graphics::Color::new(0.878, 0.603, 0.282, 1.0),
-            }),
-            None,
-        );
+        }
     }
 
-    fn remove_object(&mut self, id: usize) {
-        self.objects.remove(&id);
-        if self.spaceship_id == Some(id) {
-            self.spaceship_id = None;
-        }
-        if self.earth_id == Some(id) {
-            self.earth_id = None;
+    fn exit_state(&mut self, state: GameState) {
+        match state {
+            GameState::Title => {}
+            GameState::LevelSelect => {}
+            GameState::Editor => {
+                if let Some(editor) = &mut self.editor {
+                    editor.release();
+                }
+            }
+            // Keys released while paused never reach the ship, so let go of everything.
+            GameState::Playing => self.controls = Default::default(),
+            // Leaving with the controls still open mustn't lose what was rebound.
+            GameState::Paused => {
+                if self.bindings_screen.take().is_some() {
+                    self.save_settings();
+                }
+            }
+            GameState::GameOver | GameState::Victory => {}
         }
     }
 
-    fn shoot(&mut self, x: f32, y: f32) {
-        const PROJECTILE_RADIUS: f32 = 0.001;
-        const PROJECTILE_SPEED: f32 = 0.01;
-
-        if let Some(spaceship_id) = self.spaceship_id {
-            let &spaceship = self.objects.get(&spaceship_id).as_ref().unwrap();
-            let pos_x = spaceship.transform.pos_x;
-            let pos_y = spaceship.transform.pos_y;
-            let dx = x - pos_x;
-            let dy = y - pos_y;
-            let d = (dx * dx + dy * dy).sqrt();
-
-            let _ = self.game_resources.shoot_sound.play();
-            self.make_object(
-                Transform {
-                    pos_x: pos_x,
-                    pos_y: pos_y,
-                    vel_x: PROJECTILE_SPEED * dx / d,
-                    vel_y: PROJECTILE_SPEED * dy / d,
-                    acc_x: 0.0,
-                    acc_y: 0.0,
-                },
-                ObjType::Projectile,
-                Shape::Circle,
-                Some(CircleData {
-                    radius: PROJECTILE_RADIUS,
-                    color: graphics::Color::new(0.7, 0.9, 0.2, 1.0),
-                }),
-                None,
-            );
+    /// Moves to wherever `event` leads from the current state.
+    fn follow(&mut self, event: StateEvent) {
+        match self.state.after(event, self.testing) {
+            Some(GameState::Title) => self.quit_to_title(),
+            Some(GameState::Editor) => self.return_to_editor(),
+            Some(state) => self.set_state(state),
+            None => {}
         }
     }
 
-    fn get(&self, id: usize) -> &GameObject {
-        return self.objects.get(&id).unwrap();
+    /// Gives up the run. It is kept as a replay, like any unfinished run.
+    fn quit_to_title(&mut self) {
+        self.save_unfinished_recording();
+        self.restart();
+        self.set_state(GameState::Title);
     }
 
-    fn get_mut(&mut self, id: usize) -> &mut GameObject {
-        self.objects.get_mut(&id).unwrap()
+    fn restart_playing(&mut self) {
+        self.restart();
+        // Restarting from the pause menu still has to leave the paused state.
+        self.set_state(GameState::Playing);
     }
 
-    fn progress_difficulty_factor(&self) -> f32 {
-        1.0 + self.victory_progress * 1.5
+    /// The level select screen is skipped while watching a replay, which brings
+    /// its own game.
+    fn can_select_level(&self) -> bool {
+        self.playback.is_none()
     }
-}
 
-fn dist_object(first: &GameObject, second: &GameObject) -> f32 {
-    let size_dist = match (&first.shape, &second.shape) {
-        (Shape::Circle, Shape::Circle) => {
-            first.circle_data.as_ref().unwrap().radius + second.circle_data.as_ref().unwrap().radius
+    fn open_level_select(&mut self) {
+        // After clearing a level, the next one is the natural pick.
+        if self.state == GameState::Victory {
+            if let MenuEntry::Level(index) = self.level_menu.selected_entry() {
+                if index + 1 < self.levels.len() {
+                    self.level_menu.select_level(index + 1);
+                }
+            }
         }
-        _ => 0.0,
-    };
-    dist_transform(&first.transform, &second.transform) - size_dist
-}
+        self.set_state(GameState::LevelSelect);
+    }
 
-fn dist_transform(first: &Transform, second: &Transform) -> f32 {
-    let dx = first.pos_x - second.pos_x;
-    let dy = first.pos_y - second.pos_y;
-    (dx * dx + dy * dy).sqrt()
-}
+    /// Opens the editor on the level it was left with, or loads it from
+    /// `edit_path` the first time.
+    fn open_editor(&mut self) {
+        if self.editor.is_none() {
+            match Editor::open(&self.edit_path) {
+                Ok(editor) => self.editor = Some(editor),
+                Err(e) => {
+                    println!("Failed to open level: {}", e);
+                    return;
+                }
+            }
+        }
+        self.set_state(GameState::Editor);
+    }
 
-#[derive(Clone, Copy, Debug, PartialEq, Eq)]
-enum Direction {
-    Up,
-    Down,
-    Left,
-    Right,
-}
+    fn leave_editor(&mut self) {
+        // The preview isn't a game, so don't leave it behind for the title screen.
+        self.level = None;
+        self.restart();
+        self.set_state(GameState::Title);
+    }
 
-impl Distribution<Direction> for Standard {
-    fn sample<R: Rng + ?Sized>(&self, rng: &mut R) -> Direction {
-        match rng.gen_range(0, 4) {
-            0 => Direction::Up,
-            1 => Direction::Down,
-            2 => Direction::Left,
-            _ => Direction::Right,
+    /// Plays the level as it is in the editor, without saving it first.
+    fn test_level(&mut self) {
+        let editor = match &mut self.editor {
+            Some(editor) => editor,
+            None => return,
+        };
+        if let Err(e) = editor.level.validate() {
+            editor.status = format!("Can't test: {}", e);
+            return;
         }
+        self.level = Some(editor.level.clone());
+        self.restart_playing();
+        self.testing = true;
     }
-}
 
-fn from_keycode(key: KeyCode) -> Option<Direction> {
-    match key {
-        KeyCode::Up | KeyCode::W => Some(Direction::Up),
-        KeyCode::Down | KeyCode::S => Some(Direction::Down),
-        KeyCode::Left | KeyCode::A => Some(Direction::Left),
-        KeyCode::Right | KeyCode::D => Some(Direction::Right),
-        _ => None,
+    fn return_to_editor(&mut self) {
+        self.level = None;
+        self.set_state(GameState::Editor);
     }
-}
 
-struct Collision {
-    first: usize,
-    second: usize,
-}
+    /// Shows the level being edited as it is at the start, without running it.
+    fn rebuild_preview(&mut self) {
+        let level = match &self.editor {
+            Some(editor) => editor.level.clone(),
+            None => return,
+        };
+        self.sim = Simulation::new(
+            EDITOR_PREVIEW_SEED,
+            self.mode,
+            self.settings.flight_model,
+            self.balance.clone(),
+            Some(level),
+            false,
+        );
+        self.previous_positions.clear();
+        self.previous_headings.clear();
+        self.mesh_cache.clear();
+        self.stars_mesh = None;
+        self.text_population_id = None;
+        self.text_spaceship_hp_id = None;
+        self.text_victory_progress_id = None;
+    }
 
-fn find_collisions(game: &SaveThePinkSkin) -> Vec<Collision> {
-    let mut collisions = Vec::<Collision>::new();
-    let mut iter1 = game.objects.values();
-    while let Some(obj1) = iter1.next() {
-        if !obj1.collidable {
-            continue;
+    fn editor_action(&mut self, action: Action) {
+        match action {
+            Action::Quit => return self.leave_editor(),
+            Action::EditorTest => return self.test_level(),
+            _ => {}
         }
-        let iter2 = iter1.clone();
-        for obj2 in iter2 {
-            if obj1.id == obj2.id {
-                continue;
-            }
-
-            if obj2.collidable && dist_object(&obj1, &obj2) <= 0.0 {
-                collisions.push(Collision {
-                    first: obj1.id,
-                    second: obj2.id,
-                });
-            }
+        let editor = match &mut self.editor {
+            Some(editor) => editor,
+            None => return,
+        };
+        match action {
+            Action::EditorSave => editor.save(),
+            Action::EditorKind => editor.cycle_kind(),
+            Action::EditorShrink => editor.grow(false),
+            Action::EditorGrow => editor.grow(true),
+            Action::EditorEarlier => editor.shift(false),
+            Action::EditorLater => editor.shift(true),
+            Action::EditorShorterInterval => editor.change_interval(false),
+            Action::EditorLongerInterval => editor.change_interval(true),
+            Action::EditorEdges => editor.cycle_edges(),
+            Action::EditorAddWave => editor.add_wave(),
+            Action::EditorGoal => editor.cycle_goal(),
+            Action::EditorDelete => editor.delete_selected(),
+            _ => {}
         }
     }
 
-    collisions
-}
-
-struct MeteorData {
-    transform: Transform,
-    radius: f32,
-}
+    /// The first input bound to `action`, to name it in hints.
+    fn first_binding(&self, action: Action) -> &str {
+        let bound = self.settings.keymap.bindings(action);
+        bound.first().map_or("unbound", String::as_str)
+    }
 
-struct CollisionResults {
-    created: Vec<MeteorData>,
-    destroyed_ids: Vec<usize>,
-    ship_damage: f32,
-    population_damage: f32,
-}
+    /// Screen coordinates to world coordinates.
+    fn to_world(&self, x: f32, y: f32) -> (f32, f32) {
+        (
+            (x - self.offset_x) / self.draw_size,
+            (y - self.offset_y) / self.draw_size,
+        )
+    }
 
-fn gen_safe_range(rng: &mut ThreadRng, first: f32, second: f32) -> f32 {
-    if first == second {
-        first
-    } else if first > second {
-        rng.gen_range(second, first)
-    } else {
-        rng.gen_range(first, second)
+    fn to_screen(&self, x: f32, y: f32) -> na::Point2<f32> {
+        na::Point2::new(
+            x * self.draw_size + self.offset_x,
+            y * self.draw_size + self.offset_y,
+        )
     }
-}
 
-fn radius_to_earth_damage(radius: f32) -> f32 {
-    radius * radius * 100.0 * 100.0 * 13.0 * 100.0
-}
+    fn quit(&mut self, ctx: &mut Context) {
+        self.save_unfinished_recording();
+        ggez::event::quit(ctx);
+    }
 
-fn radius_to_ship_damage(radius: f32) -> f32 {
-    radius * radius * 100.0 * 100.0 * 13.0 * 2.0
-}
+    fn toggle_flight_model(&mut self) {
+        self.settings.flight_model = match self.settings.flight_model {
+            FlightModel::Classic => FlightModel::Thrust,
+            FlightModel::Thrust => FlightModel::Classic,
+        };
+        self.save_settings();
+        self.restart();
+    }
 
-fn population_to_string(population: f32) -> String {
-    if population > 400.0 {
-        format!("{:.1}B", population / 1000.0)
-    } else {
-        format!("{:.0}M", population)
+    fn save_settings(&self) {
+        if let Err(e) = self.settings.save(SETTINGS_PATH) {
+            println!("Failed to save settings: {}", e);
+        }
     }
-}
 
-fn process_collisions(game: &mut SaveThePinkSkin, collisions: &Vec<Collision>) -> CollisionResults {
-    let mut results = CollisionResults {
-        created: Vec::new(),
-        destroyed_ids: Vec::new(),
-        ship_damage: 0.0,
-        population_damage: 0.0,
-    };
-    let mut destroyed_unique = HashSet::<usize>::new();
-
-    for collision in collisions {
-        let first_type = game.get(collision.first).object_type.clone();
-        let second_type = game.get(collision.second).object_type.clone();
-        match (&first_type, &second_type) {
-            (ObjType::Ship, ObjType::Earth) | (ObjType::Earth, ObjType::Ship) => {
-                results.ship_damage = 1000.0;
-            }
-            (ObjType::Ship, ObjType::Meteor) | (ObjType::Meteor, ObjType::Ship) => {
-                let collider = if first_type == ObjType::Meteor {
-                    collision.first
-                } else {
-                    collision.second
-                };
-                results.ship_damage +=
-                    radius_to_ship_damage(game.get(collider).circle_data.as_ref().unwrap().radius);
-                destroyed_unique.insert(collider);
-                let _ = game.game_resources.ship_meteor_sound.play();
+    fn input_down(&mut self, ctx: &mut Context, input: Input) {
+        if self.bindings_screen.is_some() {
+            self.bindings_screen_input(input);
+            return;
+        }
+        if self.state == GameState::Paused {
+            if let Some(command) = MenuCommand::from_input(input) {
+                self.pause_menu_input(command);
+                return;
             }
-            (ObjType::Earth, ObjType::Meteor) | (ObjType::Meteor, ObjType::Earth) => {
-                let collider = if first_type == ObjType::Meteor {
-                    collision.first
-                } else {
-                    collision.second
-                };
-                let collider_object = game.get(collider);
-                let pos_x = collider_object.transform.pos_x;
-                let pos_y = collider_object.transform.pos_y;
-                let damage =
-                    radius_to_earth_damage(game.get(collider).circle_data.as_ref().unwrap().radius);
-                results.population_damage += damage;
-                destroyed_unique.insert(collider);
-                let _ = game.game_resources.earth_meteor_sound.play();
-                game.add_meteor_impact_text(pos_x, pos_y, damage);
+        }
+        if self.state == GameState::LevelSelect {
+            if let Some(command) = MenuCommand::from_input(input) {
+                self.level_menu_input(command);
+                return;
             }
-            (ObjType::Earth, ObjType::Projectile) => {
-                destroyed_unique.insert(collision.second);
+        }
+        let actions: Vec<Action> = self.bindings.actions(input).collect();
+        for action in actions {
+            self.action_down(ctx, action);
+        }
+    }
+
+    fn input_up(&mut self, input: Input) {
+        let actions: Vec<Action> = self.bindings.actions(input).collect();
+        for action in actions {
+            self.action_up(action);
+        }
+    }
+
+    fn action_down(&mut self, ctx: &mut Context, action: Action) {
+        match self.state {
+            GameState::Title => match action {
+                Action::Restart if self.can_select_level() => self.open_level_select(),
+                Action::Restart => self.set_state(GameState::Playing),
+                Action::Quit => self.quit(ctx),
+                // Settings and the editor are off while watching a replay.
+                Action::ToggleFlightModel if self.playback.is_none() => self.toggle_flight_model(),
+                Action::EditBindings if self.playback.is_none() => {
+                    self.bindings_screen = Some(BindingsScreen::default())
+                }
+                Action::OpenEditor if self.playback.is_none() => self.open_editor(),
+                _ => {}
+            },
+            // The menu takes its input as commands, before actions are looked up.
+            GameState::LevelSelect => {}
+            // The mouse goes to the editor directly.
+            GameState::Editor => self.editor_action(action),
+            GameState::Playing => {
+                if let Some(dir) = action_direction(action) {
+                    match dir {
+                        Direction::Up | Direction::Down => self.controls.up_down = Some(dir),
+                        Direction::Left | Direction::Right => self.controls.left_right = Some(dir),
+                    }
+                }
+                match action {
+                    Action::Shoot => self.controls.shooting = true,
+                    Action::Quit if self.testing => self.return_to_editor(),
+                    // Quitting mid-run goes through the pause menu, so a stray key
+                    // press can't throw the run away.
+                    Action::Pause | Action::Quit => self.follow(StateEvent::TogglePause),
+                    _ => {}
+                }
             }
-            (ObjType::Projectile, ObjType::Earth) => {
-                destroyed_unique.insert(collision.first);
+            GameState::Paused => {
+                if action == Action::Pause || action == Action::Quit {
+                    self.follow(StateEvent::TogglePause);
+                }
             }
-            (ObjType::Meteor, ObjType::Projectile) | (ObjType::Projectile, ObjType::Meteor) => {
-                let collider = if first_type == ObjType::Meteor {
-                    collision.first
-                } else {
-                    collision.second
-                };
+            GameState::GameOver | GameState::Victory => match action {
+                Action::Restart => self.restart_playing(),
+                Action::Quit if self.testing => self.return_to_editor(),
+                Action::Quit if self.can_select_level() => self.open_level_select(),
+                Action::Quit => self.quit(ctx),
+                _ => {}
+            },
+        }
+    }
 
-                let meteor = game.objects.get(&collider).unwrap();
-                let transform = &meteor.transform;
-                let radius_ratio: f32 = game.rng.gen_range(0.2, 0.5);
-                let radius = meteor.circle_data.as_ref().unwrap().radius * radius_ratio;
-                let vel_x = gen_safe_range(
-                    &mut game.rng,
-                    -transform.vel_x,
-                    -transform.vel_x / radius_ratio,
-                );
-                let vel_y = gen_safe_range(
-                    &mut game.rng,
-                    -transform.vel_y,
-                    -transform.vel_y / radius_ratio,
-                );
-                const MAX_GENERATED_VELOCITY: f32 = 0.001;
-                let meteor = MeteorData {
-                    transform: Transform {
-                        pos_x: transform.pos_x,
-                        pos_y: transform.pos_y,
-                        vel_x: vel_x.abs().min(MAX_GENERATED_VELOCITY) * vel_y.signum(),
-                        vel_y: vel_y.abs().min(MAX_GENERATED_VELOCITY) * vel_x.signum(),
-                        acc_x: 0.0,
-                        acc_y: 0.0,
-                    },
-                    radius: radius,
-                };
-                if meteor.radius > METEOR_DESTROY_RADIUS
-                    && meteor.transform.pos_x.abs() > 0.02
-                    && meteor.transform.pos_x.abs() < 0.98
-                    && meteor.transform.pos_y.abs() > 0.02
-                    && meteor.transform.pos_y.abs() < 0.98
-                {
-                    results.created.push(meteor);
+    fn action_up(&mut self, action: Action) {
+        if let Some(dir) = action_direction(action) {
+            match dir {
+                Direction::Up | Direction::Down => {
+                    if self.controls.up_down == Some(dir) {
+                        self.controls.up_down = None;
+                    }
+                }
+                Direction::Left | Direction::Right => {
+                    if self.controls.left_right == Some(dir) {
+                        self.controls.left_right = None
+                    }
                 }
-                let _ = game.game_resources.meteor_explosion_sound.play();
-
-                destroyed_unique.insert(collision.first);
-                destroyed_unique.insert(collision.second);
             }
-            (ObjType::Meteor, ObjType::Meteor) => {
-                let m1 = game.objects.get(&collision.first).unwrap();
-                let m2 = game.objects.get(&collision.second).unwrap();
-                let t1 = &m1.transform;
-                let t2 = &m2.transform;
-                let r1 = m1.circle_data.as_ref().unwrap().radius;
-                let r2 = m2.circle_data.as_ref().unwrap().radius;
-
-                let dx = t1.pos_x - t2.pos_x;
-                let dy = t1.pos_y - t2.pos_y;
-                let radius_ratio = r1 / (r1 + r2);
-
-                const MAX_GENERATED_VELOCITY: f32 = 0.001;
-
-                let vel_x1 = gen_safe_range(&mut game.rng, -t1.vel_x, -t1.vel_x / radius_ratio);
-                let vel_y1 = gen_safe_range(&mut game.rng, -t1.vel_y, -t1.vel_y / radius_ratio);
-                let meteor = MeteorData {
-                    transform: Transform {
-                        // pos_x: gen_safe_range(&mut game.rng, t1.pos_x, t1.pos_x + dx),
-                        // pos_y: gen_safe_range(&mut game.rng, t1.pos_y, t1.pos_y + dy),
-                        pos_x: t1.pos_x,
-                        pos_y: t1.pos_y,
-                        vel_x: vel_x1.abs().min(MAX_GENERATED_VELOCITY) * vel_y1.signum(),
-                        vel_y: vel_y1.abs().min(MAX_GENERATED_VELOCITY) * vel_x1.signum(),
-                        acc_x: 0.0,
-                        acc_y: 0.0,
-                    },
-                    radius: r1 * 0.7,
-                };
-                if meteor.radius > METEOR_DESTROY_RADIUS
-                    && meteor.transform.pos_x.abs() > 0.01
-                    && meteor.transform.pos_x.abs() < 0.99
-                    && meteor.transform.pos_y.abs() > 0.01
-                    && meteor.transform.pos_y.abs() < 0.99
-                {
-                    results.created.push(meteor);
+        }
+        if action == Action::Shoot {
+            self.controls.shooting = false;
+        }
+    }
+
+    fn pause_menu_input(&mut self, command: MenuCommand) {
+        match command {
+            MenuCommand::Previous => self.pause_menu.select_previous(),
+            MenuCommand::Next => self.pause_menu.select_next(),
+            MenuCommand::Confirm => {
+                let option = self.pause_menu.selected_option();
+                match option {
+                    PauseOption::Restart => self.restart(),
+                    PauseOption::Controls => self.bindings_screen = Some(BindingsScreen::default()),
+                    PauseOption::Resume | PauseOption::Quit => {}
                 }
+                self.follow(StateEvent::Picked(option));
+            }
+            MenuCommand::Back => self.follow(StateEvent::TogglePause),
+            MenuCommand::Clear => {}
+        }
+    }
 
-                let vel2_x = gen_safe_range(&mut game.rng, -t1.vel_x, -t2.vel_x / radius_ratio);
-                let vel2_y = gen_safe_range(&mut game.rng, -t1.vel_y, -t2.vel_y / radius_ratio);
-                let meteor = MeteorData {
-                    transform: Transform {
-                        // pos_x: gen_safe_range(&mut game.rng, t2.pos_x, t2.pos_x - dx),
-                        // pos_y: gen_safe_range(&mut game.rng, t2.pos_y, t2.pos_y - dy),
-                        // vel_x: gen_safe_range(&mut game.rng, -t2.vel_x, -t2.vel_x * radius_ratio),
-                        // vel_y: gen_safe_range(&mut game.rng, -t2.vel_y, -t2.vel_y * radius_ratio),
-                        pos_x: t2.pos_x,
-                        pos_y: t2.pos_y,
-                        vel_x: vel2_x.abs().min(MAX_GENERATED_VELOCITY) * vel2_y.signum(),
-                        vel_y: vel2_y.abs().min(MAX_GENERATED_VELOCITY) * vel2_x.signum(),
-                        acc_x: 0.0,
-                        acc_y: 0.0,
-                    },
-                    radius: r2 * 0.7,
+    fn level_menu_input(&mut self, command: MenuCommand) {
+        let level_count = self.levels.len();
+        match command {
+            MenuCommand::Previous => self.level_menu.select_previous(level_count),
+            MenuCommand::Next => self.level_menu.select_next(level_count),
+            MenuCommand::Confirm => {
+                let (level, endless) = match self.level_menu.selected_entry() {
+                    MenuEntry::Classic => (None, false),
+                    MenuEntry::Endless => (None, true),
+                    MenuEntry::Level(index) => (Some(self.levels[index].clone()), false),
                 };
-                results.created.push(meteor);
-
-                // const MIN_SPAWN: u32 = 1;
-                // const MAX_SPAWN: u32 = 3;
-                // let spawn_count: usize = game.rng.gen_range(MIN_SPAWN, MAX_SPAWN) as usize;
-
-                // let total_radius: f32 = (r1 + r2) * 0.3;
-                // let mut radius_weights: Vec<f32> = Vec::new();
-                // for _ in 0..spawn_count {
-                //     radius_weights.push(game.rng.gen());
-                // }
-                // let weight_factor: f32 = total_radius / radius_weights.iter().sum::<f32>();
-
-                // for i in 0..spawn_count {
-                //     let meteor = MeteorData {
-                //         transform: Transform {
-                //             pos_x: gen_safe_range(&mut game.rng, t1.pos_x, t2.pos_x),
-                //             pos_y: gen_safe_range(&mut game.rng, t1.pos_y, t2.pos_y),
-                //             vel_x: gen_safe_range(&mut game.rng, -t1.vel_x, -t2.vel_x),
-                //             vel_y: gen_safe_range(&mut game.rng, -t1.vel_y, -t2.vel_y),
-                //             acc_x: 0.0,
-                //             acc_y: 0.0,
-                //         },
-                //         radius: radius_weights[i] * weight_factor,
-                //     };
-                //     const MIN_RADIUS: f32 = 0.007;
-                //     if meteor.radius > MIN_RADIUS {
-                //         results.created.push(meteor);
-                //     }
-                // }
-                let _ = game.game_resources.meteor_bounce_sound.play();
-                destroyed_unique.insert(collision.first);
-                destroyed_unique.insert(collision.second);
+                self.level = level;
+                self.endless = endless;
+                self.restart_playing();
             }
-            _ => {}
+            MenuCommand::Back => self.set_state(GameState::Title),
+            MenuCommand::Clear => {}
+        }
+    }
+
+    /// Navigation on the bindings screen is fixed, so a broken keymap can always
+    /// be repaired: up and down pick an action, enter waits for the input to bind
+    /// to it, backspace clears it and escape saves and leaves.
+    fn bindings_screen_input(&mut self, input: Input) {
+        let screen = match &mut self.bindings_screen {
+            Some(screen) => screen,
+            None => return,
         };
+        let action = screen.selected_action();
+        if screen.capturing {
+            if input.is_bindable() {
+                self.settings.keymap.bind(action, &input.name());
+                self.bindings = Bindings::new(&self.settings.keymap).0;
+                screen.capturing = false;
+            }
+            return;
+        }
+        match MenuCommand::from_input(input) {
+            Some(MenuCommand::Previous) => screen.select_previous(),
+            Some(MenuCommand::Next) => screen.select_next(),
+            Some(MenuCommand::Confirm) => screen.capturing = true,
+            Some(MenuCommand::Clear) => {
+                self.settings.keymap.bindings_mut(action).clear();
+                self.bindings = Bindings::new(&self.settings.keymap).0;
+            }
+            Some(MenuCommand::Back) => {
+                self.bindings_screen = None;
+                self.save_settings();
+            }
+            None => {}
+        }
     }
-    for destroyed in destroyed_unique {
-        results.destroyed_ids.push(destroyed);
+
+    fn save_recording(&self) {
+        if let Err(e) = self.recording.save(&self.record_path) {
+            println!(
+                "Failed to save replay {}: {}",
+                self.record_path.display(),
+                e
+            );
+        }
     }
 
-    results
-}
+    fn save_unfinished_recording(&self) {
+        // Keep unfinished runs too; they are often the interesting ones.
+        if self.playback.is_none()
+            && self.recording.outcome.is_none()
+            && !self.recording.inputs.is_empty()
+        {
+            self.save_recording();
+        }
+    }
 
-fn cleanup_destroyed(game: &mut SaveThePinkSkin, destroyed_ids: &Vec<usize>) {
-    if game.objects.is_empty() {
-        return;
+    fn set_text(&mut self, id: Option<Entity>, text: String) {
+        if let Some(id) = id {
+            if let Some(label) = self.sim.world.labels.get_mut(&id) {
+                label.text = text;
+            }
+        }
     }
-    for id in destroyed_ids {
-        game.remove_object(*id);
+
+    /// Where to draw an entity on the screen, `alpha` of the way from where it was
+    /// before the last tick to where it is now. Anything spawned by the last tick
+    /// is drawn where it is.
+    fn draw_position(&self, entity: Entity, alpha: f32) -> na::Point2<f32> {
+        let transform = &self.sim.world.transforms[&entity];
+        let (x, y) = match self.previous_positions.get(&entity) {
+            Some(&(previous_x, previous_y)) => (
+                interpolate_axis(previous_x, transform.pos_x, transform.vel_x, alpha),
+                interpolate_axis(previous_y, transform.pos_y, transform.vel_y, alpha),
+            ),
+            None => (transform.pos_x, transform.pos_y),
+        };
+        na::Point2::new(
+            x * self.draw_size + self.offset_x,
+            y * self.draw_size + self.offset_y,
+        )
     }
-}
 
-fn add_new(game: &mut SaveThePinkSkin, created: Vec<MeteorData>) {
-    for meteor in created {
-        game.add_meteor(meteor.transform, meteor.radius);
+    /// The stars batched into one mesh, or nothing when there are none.
+    fn build_stars_mesh(&self, ctx: &mut Context) -> GameResult<Option<graphics::Mesh>> {
+        let world = &self.sim.world;
+        let stars: Vec<_> = world
+            .circles
+            .iter()
+            .filter(|(entity, circle)| is_star(world, **entity, circle))
+            .map(|(entity, circle)| {
+                let transform = &world.transforms[entity];
+                (
+                    na::Point2::new(
+                        transform.pos_x * self.draw_size + self.offset_x,
+                        transform.pos_y * self.draw_size + self.offset_y,
+                    ),
+                    circle.radius * self.draw_size,
+                    graphics::Color::from(circle.color),
+                )
+            })
+            .collect();
+        if stars.is_empty() {
+            return Ok(None);
+        }
+        build_circle_batch(ctx, stars).map(Some)
     }
-}
 
-fn object_type_image<'a>(
-    game: &'a SaveThePinkSkin,
-    obj_type: &ObjType,
-) -> Option<&'a graphics::Image> {
-    match obj_type {
-        ObjType::Earth => Some(&game.game_resources.earth_image),
-        ObjType::Meteor => Some(&game.game_resources.meteor_image),
-        ObjType::Ship => Some(&game.game_resources.ship_image),
-        ObjType::Clouds => Some(&game.game_resources.clouds_image),
-        _ => None,
+    /// Draws text at a position given in world coordinates.
+    fn draw_screen_text(
+        &self,
+        ctx: &mut Context,
+        text: &str,
+        x: f32,
+        y: f32,
+        font_size: f32,
+    ) -> GameResult<()> {
+        let text = graphics::Text::new((text, self.game_resources.font, font_size));
+        graphics::draw(
+            ctx,
+            &text,
+            (na::Point2::new(
+                self.offset_x + x * self.draw_size,
+                self.offset_y + y * self.draw_size,
+            ),),
+        )
     }
-}
 
-fn get_decay_size_factor(radius: f32) -> f32 {
-    let relative_size = (radius - METEOR_DESTROY_RADIUS) / (0.02 - METEOR_DESTROY_RADIUS);
-    (0.3 - relative_size).max(0.0)
-}
+    fn draw_pause_menu(&self, ctx: &mut Context) -> GameResult<()> {
+        let shade = graphics::Mesh::new_rectangle(
+            ctx,
+            graphics::DrawMode::fill(),
+            graphics::Rect::new(self.offset_x, self.offset_y, self.draw_size, self.draw_size),
+            graphics::Color::new(0.0, 0.0, 0.0, 0.6),
+        )?;
+        graphics::draw(ctx, &shade, graphics::DrawParam::new())?;
+
+        self.draw_screen_text(ctx, "Paused", 0.4, 0.3, 34.0)?;
+        for (index, option) in PauseOption::ALL.iter().enumerate() {
+            let marker = if index == self.pause_menu.selected {
+                ">"
+            } else {
+                " "
+            };
+            let line = format!("{} {}", marker, option.label());
+            self.draw_screen_text(ctx, &line, 0.4, 0.4 + 0.06 * index as f32, 24.0)?;
+        }
+        Ok(())
+    }
 
-impl EventHandler for SaveThePinkSkin {
-    fn update(&mut self, ctx: &mut Context) -> GameResult<()> {
-        const TARGET_FPS: u32 = 60;
-        if !self.started {
-            while ggez::timer::check_update_time(ctx, TARGET_FPS) {}
-            return Ok(());
+    fn draw_bindings_screen(&self, ctx: &mut Context, screen: &BindingsScreen) -> GameResult<()> {
+        self.draw_screen_text(ctx, "Controls", 0.05, 0.05, 32.0)?;
+        for (index, &action) in Action::ALL.iter().enumerate() {
+            let marker = if index == screen.selected { ">" } else { " " };
+            let bound = if index == screen.selected && screen.capturing {
+                "press a key or button...".to_string()
+            } else {
+                self.settings.keymap.bindings(action).join(", ")
+            };
+            let line = format!("{} {}: {}", marker, action.label(), bound);
+            self.draw_screen_text(ctx, &line, 0.05, 0.12 + 0.031 * index as f32, 16.0)?;
         }
+        self.draw_screen_text(
+            ctx,
+            "Up/Down: select  Enter: add  Backspace: clear  Escape: back",
+            0.05,
+            0.9,
+            16.0,
+        )
+    }
 
-        let time: f32 = ggez::timer::time_since_start(&ctx).as_millis() as f32 / 1000.0;
+    fn draw_level_select(&self, ctx: &mut Context) -> GameResult<()> {
+        self.draw_screen_text(ctx, "Select level", 0.05, 0.05, 32.0)?;
+        let entry_count = LevelMenu::entry_count(self.levels.len());
+        for index in 0..entry_count {
+            let name = match LevelMenu::entry(index) {
+                MenuEntry::Classic => "Classic",
+                MenuEntry::Endless => "Endless",
+                MenuEntry::Level(level) => self.levels[level].name.as_str(),
+            };
+            let marker = if index == self.level_menu.selected {
+                ">"
+            } else {
+                " "
+            };
+            let line = format!("{} {}", marker, name);
+            self.draw_screen_text(ctx, &line, 0.05, 0.15 + 0.06 * index as f32, 24.0)?;
+        }
+        let description = match self.level_menu.selected_entry() {
+            MenuEntry::Classic => "Meteors without end, coming faster as the space age nears.",
+            MenuEntry::Endless => {
+                "No space age to reach. Hold out as long as you can for a high score."
+            }
+            MenuEntry::Level(index) => self.levels[index].description.as_str(),
+        };
+        self.draw_screen_text(ctx, description, 0.05, 0.8, 18.0)?;
+        self.draw_screen_text(
+            ctx,
+            "Up/Down: select  Enter: play  Escape: back",
+            0.05,
+            0.9,
+            16.0,
+        )
+    }
 
-        let meteor_spawn_interval = METEOR_BASE_SPAWN_INTERVAL / self.progress_difficulty_factor();
-        if let Some(next_meteor_spawn) = self.next_meteor_spawn {
-            while time > self.next_meteor_spawn.unwrap() {
-                self.generate_meteor();
-                self.next_meteor_spawn =
-                    Some(self.next_meteor_spawn.unwrap() + meteor_spawn_interval);
+    /// Marks what the world preview can't show on its own: meteor velocities, the
+    /// selection and the wave timeline, with the keys and the level's state.
+    fn draw_editor(&self, ctx: &mut Context, editor: &Editor) -> GameResult<()> {
+        let velocity_color = graphics::Color::new(1.0, 0.8, 0.2, 0.8);
+        for meteor in &editor.level.meteors {
+            if meteor.vel_x == 0.0 && meteor.vel_y == 0.0 {
+                continue;
             }
-        } else {
-            self.next_meteor_spawn = Some(time + meteor_spawn_interval);
+            let points = [
+                self.to_screen(meteor.x, meteor.y),
+                self.to_screen(
+                    meteor.x + meteor.vel_x / VELOCITY_PER_DRAG,
+                    meteor.y + meteor.vel_y / VELOCITY_PER_DRAG,
+                ),
+            ];
+            let line = graphics::Mesh::new_line(ctx, &points, 2.0, velocity_color)?;
+            graphics::draw(ctx, &line, graphics::DrawParam::new())?;
+        }
+        if let Some((position, radius)) = editor.selected_circle() {
+            let ring = graphics::Mesh::new_circle(
+                ctx,
+                graphics::DrawMode::stroke(2.0),
+                self.to_screen(position.x, position.y),
+                radius * self.draw_size + 4.0,
+                0.5,
+                graphics::Color::new(0.3, 1.0, 0.3, 1.0),
+            )?;
+            graphics::draw(ctx, &ring, graphics::DrawParam::new())?;
         }
 
-        while ggez::timer::check_update_time(ctx, TARGET_FPS) {
-            if let Some(spaceship_id) = self.spaceship_id {
-                let controls = self.controls.clone();
-                let spaceship = self.get_mut(spaceship_id);
-                let spaceship_tr = &mut spaceship.transform;
-                match controls.left_right {
-                    Some(Direction::Left) => spaceship_tr.acc_x -= ACC_STEP_X,
-                    Some(Direction::Right) => spaceship_tr.acc_x += ACC_STEP_X,
-                    _ => {
-                        spaceship_tr.acc_x =
-                            spaceship_tr.acc_x.signum() * (spaceship_tr.acc_x.abs() - ACC_STEP_X)
-                    }
-                };
-
-                match controls.up_down {
-                    Some(Direction::Up) => spaceship_tr.acc_y -= ACC_STEP_Y,
-                    Some(Direction::Down) => spaceship_tr.acc_y += ACC_STEP_Y,
-                    _ => {
-                        spaceship_tr.acc_y =
-                            spaceship_tr.acc_y.signum() * (spaceship_tr.acc_y.abs() - ACC_STEP_Y)
-                    }
-                };
-
-                spaceship_tr.acc_x = na::clamp(spaceship_tr.acc_x, -MAX_ACC_X, MAX_ACC_X);
-                spaceship_tr.acc_y = na::clamp(spaceship_tr.acc_y, -MAX_ACC_Y, MAX_ACC_Y);
-                spaceship_tr.vel_x = na::clamp(spaceship_tr.vel_x, -MAX_SPEED_X, MAX_SPEED_X);
-                spaceship_tr.vel_y = na::clamp(spaceship_tr.vel_y, -MAX_SPEED_Y, MAX_SPEED_Y);
+        let mut timeline = graphics::MeshBuilder::new();
+        timeline.rectangle(
+            graphics::DrawMode::fill(),
+            graphics::Rect::new(
+                self.offset_x,
+                self.offset_y + TIMELINE_TOP * self.draw_size,
+                self.draw_size,
+                (1.0 - TIMELINE_TOP) * self.draw_size,
+            ),
+            graphics::Color::new(0.1, 0.1, 0.2, 0.8),
+        );
+        let bar_height = (1.0 - TIMELINE_TOP) * self.draw_size / 2.0;
+        for (index, wave) in editor.level.waves.iter().enumerate() {
+            let (left, right) = editor.wave_bounds(wave);
+            let color = if editor.selection == Some(Selection::Wave(index)) {
+                graphics::Color::new(0.3, 1.0, 0.3, 0.9)
+            } else {
+                graphics::Color::new(0.8, 0.4, 0.2, 0.9)
+            };
+            let top = self.to_screen(left, TIMELINE_TOP);
+            timeline.rectangle(
+                graphics::DrawMode::fill(),
+                graphics::Rect::new(
+                    top.x,
+                    top.y + bar_height / 2.0,
+                    (right - left) * self.draw_size,
+                    bar_height,
+                ),
+                color,
+            );
+        }
+        let cursor_x = editor.timeline_x(editor.cursor);
+        timeline.line(
+            &[
+                self.to_screen(cursor_x, TIMELINE_TOP),
+                self.to_screen(cursor_x, 1.0),
+            ],
+            2.0,
+            graphics::WHITE,
+        )?;
+        let timeline = timeline.build(ctx)?;
+        graphics::draw(ctx, &timeline, graphics::DrawParam::new())?;
+        let end_text = format!("{:.0}s", editor.timeline_seconds());
+        self.draw_screen_text(ctx, &end_text, 0.93, TIMELINE_TOP - 0.03, 14.0)?;
+
+        let key = |action| self.first_binding(action);
+        let help = [
+            "Click: place/pick  Drag: move  Right drag: aim".to_string(),
+            format!(
+                "{}: new wave  {}: test  {}: save  {}: back",
+                key(Action::EditorAddWave),
+                key(Action::EditorTest),
+                key(Action::EditorSave),
+                key(Action::Quit)
+            ),
+            format!(
+                "{}: kind  {} {}: size/count  {} {}: start  {} {}: interval",
+                key(Action::EditorKind),
+                key(Action::EditorShrink),
+                key(Action::EditorGrow),
+                key(Action::EditorEarlier),
+                key(Action::EditorLater),
+                key(Action::EditorShorterInterval),
+                key(Action::EditorLongerInterval)
+            ),
+            format!(
+                "{}: edges  {}: goal  {}: remove",
+                key(Action::EditorEdges),
+                key(Action::EditorGoal),
+                key(Action::EditorDelete)
+            ),
+        ];
+        for (index, line) in help.iter().enumerate() {
+            self.draw_screen_text(ctx, line, 0.01, 0.01 + 0.025 * index as f32, 12.0)?;
+        }
+        let summary = format!("{}  {}", editor.level.name, editor.describe_goal());
+        self.draw_screen_text(ctx, &summary, 0.01, 0.12, 16.0)?;
+        self.draw_screen_text(ctx, &editor.describe_selection(), 0.01, 0.8, 16.0)?;
+        self.draw_screen_text(ctx, &editor.status, 0.01, 0.84, 16.0)
+    }
 
-                if controls.shooting && self.next_shooting_time < time {
-                    let mouse_pos = ggez::input::mouse::position(ctx);
-                    self.shoot(
-                        (mouse_pos.x - self.offset_x) / self.draw_size,
-                        (mouse_pos.y - self.offset_y) / self.draw_size,
-                    );
-                    self.next_shooting_time = time + SHOOTING_SPEED;
-                }
-            }
+    fn update_text(&mut self) {
+        let text_str = format!(
+            "Population: {}",
+            population_to_string(self.sim.population_million)
+        );
+        let text_str = if self.sim.population_million > self.sim.balance.overpop_warning_number {
+            format!("{} (!)", text_str)
+        } else {
+            text_str
+        };
+        self.set_text(self.text_population_id, text_str);
+
+        let text_str = format!("HP: {:.0}", self.sim.spaceship_hp);
+        self.set_text(self.text_spaceship_hp_id, text_str);
+
+        let text_str = match &self.sim.level {
+            Some(level) => format!("{}: {:.0}%", level.name, 100.0 * self.sim.victory_progress),
+            None if self.sim.is_endless() => format!("Score: {}", self.sim.score().total()),
+            None => format!(
+                "Space Age Progress: {:.0}%",
+                100.0 * self.sim.victory_progress
+            ),
+        };
+        self.set_text(self.text_victory_progress_id, text_str);
+    }
+}
 
-            for object in &mut self.objects.values_mut() {
-                let transform = &mut object.transform;
-                transform.vel_x += transform.acc_x;
-                transform.vel_y += transform.acc_y;
+fn action_direction(action: Action) -> Option<Direction> {
+    match action {
+        Action::MoveUp => Some(Direction::Up),
+        Action::MoveDown => Some(Direction::Down),
+        Action::MoveLeft => Some(Direction::Left),
+        Action::MoveRight => Some(Direction::Right),
+        _ => None,
+    }
+}
 
-                transform.pos_x += transform.vel_x;
-                transform.pos_y += transform.vel_y;
+fn texture_image(resources: &GameResources, texture: Texture) -> &graphics::Image {
+    match texture {
+        Texture::Earth => &resources.earth_image,
+        Texture::Meteor => &resources.meteor_image,
+        Texture::IronMeteor => &resources.iron_meteor_image,
+        Texture::Comet => &resources.comet_image,
+        Texture::ExplosiveMeteor => &resources.explosive_meteor_image,
+        Texture::Shard => &resources.shard_image,
+        Texture::Ship => &resources.ship_image,
+        Texture::Clouds => &resources.clouds_image,
+    }
+}
 
-                let size_dist = match object.shape {
-                    Shape::Circle => object.circle_data.as_ref().unwrap().radius,
-                    _ => 0.0,
-                };
+/// Stars are the plain circles that take no part in the game and never expire,
+/// so they never move.
+fn is_star(world: &World, entity: Entity, circle: &Circle) -> bool {
+    circle.texture.is_none()
+        && !world.colliders.contains_key(&entity)
+        && !world.lifetimes.contains_key(&entity)
+}
 
-                if transform.pos_x > 1.0 + size_dist * 1.1 {
-                    transform.pos_x = -size_dist;
-                } else if transform.pos_x < -size_dist * 1.1 {
-                    transform.pos_x = 1.0 + size_dist;
-                }
-                if transform.pos_y > 1.0 + size_dist * 1.1 {
-                    transform.pos_y = -size_dist;
-                } else if transform.pos_y < -size_dist * 1.1 {
-                    transform.pos_y = 1.0 + size_dist;
+impl EventHandler for SaveThePinkSkin {
+    fn update(&mut self, ctx: &mut Context) -> GameResult<()> {
+        let time: f32 = ggez::timer::time_since_start(ctx).as_millis() as f32 / 1000.0;
+        self.poll_balance(time);
+
+        if self.state == GameState::Editor {
+            if let Some(editor) = &mut self.editor {
+                if editor.changed {
+                    editor.changed = false;
+                    self.rebuild_preview();
                 }
             }
+        }
 
-            for object in &mut self.objects.values_mut() {
-                let render_coords = &mut object.render_coords;
-                render_coords.pos_x += render_coords.vel_x;
-                render_coords.pos_y += render_coords.vel_y;
-            }
-
-            let mut to_destroy = vec![];
-            for object in &mut self.objects.values_mut() {
-                if let Some(ttl) = object.ttl {
-                    object.ttl = Some(ttl - 1.0);
-                    if object.ttl <= Some(0.0) {
-                        to_destroy.push(object.id);
-                    }
-                }
-            }
-            for destroy in to_destroy {
-                self.remove_object(destroy);
-            }
+        if !self.state.is_running() {
+            while ggez::timer::check_update_time(ctx, TICKS_PER_SECOND) {}
+            return Ok(());
+        }
 
-            let mut to_destroy = vec![];
-            for object in &mut self.objects.values_mut() {
-                if object.collidable && object.object_type == ObjType::Meteor {
-                    if let Some(circle_data) = &mut object.circle_data {
-                        let decay_size_factor = get_decay_size_factor(circle_data.radius);
-                        let decay_rate = 0.0005 + decay_size_factor * 0.03;
-                        circle_data.radius *= 1.0 - decay_rate;
-                        if circle_data.radius < METEOR_DESTROY_RADIUS {
-                            to_destroy.push(object.id);
-                        }
-                    }
-                }
-            }
-            for destroy in to_destroy {
-                self.remove_object(destroy);
+        let mut ticks_this_frame = 0;
+        while ggez::timer::check_update_time(ctx, TICKS_PER_SECOND) {
+            // After a hitch, drop the ticks that are too far behind instead of
+            // running them all at once.
+            if ticks_this_frame == MAX_TICKS_PER_FRAME {
+                continue;
             }
+            ticks_this_frame += 1;
 
-            let collisions = find_collisions(self);
-            let results = process_collisions(self, &collisions);
-            self.spaceship_hp -= results.ship_damage;
-            self.population_million -= results.population_damage;
-            cleanup_destroyed(self, &results.destroyed_ids);
-            add_new(self, results.created);
-
-            self.population_million *= POP_MULTI_FACTOR;
-            self.victory_progress += VICTORY_PROGRESS_TICK;
-
-            match self.victory_result {
+            let input = match &mut self.playback {
+                Some(playback) => match playback.next_input() {
+                    Some(input) => input.clone(),
+                    None => continue,
+                },
                 None => {
-                    let mut finished = true;
-                    if self.population_million <= 0.0 {
-                        self.victory_result = Some(GameVictoryResult::EveryoneDead);
-                        let _ = self.game_resources.earth_end_sound.play();
-                    } else if self.spaceship_hp <= 0.0 {
-                        self.victory_result = Some(GameVictoryResult::ShipDestroyed);
-                        let _ = self.game_resources.death_sound.play();
-                        if let Some(spaceship_id) = self.spaceship_id {
-                            self.remove_object(spaceship_id);
-                        }
-                    } else if self.population_million >= OVERPOP_LIMIT {
-                        self.victory_result = Some(GameVictoryResult::OverPopulation);
-                        let _ = self.game_resources.overpopulation_end_sound.play();
-                    } else if self.victory_progress >= 1.0 {
-                        self.victory_result = Some(GameVictoryResult::Victory);
-                        let _ = self.game_resources.victory_sound.play();
-                    } else {
-                        finished = false;
-                    }
-                    if finished {
-                        self.add_text_victory_result()
-                    }
+                    let mouse_pos = ggez::input::mouse::position(ctx);
+                    self.controls.aim_x = (mouse_pos.x - self.offset_x) / self.draw_size;
+                    self.controls.aim_y = (mouse_pos.y - self.offset_y) / self.draw_size;
+                    let input = TickInput {
+                        controls: self.controls.clone(),
+                        balance: self.pending_balance.take(),
+                    };
+                    self.recording.record(&input);
+                    input
                 }
-                _ => {}
-            }
-
-            if self.population_million > OVERPOP_WARNING_NUMBER {
-                self.maybe_make_overpopulation_warning(time);
-            } else {
-                self.next_overpop_warning_enabled = true;
-            }
+            };
 
-            self.spaceship_hp = self.spaceship_hp.max(0.0);
-            self.population_million = self.population_million.max(0.0);
-            self.victory_progress = self.victory_progress.min(1.0);
-
-            if let Some(text_population_id) = self.text_population_id {
-                let text_str = format!(
-                    "Population: {}",
-                    population_to_string(self.population_million)
-                );
-                let text_str = if self.population_million > OVERPOP_WARNING_NUMBER {
-                    format!("{} (!)", text_str)
-                } else {
-                    text_str
-                };
-                let object = self.objects.get_mut(&text_population_id).unwrap();
-                if let Some(text_data) = &mut object.text_data {
-                    text_data.text = graphics::Text::new((
-                        text_str,
-                        self.game_resources.font,
-                        text_data.font_size,
-                    ));
-                }
-            }
-            if let Some(text_spaceship_hp_id) = self.text_spaceship_hp_id {
-                let text_str = format!("HP: {:.0}", self.spaceship_hp);
-                let object = self.objects.get_mut(&text_spaceship_hp_id).unwrap();
-                if let Some(text_data) = &mut object.text_data {
-                    text_data.text = graphics::Text::new((
-                        text_str,
-                        self.game_resources.font,
-                        text_data.font_size,
-                    ));
-                }
-            }
-            if let Some(text_victory_progress_id) = self.text_victory_progress_id {
-                let text_str = format!("Space Age Progress: {:.0}%", 100.0 * self.victory_progress);
-                let object = self.objects.get_mut(&text_victory_progress_id).unwrap();
-                if let Some(text_data) = &mut object.text_data {
-                    text_data.text = graphics::Text::new((
-                        text_str,
-                        self.game_resources.font,
-                        text_data.font_size,
-                    ));
-                }
-            }
+            record_positions(&self.sim.world, &mut self.previous_positions);
+            self.previous_headings = self.sim.world.headings.clone();
+            let events = self.sim.tick(&input);
+            self.handle_events(events);
+            self.update_text();
         }
 
         Ok(())
@@ -1352,7 +1624,12 @@ impl EventHandler for SaveThePinkSkin {
     fn draw(&mut self, ctx: &mut Context) -> GameResult<()> {
         graphics::clear(ctx, graphics::BLACK);
 
-        if !self.started {
+        if let Some(screen) = &self.bindings_screen {
+            self.draw_bindings_screen(ctx, screen)?;
+            return graphics::present(ctx);
+        }
+
+        if self.state == GameState::Title {
             let w = self.game_resources.instructions_image.width();
             let h = self.game_resources.instructions_image.height();
             graphics::draw(
@@ -1365,154 +1642,190 @@ impl EventHandler for SaveThePinkSkin {
                         self.draw_size / (h as f32),
                     )),
             )?;
+            if self.playback.is_none() {
+                let key = |action| self.first_binding(action);
+                let flight_text = format!(
+                    "Flight: {} ({} to change)  Controls: {}  Editor: {}",
+                    self.settings.flight_model.name(),
+                    key(Action::ToggleFlightModel),
+                    key(Action::EditBindings),
+                    key(Action::OpenEditor)
+                );
+                self.draw_screen_text(ctx, &flight_text, 0.02, 0.95, 24.0)?;
+            }
             return graphics::present(ctx);
         }
 
-        for obj in &self.stars {
-            let image = object_type_image(self, &obj.object_type);
-            let circle_data = obj.circle_data.as_ref().unwrap();
-            let circle = graphics::Mesh::new_circle(
-                ctx,
-                graphics::DrawMode::fill(),
-                na::Point2::new(0.0, 0.0),
-                circle_data.radius * self.draw_size,
-                0.1,
-                circle_data.color,
-            )?;
+        if self.state == GameState::LevelSelect {
+            self.draw_level_select(ctx)?;
+            return graphics::present(ctx);
+        }
 
-            graphics::draw(
-                ctx,
-                &circle,
-                (na::Point2::new(
-                    obj.transform.pos_x * self.draw_size + self.offset_x,
-                    obj.transform.pos_y * self.draw_size + self.offset_y,
-                ),),
-            )?;
+        // How far along the wait for the next tick is. Frozen screens show the
+        // world exactly as it is.
+        let alpha = if self.state.is_running() {
+            let tick_fraction =
+                ggez::timer::remaining_update_time(ctx).as_secs_f32() * TICKS_PER_SECOND as f32;
+            na::clamp(tick_fraction, 0.0, 1.0)
+        } else {
+            1.0
+        };
+
+        if self.stars_mesh.is_none() {
+            self.stars_mesh = self.build_stars_mesh(ctx)?;
+        }
+        if let Some(stars) = &self.stars_mesh {
+            graphics::draw(ctx, stars, graphics::DrawParam::new())?;
         }
 
-        for obj in self.objects.values() {
-            match obj.shape {
-                Shape::Circle => {
-                    let image = object_type_image(self, &obj.object_type);
-                    let circle_data = obj.circle_data.as_ref().unwrap();
-                    let circle = graphics::Mesh::new_circle(
+        let world = &self.sim.world;
+        for (entity, circle) in &world.circles {
+            if is_star(world, *entity, circle) {
+                continue;
+            }
+            let position = self.draw_position(*entity, alpha);
+            let radius = circle.radius * self.draw_size;
+            let texture = match circle.texture {
+                Some(texture) => texture,
+                None => {
+                    let key = MeshKey::new(*entity, radius, 0);
+                    let mesh = self.mesh_cache.get_or_build(key, |radius| {
+                        graphics::Mesh::new_circle(
+                            ctx,
+                            graphics::DrawMode::fill(),
+                            na::Point2::new(0.0, 0.0),
+                            radius,
+                            0.1,
+                            circle.color.into(),
+                        )
+                    })?;
+                    let scale = radius / key.radius();
+                    graphics::draw(
+                        ctx,
+                        mesh,
+                        graphics::DrawParam::new()
+                            .dest(position)
+                            .scale(na::Vector2::new(scale, scale)),
+                    )?;
+                    continue;
+                }
+            };
+            let img = texture_image(&self.game_resources, texture);
+
+            let decay_factor =
+                get_decay_size_factor(circle.radius, self.sim.balance.meteor_destroy_radius);
+            if world.burning.contains_key(entity) && decay_factor > 0.0 {
+                // Built white once and tinted as it fades.
+                let glow_radius = radius * (na::clamp(decay_factor * 2.5, 0.01, 2.5) + 1.0);
+                let key = MeshKey::new(*entity, glow_radius, 0);
+                let glow = self.mesh_cache.get_or_build(key, |radius| {
+                    graphics::Mesh::new_circle(
                         ctx,
                         graphics::DrawMode::fill(),
                         na::Point2::new(0.0, 0.0),
-                        circle_data.radius * self.draw_size,
+                        radius,
                         0.1,
-                        circle_data.color,
-                    )?;
-                    match image {
-                        Some(img) => {
-                            let decay_factor = get_decay_size_factor(circle_data.radius);
-                            if obj.object_type == ObjType::Meteor && decay_factor > 0.0 {
-                                let circle = graphics::Mesh::new_circle(
-                                    ctx,
-                                    graphics::DrawMode::fill(),
-                                    na::Point2::new(0.0, 0.0),
-                                    circle_data.radius
-                                        * self.draw_size
-                                        * (na::clamp(decay_factor * 2.5, 0.01, 2.5) + 1.0),
-                                    0.1,
-                                    graphics::Color::new(
-                                        0.8,
-                                        0.1,
-                                        0.1,
-                                        na::clamp(decay_factor, 0.01, 0.5),
-                                    ),
-                                )?;
-                                graphics::draw(
-                                    ctx,
-                                    &circle,
-                                    (na::Point2::new(
-                                        obj.transform.pos_x * self.draw_size + self.offset_x,
-                                        obj.transform.pos_y * self.draw_size + self.offset_y,
-                                    ),),
-                                )?;
-                            }
-
-                            let uv_scale = match obj.object_type {
-                                ObjType::Earth => Some(na::Point2::new(0.5, 0.9)),
-                                ObjType::Clouds => Some(na::Point2::new(0.25 * 0.8, 0.8)),
-                                ObjType::Meteor => Some(na::Point2::new(
-                                    (obj.id as f32).sin() / 4.0 + 0.25 + 1.0,
-                                    (obj.id as f32).sin() / 4.0 + 0.25 + 1.0,
-                                )),
-                                _ => None,
-                            };
-                            let samples = match obj.object_type {
-                                ObjType::Earth => 500,
-                                ObjType::Meteor => 150,
-                                _ => 250,
-                            };
-                            let mesh = match obj.object_type {
-                                ObjType::Meteor => build_textured_circle_meteor(
-                                    ctx,
-                                    obj.id,
-                                    circle_data.radius * self.draw_size,
-                                    samples,
-                                    Some(img.clone()),
-                                    Some(na::Point2::new(
-                                        (obj.id as f32).sin(),
-                                        (obj.id as f32 * 1.3123).cos(),
-                                    )),
-                                    uv_scale,
-                                )?,
-                                _ => build_textured_circle_earth(
-                                    ctx,
-                                    circle_data.radius * self.draw_size,
-                                    samples,
-                                    Some(img.clone()),
-                                    Some(na::Point2::new(
-                                        obj.render_coords.pos_x,
-                                        obj.render_coords.pos_y,
-                                    )),
-                                    uv_scale,
-                                )?,
-                            };
-                            graphics::draw(
-                                ctx,
-                                &mesh,
-                                (na::Point2::new(
-                                    obj.transform.pos_x * self.draw_size + self.offset_x,
-                                    obj.transform.pos_y * self.draw_size + self.offset_y,
-                                ),),
-                            )?;
-                        }
-                        None => {
-                            graphics::draw(
-                                ctx,
-                                &circle,
-                                (na::Point2::new(
-                                    obj.transform.pos_x * self.draw_size + self.offset_x,
-                                    obj.transform.pos_y * self.draw_size + self.offset_y,
-                                ),),
-                            )?;
-                        }
-                    }
-                }
-                _ => {}
+                        graphics::WHITE,
+                    )
+                })?;
+                let scale = glow_radius / key.radius();
+                graphics::draw(
+                    ctx,
+                    glow,
+                    graphics::DrawParam::new()
+                        .dest(position)
+                        .scale(na::Vector2::new(scale, scale))
+                        .color(graphics::Color::new(
+                            0.8,
+                            0.1,
+                            0.1,
+                            na::clamp(decay_factor, 0.01, 0.5),
+                        )),
+                )?;
             }
-        }
 
-        for obj in self.objects.values() {
-            match obj.shape {
-                Shape::Text => {
-                    let text_data = &obj.text_data.as_ref().unwrap();
-                    graphics::draw(
-                        ctx,
-                        &text_data.text,
-                        (
-                            na::Point2::new(
-                                obj.transform.pos_x * self.draw_size + self.offset_x,
-                                obj.transform.pos_y * self.draw_size + self.offset_y,
-                            ),
-                            text_data.color,
-                        ),
-                    )?;
+            let uv_scale = match texture {
+                Texture::Earth => Some(na::Point2::new(0.5, 0.9)),
+                Texture::Clouds => Some(na::Point2::new(0.25 * 0.8, 0.8)),
+                _ if texture.is_meteor() => Some(na::Point2::new(
+                    (*entity as f32).sin() / 4.0 + 0.25 + 1.0,
+                    (*entity as f32).sin() / 4.0 + 0.25 + 1.0,
+                )),
+                _ => None,
+            };
+            let uv_offset = match texture {
+                _ if texture.is_meteor() => {
+                    na::Point2::new((*entity as f32).sin(), (*entity as f32 * 1.3123).cos())
                 }
-                _ => {}
+                _ => na::Point2::new(circle.render_coords.pos_x, circle.render_coords.pos_y),
+            };
+            let samples = match texture {
+                Texture::Earth => 500,
+                _ if texture.is_meteor() => 150,
+                _ => 250,
+            };
+            let rotation = world.headings.get(entity).map_or(0.0, |heading| {
+                match self.previous_headings.get(entity) {
+                    Some(previous) => interpolate_angle(previous.angle, heading.angle, alpha),
+                    None => heading.angle,
+                }
+            });
+            let key = MeshKey::new(*entity, radius, samples);
+            let outline = world.outlines.get(entity);
+            let mesh = self.mesh_cache.get_or_build(key, |radius| match outline {
+                Some(outline) => build_textured_circle_meteor(
+                    ctx,
+                    outline,
+                    radius,
+                    samples,
+                    Some(img.clone()),
+                    None,
+                    uv_scale,
+                ),
+                None => build_textured_circle_earth(
+                    ctx,
+                    radius,
+                    samples,
+                    Some(img.clone()),
+                    None,
+                    uv_scale,
+                ),
+            })?;
+            let scale = radius / key.radius();
+            graphics::draw(
+                ctx,
+                mesh,
+                graphics::DrawParam::new()
+                    .dest(position)
+                    .rotation(rotation)
+                    .scale(na::Vector2::new(scale, scale))
+                    .src(uv_scroll(uv_offset, uv_scale)),
+            )?;
+        }
+        self.mesh_cache.end_frame();
+
+        for (entity, label) in &world.labels {
+            let text = graphics::Text::new((
+                label.text.as_str(),
+                self.game_resources.font,
+                label.font_size,
+            ));
+            graphics::draw(
+                ctx,
+                &text,
+                (
+                    self.draw_position(*entity, alpha),
+                    graphics::Color::from(label.color),
+                ),
+            )?;
+        }
+
+        if self.state == GameState::Paused {
+            self.draw_pause_menu(ctx)?;
+        }
+        if self.state == GameState::Editor {
+            if let Some(editor) = &self.editor {
+                self.draw_editor(ctx, editor)?;
             }
         }
 
@@ -1525,53 +1838,98 @@ impl EventHandler for SaveThePinkSkin {
         ctx: &mut Context,
         keycode: KeyCode,
         _keymod: KeyMods,
-        _repeat: bool,
+        repeat: bool,
     ) {
-        if let Some(dir) = from_keycode(keycode) {
-            match dir {
-                Direction::Up | Direction::Down => self.controls.up_down = Some(dir),
-                Direction::Left | Direction::Right => self.controls.left_right = Some(dir),
-            }
-        }
-        if keycode == KeyCode::R && self.victory_result.is_some() {
-            self.restart();
-        }
-        if keycode == KeyCode::NumpadEnter || keycode == KeyCode::Return {
-            self.started = true;
-        }
-        if keycode == KeyCode::Escape {
-            ggez::event::quit(ctx);
+        if repeat {
+            return;
         }
+        self.input_down(ctx, Input::Key(keycode));
     }
 
     fn key_up_event(&mut self, _ctx: &mut Context, keycode: KeyCode, _keymods: KeyMods) {
-        if let Some(dir) = from_keycode(keycode) {
-            match dir {
-                Direction::Up | Direction::Down => {
-                    if self.controls.up_down == Some(dir) {
-                        self.controls.up_down = None;
-                    }
-                }
-                Direction::Left | Direction::Right => {
-                    if self.controls.left_right == Some(dir) {
-                        self.controls.left_right = None
-                    }
+        self.input_up(Input::Key(keycode));
+    }
+
+    fn mouse_button_down_event(&mut self, ctx: &mut Context, button: MouseButton, x: f32, y: f32) {
+        if self.state == GameState::Editor {
+            let (x, y) = self.to_world(x, y);
+            if let Some(editor) = &mut self.editor {
+                match button {
+                    MouseButton::Left => editor.press(x, y, false),
+                    MouseButton::Right => editor.press(x, y, true),
+                    _ => {}
                 }
             }
+            return;
         }
+        self.input_down(ctx, Input::Mouse(button));
     }
 
-    fn mouse_button_down_event(&mut self, _ctx: &mut Context, button: MouseButton, x: f32, y: f32) {
-        if button == MouseButton::Left {
-            self.controls.shooting = true;
-            self.next_shooting_time = 0.0;
+    fn mouse_button_up_event(&mut self, _ctx: &mut Context, button: MouseButton, _x: f32, _y: f32) {
+        if self.state == GameState::Editor {
+            if let Some(editor) = &mut self.editor {
+                editor.release();
+            }
+            return;
         }
+        self.input_up(Input::Mouse(button));
     }
 
-    fn mouse_button_up_event(&mut self, _ctx: &mut Context, button: MouseButton, _x: f32, _y: f32) {
-        if button == MouseButton::Left {
-            self.controls.shooting = false;
+    fn mouse_motion_event(&mut self, _ctx: &mut Context, x: f32, y: f32, _dx: f32, _dy: f32) {
+        if self.state == GameState::Editor {
+            let (x, y) = self.to_world(x, y);
+            if let Some(editor) = &mut self.editor {
+                editor.drag_to(x, y);
+            }
+            return;
         }
+        // Moving the mouse hands aiming back to it after using a stick.
+        self.controls.aim_dir_x = 0.0;
+        self.controls.aim_dir_y = 0.0;
+    }
+
+    fn gamepad_button_down_event(&mut self, ctx: &mut Context, btn: Button, _id: GamepadId) {
+        self.input_down(ctx, Input::Pad(btn));
+    }
+
+    fn gamepad_button_up_event(&mut self, _ctx: &mut Context, btn: Button, _id: GamepadId) {
+        self.input_up(Input::Pad(btn));
+    }
+
+    fn gamepad_axis_event(&mut self, _ctx: &mut Context, axis: Axis, value: f32, _id: GamepadId) {
+        match axis {
+            Axis::LeftStickX => self.move_stick.0 = value,
+            Axis::LeftStickY => self.move_stick.1 = value,
+            Axis::RightStickX => self.aim_stick.0 = value,
+            Axis::RightStickY => self.aim_stick.1 = value,
+            _ => return,
+        }
+        match axis {
+            Axis::LeftStickX | Axis::LeftStickY => {
+                let (x, y) =
+                    stick_direction(self.move_stick.0, self.move_stick.1).unwrap_or((0.0, 0.0));
+                self.controls.move_x = x;
+                self.controls.move_y = y;
+            }
+            // Letting go of the right stick hands aiming back to the mouse.
+            _ => {
+                let (x, y) =
+                    stick_direction(self.aim_stick.0, self.aim_stick.1).unwrap_or((0.0, 0.0));
+                self.controls.aim_dir_x = x;
+                self.controls.aim_dir_y = y;
+            }
+        }
+    }
+
+    fn focus_event(&mut self, _ctx: &mut Context, gained: bool) {
+        if !gained {
+            self.follow(StateEvent::FocusLost);
+        }
+    }
+
+    fn quit_event(&mut self, _ctx: &mut Context) -> bool {
+        self.save_unfinished_recording();
+        false
     }
 
     fn resize_event(&mut self, ctx: &mut Context, width: f32, height: f32) {
@@ -1582,6 +1940,7 @@ impl EventHandler for SaveThePinkSkin {
         self.draw_size = self.window_width.min(self.window_height);
         self.offset_x = (self.window_width - self.draw_size).max(0.0) / 2.0;
         self.offset_y = (self.window_height - self.draw_size).max(0.0) / 2.0;
+        self.stars_mesh = None;
         self.reset_text();
     }
 }
@@ -0,0 +1,242 @@
+//! The kinds of meteor the game throws at the Earth. Everything that sets one kind
+//! apart is data in `METEOR_TYPES`; a meteor gets the components its type asks
+//! for when it spawns, and the systems in `simulation` take it from there.
+
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+
+use crate::ecs::Texture;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MeteorKind {
+    Rock,
+    Iron,
+    Comet,
+    Explosive,
+    Shard,
+}
+
+/// What a meteor breaks into when a projectile destroys it.
+#[derive(Clone, Copy, Debug)]
+pub struct SplitRule {
+    pub fragments: u32,
+    /// Fragment radius, as a share of the broken meteor's.
+    pub min_ratio: f32,
+    pub max_ratio: f32,
+    pub kind: MeteorKind,
+}
+
+/// Patches a meteor leaves behind that slow the ship down while it is in them.
+#[derive(Clone, Copy, Debug)]
+pub struct TrailRule {
+    /// Ticks between patches.
+    pub interval: f32,
+    /// Ticks a patch lasts.
+    pub lifetime: f32,
+    /// Size of a patch, as a share of the meteor's radius.
+    pub radius_ratio: f32,
+    /// Share of its speed the ship loses every tick it spends in a patch.
+    pub slowdown: f32,
+}
+
+/// Steering towards the ship.
+#[derive(Clone, Copy, Debug)]
+pub struct HomingRule {
+    /// Speed gained per tick towards the ship.
+    pub acceleration: f32,
+    pub max_speed: f32,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct MeteorType {
+    pub kind: MeteorKind,
+    /// Every meteor texture is drawn with the same image, each in its own tint.
+    pub texture: Texture,
+    /// Multiplies the radius rolled for a new meteor.
+    pub size_factor: f32,
+    /// Projectile hits it takes to break.
+    pub hits: u32,
+    /// Multiplies the damage it does to the ship and the Earth.
+    pub damage_factor: f32,
+    pub split: SplitRule,
+    pub trail: Option<TrailRule>,
+    pub homing: Option<HomingRule>,
+    /// How likely the type is to spawn, relative to the others, at the start of a
+    /// run and once victory progress is full. It changes linearly in between.
+    pub weight_start: f32,
+    pub weight_end: f32,
+}
+
+/// Every meteor type, in the order of `MeteorKind`.
+pub const METEOR_TYPES: [MeteorType; 5] = [
+    MeteorType {
+        kind: MeteorKind::Rock,
+        texture: Texture::Meteor,
+        size_factor: 1.0,
+        hits: 1,
+        damage_factor: 1.0,
+        split: SplitRule {
+            fragments: 1,
+            min_ratio: 0.2,
+            max_ratio: 0.5,
+            kind: MeteorKind::Rock,
+        },
+        trail: None,
+        homing: None,
+        weight_start: 10.0,
+        weight_end: 4.0,
+    },
+    MeteorType {
+        kind: MeteorKind::Iron,
+        texture: Texture::IronMeteor,
+        size_factor: 0.9,
+        hits: 3,
+        damage_factor: 1.5,
+        split: SplitRule {
+            fragments: 1,
+            min_ratio: 0.3,
+            max_ratio: 0.5,
+            kind: MeteorKind::Rock,
+        },
+        trail: None,
+        homing: None,
+        weight_start: 0.0,
+        weight_end: 3.0,
+    },
+    MeteorType {
+        kind: MeteorKind::Comet,
+        texture: Texture::Comet,
+        size_factor: 0.8,
+        hits: 1,
+        damage_factor: 0.8,
+        split: SplitRule {
+            fragments: 1,
+            min_ratio: 0.2,
+            max_ratio: 0.4,
+            kind: MeteorKind::Rock,
+        },
+        trail: Some(TrailRule {
+            interval: 6.0,
+            lifetime: 180.0,
+            radius_ratio: 1.5,
+            slowdown: 0.05,
+        }),
+        homing: None,
+        weight_start: 1.0,
+        weight_end: 2.0,
+    },
+    MeteorType {
+        kind: MeteorKind::Explosive,
+        texture: Texture::ExplosiveMeteor,
+        size_factor: 1.1,
+        hits: 1,
+        damage_factor: 2.0,
+        split: SplitRule {
+            fragments: 6,
+            min_ratio: 0.25,
+            max_ratio: 0.4,
+            kind: MeteorKind::Rock,
+        },
+        trail: None,
+        homing: None,
+        weight_start: 0.5,
+        weight_end: 2.0,
+    },
+    MeteorType {
+        kind: MeteorKind::Shard,
+        texture: Texture::Shard,
+        size_factor: 0.5,
+        hits: 1,
+        damage_factor: 0.5,
+        split: SplitRule {
+            fragments: 0,
+            min_ratio: 0.0,
+            max_ratio: 0.0,
+            kind: MeteorKind::Rock,
+        },
+        trail: None,
+        homing: Some(HomingRule {
+            acceleration: 0.000004,
+            max_speed: 0.0015,
+        }),
+        weight_start: 0.0,
+        weight_end: 1.5,
+    },
+];
+
+impl MeteorKind {
+    pub fn meteor_type(self) -> &'static MeteorType {
+        &METEOR_TYPES[self as usize]
+    }
+}
+
+impl MeteorType {
+    pub fn weight(&self, progress: f32) -> f32 {
+        self.weight_start + (self.weight_end - self.weight_start) * progress
+    }
+}
+
+/// Picks the kind of a new meteor by the weights at `progress`, from 0 to 1.
+pub fn pick_kind<R: Rng + ?Sized>(rng: &mut R, progress: f32) -> MeteorKind {
+    let total: f32 = METEOR_TYPES.iter().map(|t| t.weight(progress)).sum();
+    let mut roll = rng.gen::<f32>() * total;
+    for meteor_type in METEOR_TYPES.iter() {
+        let weight = meteor_type.weight(progress);
+        if roll < weight {
+            return meteor_type.kind;
+        }
+        roll -= weight;
+    }
+    MeteorKind::Rock
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::SeedableRng;
+
+    use super::*;
+    use crate::simulation::GameRng;
+
+    fn picks(progress: f32) -> Vec<MeteorKind> {
+        let mut rng = GameRng::seed_from_u64(3);
+        (0..5000).map(|_| pick_kind(&mut rng, progress)).collect()
+    }
+
+    #[test]
+    fn types_are_in_kind_order() {
+        for (index, meteor_type) in METEOR_TYPES.iter().enumerate() {
+            assert_eq!(meteor_type.kind as usize, index);
+            assert_eq!(meteor_type.kind.meteor_type().kind, meteor_type.kind);
+        }
+    }
+
+    #[test]
+    fn kinds_without_weight_never_spawn() {
+        let unweighted: Vec<MeteorKind> = METEOR_TYPES
+            .iter()
+            .filter(|meteor_type| meteor_type.weight(0.0) == 0.0)
+            .map(|meteor_type| meteor_type.kind)
+            .collect();
+        assert!(!unweighted.is_empty());
+        let picks = picks(0.0);
+        assert!(picks.iter().all(|kind| !unweighted.contains(kind)));
+    }
+
+    #[test]
+    fn every_kind_spawns_late_in_the_run() {
+        let picks = picks(1.0);
+        for meteor_type in METEOR_TYPES.iter() {
+            assert!(picks.contains(&meteor_type.kind), "{:?}", meteor_type.kind);
+        }
+    }
+
+    #[test]
+    fn weights_move_linearly_with_progress() {
+        let iron = MeteorKind::Iron.meteor_type();
+        assert_eq!(iron.weight(0.0), iron.weight_start);
+        assert_eq!(iron.weight(1.0), iron.weight_end);
+        let halfway = (iron.weight_start + iron.weight_end) / 2.0;
+        assert!((iron.weight(0.5) - halfway).abs() < 1e-6);
+    }
+}
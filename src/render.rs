@@ -0,0 +1,425 @@
+//! The maths behind drawing that doesn't need a window: the triangles of a circle
+//! or meteor mesh, where things are drawn between two ticks and which meshes are
+//! kept between frames. The frontend turns the geometry into its own vertices.
+
+use std::collections::hash_map::{Entry, HashMap};
+use std::collections::BTreeMap;
+use std::f32::consts::PI;
+
+use crate::ecs::{Entity, World};
+
+/// A corner of a mesh: where it is, where it samples the texture and its colour.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Vertex {
+    pub pos: [f32; 2],
+    pub uv: [f32; 2],
+    pub color: [f32; 4],
+}
+
+/// Vertex and index buffers for a mesh, before they are handed to the GPU.
+#[derive(Clone, Debug, Default)]
+pub struct Geometry {
+    pub vertices: Vec<Vertex>,
+    pub indices: Vec<u32>,
+}
+
+/// A fan of `samples` triangles around the centre. The rim repeats its first
+/// vertex at the end, so there are `samples + 2` vertices.
+///
+/// `shape` gives how far the rim is from the centre at a given angle, as a share
+/// of the radius, to make the outline bumpy. `color` gives the colour of the rim
+/// at a given angle, or of the centre when the angle is `None`.
+pub fn circle_geometry<P, C>(
+    radius: f32,
+    samples: usize,
+    uv_offset: [f32; 2],
+    uv_scale: [f32; 2],
+    shape: P,
+    color: C,
+) -> Geometry
+where
+    P: Fn(f32) -> f32,
+    C: Fn(Option<f32>) -> [f32; 4],
+{
+    let vertex = |u: f32, v: f32, color: [f32; 4]| Vertex {
+        pos: [radius * u, radius * v],
+        uv: [
+            uv_scale[0] * (u / 2.0 + 0.5 + uv_offset[0]),
+            uv_scale[1] * (v / 2.0 + 0.5 + uv_offset[1]),
+        ],
+        color,
+    };
+
+    let mut geometry = Geometry::default();
+    geometry.vertices.push(vertex(0.0, 0.0, color(None)));
+    for i in 0..=(samples as u32) {
+        if i > 0 {
+            geometry.indices.extend_from_slice(&[i, 0, i + 1]);
+        }
+        let angle = (i as f32) * 2.0 * PI / (samples as f32);
+        let u = angle.sin() * shape(angle);
+        let v = angle.cos() * shape(angle);
+        geometry.vertices.push(vertex(u, v, color(Some(angle))));
+    }
+    geometry
+}
+
+/// A plain circle's rim, for `circle_geometry`.
+pub fn round(_angle: f32) -> f32 {
+    1.0
+}
+
+pub fn white(_angle: Option<f32>) -> [f32; 4] {
+    [1.0, 1.0, 1.0, 1.0]
+}
+
+/// Grey in the middle, fading out to white at the rim.
+pub fn shaded(angle: Option<f32>) -> [f32; 4] {
+    match angle {
+        Some(_) => [1.0, 1.0, 1.0, 1.0],
+        None => [0.5, 0.5, 0.5, 1.0],
+    }
+}
+
+/// Where something is drawn on one axis between the previous tick and the
+/// current one, `alpha` going from 0 at `previous` to 1 at `current`. When the
+/// move is longer than half the screen it wrapped around an edge, so it is drawn
+/// coming in from beyond that edge instead of streaking across the screen.
+pub fn interpolate_axis(previous: f32, current: f32, velocity: f32, alpha: f32) -> f32 {
+    let previous = if (current - previous).abs() > 0.5 {
+        current - velocity
+    } else {
+        previous
+    };
+    previous + (current - previous) * alpha
+}
+
+/// Keeps where everything that can move is, to draw it between there and where
+/// the next tick puts it. Stars and other still decorations are left out.
+pub fn record_positions(world: &World, positions: &mut BTreeMap<Entity, (f32, f32)>) {
+    positions.clear();
+    let moving = world.transforms.iter().filter(|(entity, transform)| {
+        world.colliders.contains_key(entity)
+            || transform.vel_x != 0.0
+            || transform.vel_y != 0.0
+            || transform.acc_x != 0.0
+            || transform.acc_y != 0.0
+    });
+    for (&entity, transform) in moving {
+        positions.insert(entity, (transform.pos_x, transform.pos_y));
+    }
+}
+
+/// Turns the short way round from `previous` to `current`, both in radians.
+pub fn interpolate_angle(previous: f32, current: f32, alpha: f32) -> f32 {
+    let turn = (current - previous + PI).rem_euclid(2.0 * PI) - PI;
+    previous + turn * alpha
+}
+
+/// Steps a cached mesh's radius is rounded to, per pixel. The mesh is drawn
+/// scaled to the exact radius, so this only bounds how far a mesh is stretched
+/// and how often a burning meteor's is rebuilt.
+pub const MESH_STEPS_PER_PIXEL: f32 = 4.0;
+
+/// What a cached mesh was built for. The radius is in quarter pixels so a slowly
+/// shrinking circle is only rebuilt a few times per pixel.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct MeshKey {
+    pub id: usize,
+    pub radius: u32,
+    pub samples: usize,
+}
+
+impl MeshKey {
+    pub fn new(id: usize, radius: f32, samples: usize) -> MeshKey {
+        MeshKey {
+            id,
+            radius: ((radius * MESH_STEPS_PER_PIXEL).round() as u32).max(1),
+            samples,
+        }
+    }
+
+    /// The radius the mesh is built at, in pixels.
+    pub fn radius(&self) -> f32 {
+        self.radius as f32 / MESH_STEPS_PER_PIXEL
+    }
+}
+
+/// Meshes kept from one frame to the next. Anything not drawn during a frame is
+/// dropped at the end of it.
+pub struct MeshCache<M> {
+    meshes: HashMap<MeshKey, (M, bool)>,
+}
+
+impl<M> Default for MeshCache<M> {
+    fn default() -> Self {
+        MeshCache {
+            meshes: HashMap::new(),
+        }
+    }
+}
+
+impl<M> MeshCache<M> {
+    /// The mesh for `key`, built with `build` at `key.radius()` if it isn't cached.
+    pub fn get_or_build<F, E>(&mut self, key: MeshKey, build: F) -> Result<&M, E>
+    where
+        F: FnOnce(f32) -> Result<M, E>,
+    {
+        let entry = match self.meshes.entry(key) {
+            Entry::Occupied(entry) => entry.into_mut(),
+            Entry::Vacant(entry) => entry.insert((build(key.radius())?, false)),
+        };
+        entry.1 = true;
+        Ok(&entry.0)
+    }
+
+    /// Drops the meshes that weren't used since the last call.
+    pub fn end_frame(&mut self) {
+        self.meshes
+            .retain(|_, (_, used)| std::mem::replace(used, false));
+    }
+
+    pub fn clear(&mut self) {
+        self.meshes.clear();
+    }
+
+    pub fn len(&self) -> usize {
+        self.meshes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.meshes.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::shape::{Outline, ShapeSettings};
+
+    fn plain(samples: usize) -> Geometry {
+        circle_geometry(2.0, samples, [0.0, 0.0], [1.0, 1.0], round, white)
+    }
+
+    fn outline(seed: u64) -> Outline {
+        let settings = ShapeSettings {
+            roughness: 0.15,
+            crater_count: 2,
+            crater_depth: 0.12,
+        };
+        Outline::generate(seed, &settings)
+    }
+
+    fn meteor(seed: u64) -> Geometry {
+        let outline = outline(seed);
+        circle_geometry(
+            2.0,
+            150,
+            [0.0, 0.0],
+            [1.0, 1.0],
+            |angle| outline.factor_at(angle),
+            shaded,
+        )
+    }
+
+    /// Twice the signed area of each triangle, in the mesh's own coordinates.
+    fn signed_areas(geometry: &Geometry) -> Vec<f32> {
+        geometry
+            .indices
+            .chunks(3)
+            .map(|triangle| {
+                let [ax, ay] = geometry.vertices[triangle[0] as usize].pos;
+                let [bx, by] = geometry.vertices[triangle[1] as usize].pos;
+                let [cx, cy] = geometry.vertices[triangle[2] as usize].pos;
+                (bx - ax) * (cy - ay) - (by - ay) * (cx - ax)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn vertex_and_index_counts() {
+        for &samples in &[3, 150, 500] {
+            let geometry = plain(samples);
+            assert_eq!(geometry.vertices.len(), samples + 2);
+            assert_eq!(geometry.indices.len(), samples * 3);
+            assert!(geometry
+                .indices
+                .iter()
+                .all(|&i| (i as usize) < geometry.vertices.len()));
+        }
+    }
+
+    #[test]
+    fn rim_closes_on_itself() {
+        let geometry = plain(7);
+        let first = geometry.vertices[1].pos;
+        let last = geometry.vertices[8].pos;
+        assert!((first[0] - last[0]).abs() < 1e-5);
+        assert!((first[1] - last[1]).abs() < 1e-5);
+    }
+
+    #[test]
+    fn every_triangle_winds_the_same_way() {
+        for geometry in &[plain(3), plain(250), meteor(3), meteor(42)] {
+            assert!(signed_areas(geometry).iter().all(|&area| area > 0.0));
+        }
+    }
+
+    #[test]
+    fn uv_stays_in_the_unit_square() {
+        for vertex in &plain(250).vertices {
+            assert!(vertex.uv.iter().all(|uv| (0.0..=1.0).contains(uv)));
+        }
+        // Bumps push the rim out past the unit circle, by half as much in UV.
+        let slack = (outline(42).max_factor() - 1.0) / 2.0 + 1e-5;
+        for vertex in &meteor(42).vertices {
+            assert!(vertex
+                .uv
+                .iter()
+                .all(|uv| (-slack..=1.0 + slack).contains(uv)));
+        }
+    }
+
+    #[test]
+    fn uv_follows_scale_and_offset() {
+        let geometry = circle_geometry(1.0, 4, [0.25, 0.5], [2.0, 0.5], round, white);
+        assert_eq!(geometry.vertices[0].uv, [1.5, 0.5]);
+    }
+
+    #[test]
+    fn meteor_rim_follows_its_outline() {
+        let outline = outline(7);
+        let geometry = meteor(7);
+        for (i, vertex) in geometry.vertices[1..].iter().enumerate() {
+            let angle = i as f32 * 2.0 * PI / 150.0;
+            let [x, y] = vertex.pos;
+            let distance = (x * x + y * y).sqrt();
+            assert!((distance - 2.0 * outline.factor_at(angle)).abs() < 1e-4);
+        }
+    }
+
+    #[test]
+    fn centre_is_shaded() {
+        let geometry = meteor(1);
+        assert_eq!(geometry.vertices[0].color, [0.5, 0.5, 0.5, 1.0]);
+        assert!(geometry.vertices[1..]
+            .iter()
+            .all(|vertex| vertex.color == [1.0, 1.0, 1.0, 1.0]));
+    }
+
+    #[test]
+    fn interpolation_goes_from_previous_to_current() {
+        assert_eq!(interpolate_axis(0.2, 0.3, 0.1, 0.0), 0.2);
+        assert!((interpolate_axis(0.2, 0.3, 0.1, 0.5) - 0.25).abs() < 1e-6);
+        assert_eq!(interpolate_axis(0.2, 0.3, 0.1, 1.0), 0.3);
+    }
+
+    #[test]
+    fn interpolation_enters_from_beyond_the_edge_it_wrapped_over() {
+        // Left through the right edge and came back in on the left.
+        let x = interpolate_axis(1.02, -0.01, 0.02, 0.5);
+        assert!((x - -0.02).abs() < 1e-6, "{}", x);
+        // Left through the left edge and came back in on the right.
+        let x = interpolate_axis(-0.02, 1.01, -0.02, 0.5);
+        assert!((x - 1.02).abs() < 1e-6, "{}", x);
+        // Never anywhere near the middle of the screen on the way.
+        for step in 0..=10 {
+            let x = interpolate_axis(1.02, -0.01, 0.02, step as f32 / 10.0);
+            assert!(x < 0.0, "{}", x);
+        }
+    }
+
+    #[test]
+    fn only_things_that_can_move_are_recorded() {
+        use crate::ecs::{Collider, Transform};
+
+        let mut world = World::new();
+        let star = world.spawn(Transform {
+            pos_x: 0.1,
+            pos_y: 0.2,
+            ..Default::default()
+        });
+        let earth = world.spawn(Transform {
+            pos_x: 0.5,
+            pos_y: 0.5,
+            ..Default::default()
+        });
+        world.colliders.insert(earth, Collider::circle(0.1));
+        let label = world.spawn(Transform {
+            pos_x: 0.3,
+            pos_y: 0.3,
+            vel_y: -0.001,
+            ..Default::default()
+        });
+        let falling = world.spawn(Transform {
+            acc_y: 0.001,
+            ..Default::default()
+        });
+
+        let mut positions = BTreeMap::new();
+        positions.insert(star, (0.9, 0.9));
+        record_positions(&world, &mut positions);
+        assert!(!positions.contains_key(&star));
+        assert_eq!(positions[&earth], (0.5, 0.5));
+        assert_eq!(positions[&label], (0.3, 0.3));
+        assert!(positions.contains_key(&falling));
+    }
+
+    #[test]
+    fn angle_interpolation_turns_the_short_way() {
+        // Halfway across straight up, not all the way back round.
+        let angle = interpolate_angle(2.0 * PI - 0.1, 0.1, 0.5);
+        assert!((angle - 2.0 * PI).abs() < 1e-5, "{}", angle);
+    }
+
+    #[test]
+    fn mesh_keys_round_to_a_quarter_pixel() {
+        assert_eq!(MeshKey::new(1, 10.1, 0), MeshKey::new(1, 10.0, 0));
+        assert_ne!(MeshKey::new(1, 10.2, 0), MeshKey::new(1, 10.0, 0));
+        assert_eq!(MeshKey::new(1, 10.2, 0).radius(), 10.25);
+        // A shrinking meteor never drifts further than an eighth of a pixel.
+        for step in 0..390 {
+            let radius = 20.0 - step as f32 * 0.05;
+            assert!((MeshKey::new(1, radius, 0).radius() - radius).abs() <= 0.125 + 1e-4);
+        }
+        // Nothing is built with no size at all.
+        assert_eq!(MeshKey::new(1, 0.0, 0).radius(), 0.25);
+    }
+
+    #[test]
+    fn cached_meshes_are_reused_until_a_frame_skips_them() {
+        let mut cache: MeshCache<f32> = MeshCache::default();
+        let builds = std::cell::Cell::new(0);
+        let build = |radius| -> Result<f32, ()> {
+            builds.set(builds.get() + 1);
+            Ok(radius)
+        };
+        let earth = MeshKey::new(1, 40.0, 500);
+        let meteor = MeshKey::new(2, 10.0, 150);
+
+        assert_eq!(cache.get_or_build(earth, build), Ok(&40.0));
+        assert_eq!(cache.get_or_build(meteor, build), Ok(&10.0));
+        cache.end_frame();
+        assert_eq!(cache.get_or_build(earth, build), Ok(&40.0));
+        assert_eq!(
+            cache.get_or_build(MeshKey::new(2, 10.1, 150), build),
+            Ok(&10.0)
+        );
+        cache.end_frame();
+        assert_eq!(builds.get(), 2);
+
+        // The meteor isn't drawn this frame, so it is dropped.
+        cache.get_or_build(earth, build).unwrap();
+        cache.end_frame();
+        assert_eq!(cache.len(), 1);
+        cache.get_or_build(meteor, build).unwrap();
+        assert_eq!(builds.get(), 3);
+
+        // A failed build leaves nothing behind.
+        let failed = cache.get_or_build(MeshKey::new(3, 5.0, 150), |_| Err(()));
+        assert_eq!(failed, Err(()));
+        assert_eq!(cache.len(), 2);
+        cache.clear();
+        assert!(cache.is_empty());
+    }
+}
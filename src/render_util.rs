@@ -1,70 +1,30 @@
 use ggez::nalgebra as na;
-use ggez::{graphics, Context, ContextBuilder, GameResult};
+use ggez::{graphics, Context, GameResult};
 
-fn uv_wrap(v: f32) -> f32 {
-	v
-}
+use ld46_keep_it_alive::render::{circle_geometry, round, shaded, Geometry};
+use ld46_keep_it_alive::shape::Outline;
 
-pub fn build_textured_circle(
+fn build_mesh(
 	ctx: &mut Context,
-	radius: f32,
-	samples: usize,
+	geometry: &Geometry,
 	image: Option<graphics::Image>,
-	uv_offset: Option<na::Point2<f32>>,
-	uv_scale: Option<na::Point2<f32>>,
 ) -> GameResult<graphics::Mesh> {
-	let mb = &mut graphics::MeshBuilder::new();
-	let uv_offset = uv_offset.unwrap_or(na::Point2::new(0.0, 0.0));
-	let uv_scale = uv_scale.unwrap_or(na::Point2::new(1.0, 1.0));
-
-	let mut triangle_verts = Vec::new();
-	let mut triangle_indices = Vec::new();
-	let middle = graphics::Vertex {
-		pos: [0.0, 0.0],
-		uv: [
-			uv_wrap(uv_scale.x * (0.0 / 2.0 + 0.5 + uv_offset.x)),
-			uv_wrap(uv_scale.y * (0.0 / 2.0 + 0.5 + uv_offset.y)),
-		],
-		color: [1.0, 1.0, 1.0, 1.0],
-	};
-
-	triangle_verts.push(middle);
-	let angle: f32 = 0.0;
-	let u = angle.sin();
-	let v = angle.cos();
-	let x = radius * u;
-	let y = radius * v;
-
-	triangle_verts.push(graphics::Vertex {
-		pos: [x, y],
-		uv: [
-			uv_wrap(uv_scale.x * (u / 2.0 + 0.5 + uv_offset.x)),
-			uv_wrap(uv_scale.y * (v / 2.0 + 0.5 + uv_offset.y)),
-		],
-		color: [1.0, 1.0, 1.0, 1.0],
-	});
-
-	for i in 1..(samples as u32) + 1 {
-		triangle_indices.push(i);
-		triangle_indices.push(0);
-		triangle_indices.push(i + 1);
+	let vertices: Vec<graphics::Vertex> = geometry
+		.vertices
+		.iter()
+		.map(|vertex| graphics::Vertex {
+			pos: vertex.pos,
+			uv: vertex.uv,
+			color: vertex.color,
+		})
+		.collect();
+	graphics::MeshBuilder::new()
+		.raw(&vertices, &geometry.indices, image)
+		.build(ctx)
+}
 
-		let angle = (i as f32) * 2.0 * std::f32::consts::PI / (samples as f32);
-		let u = angle.sin();
-		let v = angle.cos();
-		let x = radius * u;
-		let y = radius * v;
-		triangle_verts.push(graphics::Vertex {
-			pos: [x, y],
-			uv: [
-				uv_wrap(uv_scale.x * (u / 2.0 + 0.5 + uv_offset.x)),
-				uv_wrap(uv_scale.y * (v / 2.0 + 0.5 + uv_offset.y)),
-			],
-			color: [1.0, 1.0, 1.0, 1.0],
-		});
-	}
-	mb.raw(&triangle_verts, &triangle_indices, image);
-	mb.build(ctx)
+fn uv_or(point: Option<na::Point2<f32>>, default: f32) -> [f32; 2] {
+	point.map_or([default, default], |point| [point.x, point.y])
 }
 
 pub fn build_textured_circle_earth(
@@ -75,127 +35,54 @@ pub fn build_textured_circle_earth(
 	uv_offset: Option<na::Point2<f32>>,
 	uv_scale: Option<na::Point2<f32>>,
 ) -> GameResult<graphics::Mesh> {
-	let mb = &mut graphics::MeshBuilder::new();
-	let uv_offset = uv_offset.unwrap_or(na::Point2::new(0.0, 0.0));
-	let uv_scale = uv_scale.unwrap_or(na::Point2::new(1.0, 1.0));
-
-	let mut triangle_verts = Vec::new();
-	let mut triangle_indices = Vec::new();
-	let middle = graphics::Vertex {
-		pos: [0.0, 0.0],
-		uv: [
-			uv_wrap(uv_scale.x * (0.0 / 2.0 + 0.5 + uv_offset.x)),
-			uv_wrap(uv_scale.y * (0.0 / 2.0 + 0.5 + uv_offset.y)),
-		],
-		color: [0.5, 0.5, 0.5, 1.0],
-	};
-
-	triangle_verts.push(middle);
-	let angle: f32 = 0.0;
-	let u = angle.sin();
-	let v = angle.cos();
-	let x = radius * u;
-	let y = radius * v;
-	// let light = 1.0 - u;
-	let light = 1.0;
-
-	triangle_verts.push(graphics::Vertex {
-		pos: [x, y],
-		uv: [
-			uv_wrap(uv_scale.x * (u / 2.0 + 0.5 + uv_offset.x)),
-			uv_wrap(uv_scale.y * (v / 2.0 + 0.5 + uv_offset.y)),
-		],
-		color: [light, light, light, 1.0],
-	});
-
-	for i in 1..(samples as u32) + 1 {
-		triangle_indices.push(i);
-		triangle_indices.push(0);
-		triangle_indices.push(i + 1);
-
-		let angle = (i as f32) * 2.0 * std::f32::consts::PI / (samples as f32);
-		let u = angle.sin();
-		let v = angle.cos();
-		let x = radius * u;
-		let y = radius * v;
-		triangle_verts.push(graphics::Vertex {
-			pos: [x, y],
-			uv: [
-				uv_wrap(uv_scale.x * (u / 2.0 + 0.5 + uv_offset.x)),
-				uv_wrap(uv_scale.y * (v / 2.0 + 0.5 + uv_offset.y)),
-			],
-			color: [light, light, light, 1.0],
-		});
-	}
-	mb.raw(&triangle_verts, &triangle_indices, image);
-	mb.build(ctx)
-}
-
-const METEOR_BUMPINESS: f32 = 0.1;
-
-fn rng_meteor(id: usize, angle: f32) -> f32 {
-	(id as f32).sin() * (angle).sin() * METEOR_BUMPINESS
+	let geometry = circle_geometry(
+		radius,
+		samples,
+		uv_or(uv_offset, 0.0),
+		uv_or(uv_scale, 1.0),
+		round,
+		shaded,
+	);
+	build_mesh(ctx, &geometry, image)
 }
 
 pub fn build_textured_circle_meteor(
 	ctx: &mut Context,
-	id: usize,
+	outline: &Outline,
 	radius: f32,
 	samples: usize,
 	image: Option<graphics::Image>,
 	uv_offset: Option<na::Point2<f32>>,
 	uv_scale: Option<na::Point2<f32>>,
 ) -> GameResult<graphics::Mesh> {
-	let mb = &mut graphics::MeshBuilder::new();
-	let uv_offset = uv_offset.unwrap_or(na::Point2::new(0.0, 0.0));
-	let uv_scale = uv_scale.unwrap_or(na::Point2::new(1.0, 1.0));
-
-	let mut triangle_verts = Vec::new();
-	let mut triangle_indices = Vec::new();
-	let middle = graphics::Vertex {
-		pos: [0.0, 0.0],
-		uv: [
-			uv_wrap(uv_scale.x * (0.0 / 2.0 + 0.5 + uv_offset.x)),
-			uv_wrap(uv_scale.y * (0.0 / 2.0 + 0.5 + uv_offset.y)),
-		],
-		color: [0.5, 0.5, 0.5, 1.0],
-	};
-
-	triangle_verts.push(middle);
-	let angle: f32 = 0.0;
-	let u = angle.sin() + rng_meteor(id, angle);
-	let v = angle.cos() + rng_meteor(id, angle);
-	let x = radius * u;
-	let y = radius * v;
-
-	triangle_verts.push(graphics::Vertex {
-		pos: [x, y],
-		uv: [
-			uv_wrap(uv_scale.x * (u / 2.0 + 0.5 + uv_offset.x)),
-			uv_wrap(uv_scale.y * (v / 2.0 + 0.5 + uv_offset.y)),
-		],
-		color: [1.0, 1.0, 1.0, 1.0],
-	});
+	let geometry = circle_geometry(
+		radius,
+		samples,
+		uv_or(uv_offset, 0.0),
+		uv_or(uv_scale, 1.0),
+		|angle| outline.factor_at(angle),
+		shaded,
+	);
+	build_mesh(ctx, &geometry, image)
+}
 
-	for i in 1..(samples as u32) + 1 {
-		triangle_indices.push(i);
-		triangle_indices.push(0);
-		triangle_indices.push(i + 1);
+/// Texture coordinates to draw a cached mesh with, so a texture can scroll
+/// without rebuilding the mesh. The mesh is built without an offset; this shifts
+/// its texture coordinates as `uv_offset` would have.
+pub fn uv_scroll(uv_offset: na::Point2<f32>, uv_scale: Option<na::Point2<f32>>) -> graphics::Rect {
+	let uv_scale = uv_scale.unwrap_or(na::Point2::new(1.0, 1.0));
+	graphics::Rect::new(uv_scale.x * uv_offset.x, uv_scale.y * uv_offset.y, 1.0, 1.0)
+}
 
-		let angle = (i as f32) * 2.0 * std::f32::consts::PI / (samples as f32);
-		let u = angle.sin() + rng_meteor(id, angle);
-		let v = angle.cos() + rng_meteor(id, angle);
-		let x = radius * u;
-		let y = radius * v;
-		triangle_verts.push(graphics::Vertex {
-			pos: [x, y],
-			uv: [
-				uv_wrap(uv_scale.x * (u / 2.0 + 0.5 + uv_offset.x)),
-				uv_wrap(uv_scale.y * (v / 2.0 + 0.5 + uv_offset.y)),
-			],
-			color: [1.0, 1.0, 1.0, 1.0],
-		});
+/// Plain circles that never move, all in one mesh so they take a single draw.
+/// Positions are in pixels.
+pub fn build_circle_batch<I>(ctx: &mut Context, circles: I) -> GameResult<graphics::Mesh>
+where
+	I: IntoIterator<Item = (na::Point2<f32>, f32, graphics::Color)>,
+{
+	let mb = &mut graphics::MeshBuilder::new();
+	for (position, radius, color) in circles {
+		mb.circle(graphics::DrawMode::fill(), position, radius, 0.1, color);
 	}
-	mb.raw(&triangle_verts, &triangle_indices, image);
 	mb.build(ctx)
 }
@@ -0,0 +1,674 @@
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use crate::balance::Balance;
+use crate::level::Level;
+use crate::simulation::{
+    Controls, Direction, FlightModel, GameMode, GameVictoryResult, Simulation, TickInput,
+};
+
+/// Bumped whenever the file layout or the meaning of a recorded input changes.
+pub const REPLAY_VERSION: u32 = 10;
+
+const REPLAY_HEADER: &str = "# Save The Pink Skins replay";
+
+/// How a recorded game ended, as claimed by the machine that recorded it.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ReplayOutcome {
+    pub result: GameVictoryResult,
+    pub ticks: u64,
+    pub population_million: f32,
+    pub spaceship_hp: f32,
+    pub score: u32,
+}
+
+impl ReplayOutcome {
+    /// How `sim` ended, or nothing while it is still going.
+    pub fn of(sim: &Simulation) -> Option<ReplayOutcome> {
+        let result = sim.victory_result.clone()?;
+        Some(ReplayOutcome {
+            result,
+            ticks: sim.ticks,
+            population_million: sim.population_million,
+            spaceship_hp: sim.spaceship_hp,
+            score: sim.score().total(),
+        })
+    }
+}
+
+/// A whole session: the seed, rules, balance and level it started from, whether
+/// it was endless, and the input of every tick.
+#[derive(Clone, Debug)]
+pub struct Replay {
+    pub seed: u64,
+    pub mode: GameMode,
+    pub flight_model: FlightModel,
+    pub balance: Balance,
+    pub level: Option<Level>,
+    pub endless: bool,
+    pub inputs: Vec<TickInput>,
+    pub outcome: Option<ReplayOutcome>,
+}
+
+#[derive(Debug)]
+pub enum ReplayError {
+    Io(io::Error),
+    UnsupportedVersion(u32),
+    Parse { line: usize, message: String },
+}
+
+impl fmt::Display for ReplayError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ReplayError::Io(e) => write!(f, "{}", e),
+            ReplayError::UnsupportedVersion(version) => write!(
+                f,
+                "replay version {} is not supported (expected {})",
+                version, REPLAY_VERSION
+            ),
+            ReplayError::Parse { line, message } => write!(f, "line {}: {}", line, message),
+        }
+    }
+}
+
+impl std::error::Error for ReplayError {}
+
+impl From<io::Error> for ReplayError {
+    fn from(e: io::Error) -> ReplayError {
+        ReplayError::Io(e)
+    }
+}
+
+impl Replay {
+    pub fn new(
+        seed: u64,
+        mode: GameMode,
+        flight_model: FlightModel,
+        balance: Balance,
+        level: Option<Level>,
+        endless: bool,
+    ) -> Replay {
+        Replay {
+            seed,
+            mode,
+            flight_model,
+            balance,
+            level,
+            endless,
+            inputs: Vec::new(),
+            outcome: None,
+        }
+    }
+
+    pub fn record(&mut self, input: &TickInput) {
+        self.inputs.push(input.clone());
+    }
+
+    /// Stores how the recorded session ended, once it has.
+    pub fn finish(&mut self, sim: &Simulation) {
+        self.outcome = ReplayOutcome::of(sim);
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Replay, ReplayError> {
+        let contents = fs::read_to_string(path)?;
+        Replay::parse(&contents)
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), ReplayError> {
+        fs::write(path, self.serialize())?;
+        Ok(())
+    }
+
+    /// Writes the replay in its text form. Consecutive identical inputs are stored
+    /// as a single line with a repeat count, which keeps idle stretches small.
+    /// A balance reload is written as `reload` lines in front of the input it
+    /// arrived with. A level is written whole, as `level` lines.
+    pub fn serialize(&self) -> String {
+        let mut out = String::new();
+        out.push_str(REPLAY_HEADER);
+        out.push('\n');
+        out.push_str(&format!("version {}\n", REPLAY_VERSION));
+        out.push_str(&format!("seed {}\n", self.seed));
+        out.push_str(&format!("mode {}\n", self.mode.name()));
+        out.push_str(&format!("flight {}\n", self.flight_model.name()));
+        out.push_str(&format!("endless {}\n", self.endless as u8));
+        for line in self.balance.to_toml().lines() {
+            out.push_str(&format!("balance {}\n", line));
+        }
+        if let Some(level) = &self.level {
+            for line in level.to_toml().lines().filter(|line| !line.is_empty()) {
+                out.push_str(&format!("level {}\n", line));
+            }
+        }
+
+        let mut i = 0;
+        while i < self.inputs.len() {
+            let input = &self.inputs[i];
+            let mut count = 1;
+            while i + count < self.inputs.len() && same_input(&self.inputs[i + count], input) {
+                count += 1;
+            }
+            if let Some(balance) = &input.balance {
+                for line in balance.to_toml().lines() {
+                    out.push_str(&format!("reload {}\n", line));
+                }
+            }
+            let controls = &input.controls;
+            out.push_str(&format!(
+                "input {} {} {} {} {} {} {} {} {} {}\n",
+                count,
+                direction_to_str(controls.left_right),
+                direction_to_str(controls.up_down),
+                controls.move_x,
+                controls.move_y,
+                controls.shooting as u8,
+                controls.aim_x,
+                controls.aim_y,
+                controls.aim_dir_x,
+                controls.aim_dir_y,
+            ));
+            i += count;
+        }
+
+        if let Some(outcome) = &self.outcome {
+            // Debug formatting keeps every bit of the floats.
+            out.push_str(&format!(
+                "outcome {} {} {:?} {:?} {}\n",
+                result_to_str(&outcome.result),
+                outcome.ticks,
+                outcome.population_million,
+                outcome.spaceship_hp,
+                outcome.score
+            ));
+        }
+        out
+    }
+
+    pub fn parse(contents: &str) -> Result<Replay, ReplayError> {
+        let mut version = None;
+        let mut seed = None;
+        let mut mode = None;
+        let mut flight_model = None;
+        let mut balance_toml = String::new();
+        let mut level_toml: Option<String> = None;
+        let mut endless = false;
+        let mut reload_toml: Option<String> = None;
+        let mut inputs = Vec::new();
+        let mut outcome = None;
+
+        for (index, line) in contents.lines().enumerate() {
+            let line_number = index + 1;
+            let err = |message: &str| ReplayError::Parse {
+                line: line_number,
+                message: message.to_string(),
+            };
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            match fields[0] {
+                "version" => {
+                    let value = parse_field::<u32>(&fields, 1).ok_or_else(|| err("bad version"))?;
+                    if value != REPLAY_VERSION {
+                        return Err(ReplayError::UnsupportedVersion(value));
+                    }
+                    version = Some(value);
+                }
+                "seed" => {
+                    seed = Some(parse_field::<u64>(&fields, 1).ok_or_else(|| err("bad seed"))?);
+                }
+                "mode" => {
+                    let value = fields
+                        .get(1)
+                        .and_then(|s| GameMode::from_name(s))
+                        .ok_or_else(|| err("bad mode"))?;
+                    mode = Some(value);
+                }
+                "flight" => {
+                    let value = fields
+                        .get(1)
+                        .and_then(|s| FlightModel::from_name(s))
+                        .ok_or_else(|| err("bad flight model"))?;
+                    flight_model = Some(value);
+                }
+                "endless" => {
+                    endless =
+                        parse_field::<u8>(&fields, 1).ok_or_else(|| err("bad endless flag"))? != 0;
+                }
+                "balance" => {
+                    balance_toml.push_str(line["balance".len()..].trim());
+                    balance_toml.push('\n');
+                }
+                "level" => {
+                    let level_toml = level_toml.get_or_insert_with(String::new);
+                    level_toml.push_str(line["level".len()..].trim());
+                    level_toml.push('\n');
+                }
+                "reload" => {
+                    let reload_toml = reload_toml.get_or_insert_with(String::new);
+                    reload_toml.push_str(line["reload".len()..].trim());
+                    reload_toml.push('\n');
+                }
+                "input" => {
+                    if version.is_none() {
+                        return Err(err("input before version"));
+                    }
+                    if fields.len() != 11 {
+                        return Err(err("input expects 10 values"));
+                    }
+                    let count = parse_field::<usize>(&fields, 1).ok_or_else(|| err("bad count"))?;
+                    let balance = match reload_toml.take() {
+                        Some(reload_toml) => Some(
+                            Balance::parse(&reload_toml)
+                                .map_err(|e| err(&format!("bad reload: {}", e)))?,
+                        ),
+                        None => None,
+                    };
+                    let mut input = TickInput {
+                        controls: Controls {
+                            left_right: str_to_direction(fields[2])
+                                .ok_or_else(|| err("bad horizontal direction"))?,
+                            up_down: str_to_direction(fields[3])
+                                .ok_or_else(|| err("bad vertical direction"))?,
+                            move_x: parse_field::<f32>(&fields, 4)
+                                .ok_or_else(|| err("bad movement"))?,
+                            move_y: parse_field::<f32>(&fields, 5)
+                                .ok_or_else(|| err("bad movement"))?,
+                            shooting: parse_field::<u8>(&fields, 6)
+                                .ok_or_else(|| err("bad shooting flag"))?
+                                != 0,
+                            aim_x: parse_field::<f32>(&fields, 7).ok_or_else(|| err("bad aim"))?,
+                            aim_y: parse_field::<f32>(&fields, 8).ok_or_else(|| err("bad aim"))?,
+                            aim_dir_x: parse_field::<f32>(&fields, 9)
+                                .ok_or_else(|| err("bad aim direction"))?,
+                            aim_dir_y: parse_field::<f32>(&fields, 10)
+                                .ok_or_else(|| err("bad aim direction"))?,
+                        },
+                        balance,
+                    };
+                    for _ in 0..count {
+                        inputs.push(input.clone());
+                        // Only the first tick of a run carries the reload.
+                        input.balance = None;
+                    }
+                }
+                "outcome" => {
+                    let result = fields
+                        .get(1)
+                        .and_then(|s| str_to_result(s))
+                        .ok_or_else(|| err("bad result"))?;
+                    let ticks = parse_field::<u64>(&fields, 2).ok_or_else(|| err("bad ticks"))?;
+                    let population_million =
+                        parse_field::<f32>(&fields, 3).ok_or_else(|| err("bad population"))?;
+                    let spaceship_hp =
+                        parse_field::<f32>(&fields, 4).ok_or_else(|| err("bad ship hp"))?;
+                    let score = parse_field::<u32>(&fields, 5).ok_or_else(|| err("bad score"))?;
+                    outcome = Some(ReplayOutcome {
+                        result,
+                        ticks,
+                        population_million,
+                        spaceship_hp,
+                        score,
+                    });
+                }
+                _ => return Err(err(&format!("unknown entry '{}'", fields[0]))),
+            }
+        }
+
+        if version.is_none() {
+            return Err(ReplayError::Parse {
+                line: 0,
+                message: "missing version".to_string(),
+            });
+        }
+        let seed = seed.ok_or(ReplayError::Parse {
+            line: 0,
+            message: "missing seed".to_string(),
+        })?;
+        let mode = mode.ok_or(ReplayError::Parse {
+            line: 0,
+            message: "missing mode".to_string(),
+        })?;
+        let flight_model = flight_model.ok_or(ReplayError::Parse {
+            line: 0,
+            message: "missing flight model".to_string(),
+        })?;
+
+        let balance = Balance::parse(&balance_toml).map_err(|e| ReplayError::Parse {
+            line: 0,
+            message: format!("bad balance: {}", e),
+        })?;
+        let level = match level_toml {
+            Some(level_toml) => {
+                Some(Level::parse(&level_toml).map_err(|e| ReplayError::Parse {
+                    line: 0,
+                    message: format!("bad level: {}", e),
+                })?)
+            }
+            None => None,
+        };
+
+        Ok(Replay {
+            seed,
+            mode,
+            flight_model,
+            balance,
+            level,
+            endless,
+            inputs,
+            outcome,
+        })
+    }
+}
+
+/// Feeds a recorded session back one tick at a time, in place of live input.
+pub struct ReplayPlayer {
+    replay: Replay,
+    position: usize,
+}
+
+impl ReplayPlayer {
+    pub fn new(replay: Replay) -> ReplayPlayer {
+        ReplayPlayer {
+            replay,
+            position: 0,
+        }
+    }
+
+    pub fn replay(&self) -> &Replay {
+        &self.replay
+    }
+
+    pub fn next_input(&mut self) -> Option<&TickInput> {
+        let input = self.replay.inputs.get(self.position);
+        if input.is_some() {
+            self.position += 1;
+        }
+        input
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.position >= self.replay.inputs.len()
+    }
+
+    pub fn rewind(&mut self) {
+        self.position = 0;
+    }
+}
+
+fn same_input(first: &TickInput, second: &TickInput) -> bool {
+    first.balance.is_none()
+        && second.balance.is_none()
+        && first.controls.left_right == second.controls.left_right
+        && first.controls.up_down == second.controls.up_down
+        && first.controls.move_x.to_bits() == second.controls.move_x.to_bits()
+        && first.controls.move_y.to_bits() == second.controls.move_y.to_bits()
+        && first.controls.shooting == second.controls.shooting
+        && first.controls.aim_x.to_bits() == second.controls.aim_x.to_bits()
+        && first.controls.aim_y.to_bits() == second.controls.aim_y.to_bits()
+        && first.controls.aim_dir_x.to_bits() == second.controls.aim_dir_x.to_bits()
+        && first.controls.aim_dir_y.to_bits() == second.controls.aim_dir_y.to_bits()
+}
+
+fn parse_field<T: std::str::FromStr>(fields: &[&str], index: usize) -> Option<T> {
+    fields.get(index).and_then(|s| s.parse::<T>().ok())
+}
+
+fn direction_to_str(direction: Option<Direction>) -> &'static str {
+    match direction {
+        Some(Direction::Up) => "U",
+        Some(Direction::Down) => "D",
+        Some(Direction::Left) => "L",
+        Some(Direction::Right) => "R",
+        None => "-",
+    }
+}
+
+fn str_to_direction(s: &str) -> Option<Option<Direction>> {
+    match s {
+        "U" => Some(Some(Direction::Up)),
+        "D" => Some(Some(Direction::Down)),
+        "L" => Some(Some(Direction::Left)),
+        "R" => Some(Some(Direction::Right)),
+        "-" => Some(None),
+        _ => None,
+    }
+}
+
+pub fn result_to_str(result: &GameVictoryResult) -> &'static str {
+    match result {
+        GameVictoryResult::ShipDestroyed => "ShipDestroyed",
+        GameVictoryResult::EveryoneDead => "EveryoneDead",
+        GameVictoryResult::OverPopulation => "OverPopulation",
+        GameVictoryResult::Victory => "Victory",
+        GameVictoryResult::TimeUp => "TimeUp",
+        GameVictoryResult::EarthOverrun => "EarthOverrun",
+    }
+}
+
+fn str_to_result(s: &str) -> Option<GameVictoryResult> {
+    match s {
+        "ShipDestroyed" => Some(GameVictoryResult::ShipDestroyed),
+        "EveryoneDead" => Some(GameVictoryResult::EveryoneDead),
+        "OverPopulation" => Some(GameVictoryResult::OverPopulation),
+        "Victory" => Some(GameVictoryResult::Victory),
+        "TimeUp" => Some(GameVictoryResult::TimeUp),
+        "EarthOverrun" => Some(GameVictoryResult::EarthOverrun),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::level::{Goal, PlacedMeteor, Wave};
+    use crate::meteor::MeteorKind;
+    use crate::simulation::Simulation;
+
+    fn input(tick: u32) -> TickInput {
+        TickInput {
+            controls: Controls {
+                left_right: if tick % 300 < 150 {
+                    Some(Direction::Left)
+                } else {
+                    Some(Direction::Right)
+                },
+                up_down: if tick % 500 < 100 {
+                    Some(Direction::Down)
+                } else {
+                    None
+                },
+                shooting: tick % 100 < 60,
+                aim_x: (tick as f32 * 0.02).sin() * 0.5 + 0.5,
+                aim_y: 0.25,
+                ..Default::default()
+            },
+            balance: None,
+        }
+    }
+
+    fn start(replay: &Replay) -> Simulation {
+        Simulation::new(
+            replay.seed,
+            replay.mode,
+            replay.flight_model,
+            replay.balance.clone(),
+            replay.level.clone(),
+            replay.endless,
+        )
+    }
+
+    /// The state a replay has to reproduce, with floats compared bit for bit.
+    fn final_state(sim: &Simulation) -> (u64, Vec<(usize, u32, u32)>, u32, u32) {
+        let transforms = sim
+            .world
+            .transforms
+            .iter()
+            .map(|(&entity, t)| (entity, t.pos_x.to_bits(), t.pos_y.to_bits()))
+            .collect();
+        (
+            sim.ticks,
+            transforms,
+            sim.population_million.to_bits(),
+            sim.spaceship_hp.to_bits(),
+        )
+    }
+
+    #[test]
+    fn replay_survives_a_round_trip() {
+        let balance = Balance::default();
+        let mut recording = Replay::new(
+            9,
+            GameMode::Classic,
+            FlightModel::Classic,
+            balance.clone(),
+            None,
+            false,
+        );
+        let mut sim = start(&recording);
+        for tick in 0..2000 {
+            let mut input = input(tick);
+            if tick == 200 {
+                input.balance = Some(Balance {
+                    meteor_base_spawn_interval: 0.9,
+                    ..balance.clone()
+                });
+            }
+            recording.record(&input);
+            sim.tick(&input);
+            if sim.victory_result.is_some() {
+                recording.finish(&sim);
+                break;
+            }
+        }
+
+        // The run has to get as far as the reload for it to be covered.
+        assert!(recording.inputs.len() > 200);
+        let parsed = Replay::parse(&recording.serialize()).unwrap();
+        assert_eq!(parsed.inputs.len(), recording.inputs.len());
+        assert_eq!(parsed.outcome, recording.outcome);
+        let mut replayed = start(&parsed);
+        let mut player = ReplayPlayer::new(parsed);
+        while let Some(input) = player.next_input() {
+            replayed.tick(input);
+        }
+        assert_eq!(final_state(&replayed), final_state(&sim));
+        assert_eq!(replayed.victory_result, sim.victory_result);
+        assert_eq!(ReplayOutcome::of(&replayed), recording.outcome);
+    }
+
+    #[test]
+    fn level_replay_with_an_outcome_survives_a_round_trip() {
+        let mut level = Level::new("Round trip");
+        level.win = Goal::Survive { seconds: 4.0 };
+        level.waves.push(Wave {
+            start: 0.5,
+            count: 6,
+            interval: 0.4,
+            min_velocity: Some(0.004),
+            max_velocity: Some(0.006),
+            ..Default::default()
+        });
+        level.meteors.push(PlacedMeteor {
+            x: 0.8,
+            y: 0.2,
+            vel_x: -0.001,
+            vel_y: 0.0005,
+            radius: 0.02,
+            kind: MeteorKind::Iron,
+        });
+        let mut recording = Replay::new(
+            4,
+            GameMode::Gravity,
+            FlightModel::Thrust,
+            Balance::default(),
+            Some(level),
+            false,
+        );
+        let mut sim = start(&recording);
+        for tick in 0..1000 {
+            let input = input(tick);
+            recording.record(&input);
+            sim.tick(&input);
+            if sim.victory_result.is_some() {
+                recording.finish(&sim);
+                break;
+            }
+        }
+
+        // Without an outcome the outcome line is never written or read.
+        assert!(recording.outcome.is_some());
+        let parsed = Replay::parse(&recording.serialize()).unwrap();
+        assert_eq!(parsed.outcome, recording.outcome);
+        assert_eq!(parsed.level, recording.level);
+        assert_eq!(parsed.mode, recording.mode);
+        assert_eq!(parsed.flight_model, recording.flight_model);
+        let mut replayed = start(&parsed);
+        let mut player = ReplayPlayer::new(parsed);
+        while let Some(input) = player.next_input() {
+            replayed.tick(input);
+        }
+        assert_eq!(final_state(&replayed), final_state(&sim));
+        assert_eq!(ReplayOutcome::of(&replayed), recording.outcome);
+    }
+
+    fn header(version: u32) -> String {
+        let mut text = format!(
+            "{}\nversion {}\nseed 1\nmode classic\nflight classic\nendless 0\n",
+            REPLAY_HEADER, version
+        );
+        for line in Balance::default().to_toml().lines() {
+            text.push_str(&format!("balance {}\n", line));
+        }
+        text
+    }
+
+    #[test]
+    fn parse_accepts_a_minimal_replay() {
+        let text = format!("{}input 3 L - 0 0 1 0.5 0.5 0 0\n", header(REPLAY_VERSION));
+        let replay = Replay::parse(&text).unwrap();
+        assert_eq!(replay.inputs.len(), 3);
+        assert!(replay.inputs[2].controls.shooting);
+    }
+
+    #[test]
+    fn parse_rejects_another_version() {
+        let text = header(REPLAY_VERSION - 1);
+        match Replay::parse(&text) {
+            Err(ReplayError::UnsupportedVersion(version)) => {
+                assert_eq!(version, REPLAY_VERSION - 1)
+            }
+            other => panic!("expected an unsupported version, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn parse_rejects_a_truncated_input_line() {
+        let text = format!("{}input 3 L - 0 0 1\n", header(REPLAY_VERSION));
+        match Replay::parse(&text) {
+            Err(ReplayError::Parse { line, .. }) => assert_eq!(line, text.lines().count()),
+            other => panic!("expected a parse error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn parse_rejects_garbage() {
+        let bad_field = format!(
+            "{}input 3 L - 0 0 yes 0.5 0.5 0 0\n",
+            header(REPLAY_VERSION)
+        );
+        assert!(matches!(
+            Replay::parse(&bad_field),
+            Err(ReplayError::Parse { .. })
+        ));
+        let unknown = format!("{}flurb 12\n", header(REPLAY_VERSION));
+        assert!(matches!(
+            Replay::parse(&unknown),
+            Err(ReplayError::Parse { .. })
+        ));
+    }
+}
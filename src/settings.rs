@@ -0,0 +1,307 @@
+use std::fmt;
+use std::fs;
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+use crate::simulation::FlightModel;
+
+/// Player preferences that are kept between launches.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Settings {
+    pub flight_model: FlightModel,
+    pub keymap: Keymap,
+}
+
+/// Something the player can do, bound to inputs through the `Keymap`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Action {
+    MoveUp,
+    MoveDown,
+    MoveLeft,
+    MoveRight,
+    Shoot,
+    /// Starts the game from the title screen, or a new one once it is over.
+    Restart,
+    Pause,
+    /// Leaves the game. During a run it opens the pause menu instead, and in the
+    /// level editor it goes back to the title screen.
+    Quit,
+    /// Switches between the flight models on the title screen.
+    ToggleFlightModel,
+    /// Opens this list of bindings from the title screen.
+    EditBindings,
+    /// Opens the level editor from the title screen.
+    OpenEditor,
+    // The rest only do something in the level editor.
+    EditorSave,
+    /// Plays the level being edited.
+    EditorTest,
+    /// Changes the kind of meteor placed, or of the selection.
+    EditorKind,
+    /// Makes the selected meteor smaller, or its wave send fewer.
+    EditorShrink,
+    EditorGrow,
+    /// Moves the selected wave, or the cursor, along the timeline.
+    EditorEarlier,
+    EditorLater,
+    /// Changes the time between the meteors of the selected wave.
+    EditorShorterInterval,
+    EditorLongerInterval,
+    /// Changes the edges the selected wave comes in from.
+    EditorEdges,
+    EditorAddWave,
+    EditorGoal,
+    EditorDelete,
+}
+
+impl Action {
+    pub const ALL: [Action; 24] = [
+        Action::MoveUp,
+        Action::MoveDown,
+        Action::MoveLeft,
+        Action::MoveRight,
+        Action::Shoot,
+        Action::Restart,
+        Action::Pause,
+        Action::Quit,
+        Action::ToggleFlightModel,
+        Action::EditBindings,
+        Action::OpenEditor,
+        Action::EditorSave,
+        Action::EditorTest,
+        Action::EditorKind,
+        Action::EditorShrink,
+        Action::EditorGrow,
+        Action::EditorEarlier,
+        Action::EditorLater,
+        Action::EditorShorterInterval,
+        Action::EditorLongerInterval,
+        Action::EditorEdges,
+        Action::EditorAddWave,
+        Action::EditorGoal,
+        Action::EditorDelete,
+    ];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Action::MoveUp => "Move up",
+            Action::MoveDown => "Move down",
+            Action::MoveLeft => "Move left",
+            Action::MoveRight => "Move right",
+            Action::Shoot => "Shoot",
+            Action::Restart => "Start / restart",
+            Action::Pause => "Pause",
+            Action::Quit => "Quit",
+            Action::ToggleFlightModel => "Change flight model",
+            Action::EditBindings => "Edit controls",
+            Action::OpenEditor => "Level editor",
+            Action::EditorSave => "Editor: save",
+            Action::EditorTest => "Editor: test play",
+            Action::EditorKind => "Editor: meteor kind",
+            Action::EditorShrink => "Editor: smaller / fewer",
+            Action::EditorGrow => "Editor: bigger / more",
+            Action::EditorEarlier => "Editor: earlier",
+            Action::EditorLater => "Editor: later",
+            Action::EditorShorterInterval => "Editor: shorter interval",
+            Action::EditorLongerInterval => "Editor: longer interval",
+            Action::EditorEdges => "Editor: wave edges",
+            Action::EditorAddWave => "Editor: new wave",
+            Action::EditorGoal => "Editor: goal",
+            Action::EditorDelete => "Editor: remove",
+        }
+    }
+
+    /// Editor actions are only looked at in the level editor, where the game's
+    /// aren't, so the two can share inputs.
+    pub fn in_editor(self) -> bool {
+        matches!(
+            self,
+            Action::EditorSave
+                | Action::EditorTest
+                | Action::EditorKind
+                | Action::EditorShrink
+                | Action::EditorGrow
+                | Action::EditorEarlier
+                | Action::EditorLater
+                | Action::EditorShorterInterval
+                | Action::EditorLongerInterval
+                | Action::EditorEdges
+                | Action::EditorAddWave
+                | Action::EditorGoal
+                | Action::EditorDelete
+        )
+    }
+}
+
+/// The inputs bound to every action, by name. What the names mean is up to the
+/// frontend; an action can have any number of inputs, or none.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Keymap {
+    pub move_up: Vec<String>,
+    pub move_down: Vec<String>,
+    pub move_left: Vec<String>,
+    pub move_right: Vec<String>,
+    pub shoot: Vec<String>,
+    pub restart: Vec<String>,
+    pub pause: Vec<String>,
+    pub quit: Vec<String>,
+    pub toggle_flight_model: Vec<String>,
+    pub edit_bindings: Vec<String>,
+    pub open_editor: Vec<String>,
+    pub editor_save: Vec<String>,
+    pub editor_test: Vec<String>,
+    pub editor_kind: Vec<String>,
+    pub editor_shrink: Vec<String>,
+    pub editor_grow: Vec<String>,
+    pub editor_earlier: Vec<String>,
+    pub editor_later: Vec<String>,
+    pub editor_shorter_interval: Vec<String>,
+    pub editor_longer_interval: Vec<String>,
+    pub editor_edges: Vec<String>,
+    pub editor_add_wave: Vec<String>,
+    pub editor_goal: Vec<String>,
+    pub editor_delete: Vec<String>,
+}
+
+impl Default for Keymap {
+    fn default() -> Self {
+        let names = |names: &[&str]| names.iter().map(|name| name.to_string()).collect();
+        Keymap {
+            move_up: names(&["W", "Up", "Pad DPadUp"]),
+            move_down: names(&["S", "Down", "Pad DPadDown"]),
+            move_left: names(&["A", "Left", "Pad DPadLeft"]),
+            move_right: names(&["D", "Right", "Pad DPadRight"]),
+            shoot: names(&["Mouse Left", "Pad RightTrigger", "Pad RightTrigger2"]),
+            restart: names(&["Return", "NumpadEnter", "R", "Pad Start", "Pad South"]),
+            pause: names(&["P", "Pad Select"]),
+            quit: names(&["Escape"]),
+            toggle_flight_model: names(&["F"]),
+            edit_bindings: names(&["B"]),
+            open_editor: names(&["E"]),
+            editor_save: names(&["S"]),
+            editor_test: names(&["T"]),
+            editor_kind: names(&["K"]),
+            editor_shrink: names(&["LBracket"]),
+            editor_grow: names(&["RBracket"]),
+            editor_earlier: names(&["Comma"]),
+            editor_later: names(&["Period"]),
+            editor_shorter_interval: names(&["Minus"]),
+            editor_longer_interval: names(&["Equals"]),
+            editor_edges: names(&["E"]),
+            editor_add_wave: names(&["N"]),
+            editor_goal: names(&["G"]),
+            editor_delete: names(&["Delete", "Back"]),
+        }
+    }
+}
+
+impl Keymap {
+    pub fn bindings(&self, action: Action) -> &Vec<String> {
+        match action {
+            Action::MoveUp => &self.move_up,
+            Action::MoveDown => &self.move_down,
+            Action::MoveLeft => &self.move_left,
+            Action::MoveRight => &self.move_right,
+            Action::Shoot => &self.shoot,
+            Action::Restart => &self.restart,
+            Action::Pause => &self.pause,
+            Action::Quit => &self.quit,
+            Action::ToggleFlightModel => &self.toggle_flight_model,
+            Action::EditBindings => &self.edit_bindings,
+            Action::OpenEditor => &self.open_editor,
+            Action::EditorSave => &self.editor_save,
+            Action::EditorTest => &self.editor_test,
+            Action::EditorKind => &self.editor_kind,
+            Action::EditorShrink => &self.editor_shrink,
+            Action::EditorGrow => &self.editor_grow,
+            Action::EditorEarlier => &self.editor_earlier,
+            Action::EditorLater => &self.editor_later,
+            Action::EditorShorterInterval => &self.editor_shorter_interval,
+            Action::EditorLongerInterval => &self.editor_longer_interval,
+            Action::EditorEdges => &self.editor_edges,
+            Action::EditorAddWave => &self.editor_add_wave,
+            Action::EditorGoal => &self.editor_goal,
+            Action::EditorDelete => &self.editor_delete,
+        }
+    }
+
+    pub fn bindings_mut(&mut self, action: Action) -> &mut Vec<String> {
+        match action {
+            Action::MoveUp => &mut self.move_up,
+            Action::MoveDown => &mut self.move_down,
+            Action::MoveLeft => &mut self.move_left,
+            Action::MoveRight => &mut self.move_right,
+            Action::Shoot => &mut self.shoot,
+            Action::Restart => &mut self.restart,
+            Action::Pause => &mut self.pause,
+            Action::Quit => &mut self.quit,
+            Action::ToggleFlightModel => &mut self.toggle_flight_model,
+            Action::EditBindings => &mut self.edit_bindings,
+            Action::OpenEditor => &mut self.open_editor,
+            Action::EditorSave => &mut self.editor_save,
+            Action::EditorTest => &mut self.editor_test,
+            Action::EditorKind => &mut self.editor_kind,
+            Action::EditorShrink => &mut self.editor_shrink,
+            Action::EditorGrow => &mut self.editor_grow,
+            Action::EditorEarlier => &mut self.editor_earlier,
+            Action::EditorLater => &mut self.editor_later,
+            Action::EditorShorterInterval => &mut self.editor_shorter_interval,
+            Action::EditorLongerInterval => &mut self.editor_longer_interval,
+            Action::EditorEdges => &mut self.editor_edges,
+            Action::EditorAddWave => &mut self.editor_add_wave,
+            Action::EditorGoal => &mut self.editor_goal,
+            Action::EditorDelete => &mut self.editor_delete,
+        }
+    }
+
+    /// Binds `name` to `action` alone, taking it away from any other action that
+    /// is looked at in the same place: the editor's or the game's.
+    pub fn bind(&mut self, action: Action, name: &str) {
+        for &other in Action::ALL.iter() {
+            if other.in_editor() == action.in_editor() {
+                self.bindings_mut(other).retain(|bound| bound != name);
+            }
+        }
+        self.bindings_mut(action).push(name.to_string());
+    }
+}
+
+#[derive(Debug)]
+pub enum SettingsError {
+    Io(String),
+    Parse(String),
+}
+
+impl fmt::Display for SettingsError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SettingsError::Io(message) => write!(f, "{}", message),
+            SettingsError::Parse(message) => write!(f, "{}", message),
+        }
+    }
+}
+
+impl std::error::Error for SettingsError {}
+
+impl Settings {
+    /// Reads the settings file, or returns the defaults if there isn't one yet.
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Settings, SettingsError> {
+        let path = path.as_ref();
+        if !path.exists() {
+            return Ok(Settings::default());
+        }
+        let contents = fs::read_to_string(path)
+            .map_err(|e| SettingsError::Io(format!("{}: {}", path.display(), e)))?;
+        toml::from_str(&contents).map_err(|e| SettingsError::Parse(e.to_string()))
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), SettingsError> {
+        let path = path.as_ref();
+        let contents = toml::to_string(self).expect("Settings always serialize");
+        fs::write(path, contents)
+            .map_err(|e| SettingsError::Io(format!("{}: {}", path.display(), e)))
+    }
+}
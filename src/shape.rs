@@ -0,0 +1,201 @@
+//! Bumpy outlines for meteors. Every meteor gets its own shape from a seed: a few
+//! layers of noise around the circle with some craters pressed in. The outline is
+//! what gets drawn and also what collisions test against.
+
+use std::f32::consts::PI;
+
+use rand::prelude::*;
+
+use crate::simulation::GameRng;
+
+/// Points stored around an outline; anything in between is interpolated.
+pub const OUTLINE_POINTS: usize = 64;
+
+/// Layers of noise, each with twice the detail and half the strength of the last.
+const NOISE_OCTAVES: u32 = 3;
+/// Random values around the circle in the coarsest layer of noise.
+const NOISE_BASE_POINTS: usize = 5;
+/// Half the width of a crater, in radians.
+const CRATER_MIN_WIDTH: f32 = 0.2;
+const CRATER_MAX_WIDTH: f32 = 0.5;
+/// Bounds for the outline, as a share of the radius, so a meteor never turns
+/// into a sliver or a spike.
+const MIN_FACTOR: f32 = 0.6;
+const MAX_FACTOR: f32 = 1.3;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct ShapeSettings {
+    /// How far the noise pushes the outline in or out, as a share of the radius.
+    pub roughness: f32,
+    pub crater_count: u32,
+    /// How deep the deepest crater goes, as a share of the radius.
+    pub crater_depth: f32,
+}
+
+/// Distance from the centre to the edge all the way around, as a share of the
+/// collider's radius. Angles are measured from straight down (+y) towards +x, so
+/// the direction `(dx, dy)` has the angle `dx.atan2(dy)`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Outline {
+    factors: Vec<f32>,
+    /// The largest of `factors`, kept so collision tests don't rescan them.
+    max_factor: f32,
+}
+
+impl Outline {
+    pub fn generate(seed: u64, settings: &ShapeSettings) -> Outline {
+        let mut rng = GameRng::seed_from_u64(seed);
+
+        let mut layers = Vec::new();
+        let mut total_strength = 0.0;
+        for octave in 0..NOISE_OCTAVES {
+            let points = NOISE_BASE_POINTS << octave;
+            let values: Vec<f32> = (0..points).map(|_| rng.gen_range(-1.0, 1.0)).collect();
+            let strength = 0.5f32.powi(octave as i32);
+            total_strength += strength;
+            layers.push((values, strength));
+        }
+
+        let craters: Vec<(f32, f32, f32)> = (0..settings.crater_count)
+            .map(|_| {
+                let center = rng.gen_range(0.0, 2.0 * PI);
+                let width = rng.gen_range(CRATER_MIN_WIDTH, CRATER_MAX_WIDTH);
+                let depth = settings.crater_depth * rng.gen_range(0.5, 1.0);
+                (center, width, depth)
+            })
+            .collect();
+
+        let factors = (0..OUTLINE_POINTS)
+            .map(|i| {
+                let angle = i as f32 * 2.0 * PI / OUTLINE_POINTS as f32;
+                let noise: f32 = layers
+                    .iter()
+                    .map(|(values, strength)| periodic_noise(values, angle) * strength)
+                    .sum();
+                let dents: f32 = craters
+                    .iter()
+                    .map(|&(center, width, depth)| crater(angle, center, width, depth))
+                    .sum();
+                let factor = 1.0 + settings.roughness * noise / total_strength - dents;
+                factor.clamp(MIN_FACTOR, MAX_FACTOR)
+            })
+            .collect::<Vec<f32>>();
+        let max_factor = factors.iter().cloned().fold(0.0, f32::max);
+        Outline {
+            factors,
+            max_factor,
+        }
+    }
+
+    /// Share of the radius the edge is from the centre at `angle`.
+    pub fn factor_at(&self, angle: f32) -> f32 {
+        let position = angle.rem_euclid(2.0 * PI) / (2.0 * PI) * self.factors.len() as f32;
+        let index = position.floor() as usize % self.factors.len();
+        let next = (index + 1) % self.factors.len();
+        let t = position - position.floor();
+        self.factors[index] + (self.factors[next] - self.factors[index]) * t
+    }
+
+    /// Share of the radius the edge is from the centre in the direction `(dx, dy)`.
+    pub fn factor_towards(&self, dx: f32, dy: f32) -> f32 {
+        self.factor_at(dx.atan2(dy))
+    }
+
+    /// The furthest the edge gets from the centre, as a share of the radius.
+    pub fn max_factor(&self) -> f32 {
+        self.max_factor
+    }
+}
+
+/// Smoothly interpolates random values spread evenly around the circle, wrapping
+/// from the last back to the first.
+fn periodic_noise(values: &[f32], angle: f32) -> f32 {
+    let position = angle / (2.0 * PI) * values.len() as f32;
+    let index = position.floor() as usize % values.len();
+    let next = (index + 1) % values.len();
+    let t = position - position.floor();
+    let smooth = (1.0 - (t * PI).cos()) / 2.0;
+    values[index] + (values[next] - values[index]) * smooth
+}
+
+/// How far a crater at `center` pushes the outline in at `angle`.
+fn crater(angle: f32, center: f32, width: f32, depth: f32) -> f32 {
+    let distance = ((angle - center + PI).rem_euclid(2.0 * PI) - PI).abs();
+    if distance >= width {
+        return 0.0;
+    }
+    let falloff = 1.0 - (distance / width) * (distance / width);
+    depth * falloff * falloff
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn settings() -> ShapeSettings {
+        ShapeSettings {
+            roughness: 0.3,
+            crater_count: 3,
+            crater_depth: 0.2,
+        }
+    }
+
+    #[test]
+    fn smooth_settings_give_a_circle() {
+        let smooth = ShapeSettings {
+            roughness: 0.0,
+            crater_count: 0,
+            crater_depth: 0.0,
+        };
+        let outline = Outline::generate(5, &smooth);
+        for i in 0..16 {
+            assert_eq!(outline.factor_at(i as f32 * 0.4), 1.0);
+        }
+        assert_eq!(outline.max_factor(), 1.0);
+    }
+
+    #[test]
+    fn stored_points_are_hit_exactly() {
+        let outline = Outline::generate(11, &settings());
+        let step = 2.0 * PI / OUTLINE_POINTS as f32;
+        for (i, &factor) in outline.factors.iter().enumerate() {
+            assert!((outline.factor_at(i as f32 * step) - factor).abs() < 1e-5);
+        }
+        // Halfway between two points is halfway between their factors.
+        let halfway = (outline.factors[3] + outline.factors[4]) / 2.0;
+        assert!((outline.factor_at(3.5 * step) - halfway).abs() < 1e-5);
+    }
+
+    #[test]
+    fn angles_wrap_around() {
+        let outline = Outline::generate(11, &settings());
+        for &angle in &[0.0, 1.0, 3.0, 6.0] {
+            let factor = outline.factor_at(angle);
+            assert!((outline.factor_at(angle + 2.0 * PI) - factor).abs() < 1e-4);
+            assert!((outline.factor_at(angle - 2.0 * PI) - factor).abs() < 1e-4);
+        }
+    }
+
+    #[test]
+    fn directions_map_to_angles_from_straight_down() {
+        let outline = Outline::generate(11, &settings());
+        let quarter = OUTLINE_POINTS / 4;
+        assert_eq!(outline.factor_towards(0.0, 1.0), outline.factors[0]);
+        assert!((outline.factor_towards(1.0, 0.0) - outline.factors[quarter]).abs() < 1e-5);
+        assert!((outline.factor_towards(0.0, -1.0) - outline.factors[2 * quarter]).abs() < 1e-5);
+    }
+
+    #[test]
+    fn max_factor_bounds_the_outline() {
+        for seed in 0..20 {
+            let outline = Outline::generate(seed, &settings());
+            let max_factor = outline.max_factor();
+            assert!((MIN_FACTOR..=MAX_FACTOR).contains(&max_factor));
+            for i in 0..1000 {
+                let angle = i as f32 * 2.0 * PI / 1000.0;
+                assert!(outline.factor_at(angle) <= max_factor);
+            }
+            assert!(outline.factors.contains(&max_factor));
+        }
+    }
+}
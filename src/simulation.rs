@@ -0,0 +1,2188 @@
+use std::collections::HashSet;
+
+use rand::prelude::*;
+use rand_pcg::Pcg32;
+use serde::{Deserialize, Serialize};
+
+use crate::balance::Balance;
+use crate::collision::{dist_transform, find_collisions, Collision};
+use crate::ecs::{
+    Burning, Circle, Collider, ColliderShape, Color, Entity, Faction, GravityWell, Heading, Homing,
+    Lifetime, Meteor, RenderCoords, SlowField, Sweep, TextLabel, Texture, TrailEmitter, Transform,
+    World,
+};
+use crate::level::{Goal, Level, Position, Wave};
+use crate::meteor::{pick_kind, MeteorKind, TrailRule};
+use crate::shape::Outline;
+
+use rand::{
+    distributions::{Distribution, Standard},
+    Rng,
+};
+
+/// Portable, seedable generator used for everything random in a session, so a run
+/// can be reproduced from its seed.
+pub type GameRng = Pcg32;
+
+/// Fixed rate the simulation advances at. Durations in the balance are given in
+/// seconds of simulation time at this rate.
+pub const TICKS_PER_SECOND: u32 = 60;
+
+pub const STARS_COUNT: usize = 200;
+pub const STAR_MIN_SIZE: f32 = 0.0001;
+pub const STAR_MAX_SIZE: f32 = 0.0005;
+
+/// Where the ship starts and the Earth sits, unless the level places them.
+pub const SHIP_START: Position = Position { x: 0.1, y: 0.3 };
+pub const EARTH_POSITION: Position = Position { x: 0.5, y: 0.5 };
+pub const SHIP_RADIUS: f32 = 0.02;
+pub const EARTH_RADIUS: f32 = 0.1;
+
+/// Aiming closer to the ship than this gives no direction to shoot in, so the
+/// ship shoots where it faces instead.
+const MIN_AIM_DISTANCE: f32 = 1e-6;
+
+/// Roughly how far the edge of `ship.png` is from its centre, as a share of the
+/// ship's radius, every 11.25 degrees from the tip of one point to the next. All
+/// four points of the sprite look the same.
+///
+/// A hand-made approximation eyeballed from the sprite, not traced from it, so
+/// it won't follow changes to the image.
+const SHIP_PROFILE: [f32; 8] = [0.98, 0.84, 0.73, 0.68, 0.66, 0.68, 0.73, 0.81];
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum GameVictoryResult {
+    ShipDestroyed,
+    EveryoneDead,
+    OverPopulation,
+    Victory,
+    /// The level's time limit ran out.
+    TimeUp,
+    /// More meteors hit the Earth than the level allows.
+    EarthOverrun,
+}
+
+/// Rule sets a session can be played with.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum GameMode {
+    #[default]
+    Classic,
+    /// The Earth pulls on meteors, projectiles and the ship.
+    Gravity,
+}
+
+impl GameMode {
+    pub fn has_gravity(self) -> bool {
+        self == GameMode::Gravity
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            GameMode::Classic => "classic",
+            GameMode::Gravity => "gravity",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<GameMode> {
+        match name {
+            "classic" => Some(GameMode::Classic),
+            "gravity" => Some(GameMode::Gravity),
+            _ => None,
+        }
+    }
+}
+
+/// How the ship responds to the movement keys.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FlightModel {
+    /// Every key accelerates the ship along its own screen axis.
+    #[default]
+    Classic,
+    /// Asteroids style: the ship turns, thrusts forward and drifts.
+    Thrust,
+}
+
+impl FlightModel {
+    pub fn name(self) -> &'static str {
+        match self {
+            FlightModel::Classic => "classic",
+            FlightModel::Thrust => "thrust",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<FlightModel> {
+        match name {
+            "classic" => Some(FlightModel::Classic),
+            "thrust" => Some(FlightModel::Thrust),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Default, Clone, Debug)]
+pub struct Controls {
+    pub left_right: Option<Direction>,
+    pub up_down: Option<Direction>,
+    /// Analog movement from a stick, from -1 to 1 on each axis. Only used on an
+    /// axis while no direction is held on it.
+    pub move_x: f32,
+    pub move_y: f32,
+    pub shooting: bool,
+    /// Where the ship is aiming, in world coordinates.
+    pub aim_x: f32,
+    pub aim_y: f32,
+    /// Direction to shoot in when aiming with a stick. Overrides `aim_x` and
+    /// `aim_y` while it is not zero.
+    pub aim_dir_x: f32,
+    pub aim_dir_y: f32,
+}
+
+impl Controls {
+    /// Horizontal movement from -1 (left) to 1 (right).
+    pub fn axis_x(&self) -> f32 {
+        match self.left_right {
+            Some(Direction::Left) => -1.0,
+            Some(Direction::Right) => 1.0,
+            _ => clamp(self.move_x, -1.0, 1.0),
+        }
+    }
+
+    /// Vertical movement from -1 (up) to 1 (down).
+    pub fn axis_y(&self) -> f32 {
+        match self.up_down {
+            Some(Direction::Up) => -1.0,
+            Some(Direction::Down) => 1.0,
+            _ => clamp(self.move_y, -1.0, 1.0),
+        }
+    }
+}
+
+/// Everything the simulation needs from the outside world to advance one tick.
+#[derive(Default, Clone, Debug)]
+pub struct TickInput {
+    pub controls: Controls,
+    /// New tunables to switch to before this tick runs (e.g. after a config reload).
+    pub balance: Option<Balance>,
+}
+
+/// Things that happened during a tick which the frontend may want to react to
+/// (sounds, floating text, end screens).
+#[derive(Clone, Debug)]
+pub enum SimEvent {
+    Shot,
+    ShipHit { pos_x: f32, pos_y: f32 },
+    EarthHit { pos_x: f32, pos_y: f32, damage: f32 },
+    MeteorExploded { pos_x: f32, pos_y: f32 },
+    MeteorDented { pos_x: f32, pos_y: f32 },
+    MeteorBounced,
+    OverpopulationWarning,
+    WaveStarted(usize),
+    Finished(GameVictoryResult),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Direction {
+    Up,
+    Down,
+    Left,
+    Right,
+}
+
+impl Distribution<Direction> for Standard {
+    fn sample<R: Rng + ?Sized>(&self, rng: &mut R) -> Direction {
+        match rng.gen_range(0, 4) {
+            0 => Direction::Up,
+            1 => Direction::Down,
+            2 => Direction::Left,
+            _ => Direction::Right,
+        }
+    }
+}
+
+/// What an endless run is worth, and what it was earned with.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Score {
+    pub meteors_destroyed: u32,
+    /// Millions of people still alive. Nobody counts after an overpopulation
+    /// ending: famine and war take them.
+    pub population_million: f32,
+    pub seconds: f32,
+    pub meteor_points: u32,
+    pub population_points: u32,
+    pub time_points: u32,
+}
+
+impl Score {
+    pub fn total(&self) -> u32 {
+        self.meteor_points + self.population_points + self.time_points
+    }
+}
+
+/// The game rules, free of any windowing, rendering or audio.
+pub struct Simulation {
+    pub world: World,
+    pub spaceship_id: Option<Entity>,
+    pub earth_id: Option<Entity>,
+    pub seed: u64,
+    pub mode: GameMode,
+    pub flight_model: FlightModel,
+    pub balance: Balance,
+    /// Scripted waves to play instead of the endless stream of meteors.
+    pub level: Option<Level>,
+    /// No victory: progress runs past full, meteors keep coming faster and the
+    /// run is scored instead. Has no effect on levels.
+    pub endless: bool,
+    rng: GameRng,
+    // Timers below count in ticks; intervals from the balance are converted with
+    // `seconds_to_ticks`.
+    next_meteor_spawn: Option<f32>,
+    // Meteors sent in so far by each wave of the level.
+    wave_spawned: Vec<u32>,
+    pub victory_result: Option<GameVictoryResult>,
+    pub population_million: f32,
+    pub victory_progress: f32,
+    pub spaceship_hp: f32,
+    pub earth_hits: u32,
+    /// Meteors shot apart, fragments included.
+    pub meteors_destroyed: u32,
+    /// Number of ticks simulated so far. This is the only clock the game runs on.
+    pub ticks: u64,
+    next_overpop_warning: f32,
+    next_overpop_warning_enabled: bool,
+    next_shot: f32,
+    was_shooting: bool,
+    events: Vec<SimEvent>,
+}
+
+impl Simulation {
+    /// Creates a new session. The same seed with the same sequence of inputs always
+    /// produces the same game.
+    pub fn new(
+        seed: u64,
+        mode: GameMode,
+        flight_model: FlightModel,
+        balance: Balance,
+        level: Option<Level>,
+        endless: bool,
+    ) -> Simulation {
+        let wave_count = level.as_ref().map_or(0, |level| level.waves.len());
+        let mut sim = Simulation {
+            world: World::new(),
+            spaceship_id: None,
+            earth_id: None,
+            seed,
+            mode,
+            flight_model,
+            level,
+            endless,
+            rng: GameRng::seed_from_u64(seed),
+            next_meteor_spawn: None,
+            wave_spawned: vec![0; wave_count],
+            victory_result: None,
+            population_million: balance.population_start,
+            spaceship_hp: 100.0,
+            earth_hits: 0,
+            meteors_destroyed: 0,
+            victory_progress: 0.0,
+            ticks: 0,
+            next_overpop_warning: 0.0,
+            next_overpop_warning_enabled: true,
+            next_shot: 0.0,
+            was_shooting: false,
+            events: Vec::new(),
+            balance,
+        };
+        // Stars go first so they are drawn behind everything else.
+        sim.add_stars();
+        sim.add_spaceship();
+        sim.add_earth();
+        sim.add_placed_meteors();
+
+        sim
+    }
+
+    /// Spawns something that takes part in the game rules: it is drawn as a
+    /// circle, collides with that same circle and belongs to a faction.
+    fn spawn_body(
+        &mut self,
+        transform: Transform,
+        faction: Faction,
+        radius: f32,
+        color: Color,
+        texture: Option<Texture>,
+    ) -> Entity {
+        let entity = self.world.spawn(transform);
+        self.world
+            .colliders
+            .insert(entity, Collider::circle(radius));
+        self.world.factions.insert(entity, faction);
+        self.world.circles.insert(
+            entity,
+            Circle {
+                radius,
+                color,
+                texture,
+                render_coords: Default::default(),
+            },
+        );
+        entity
+    }
+
+    /// Makes collisions follow the entity's path instead of only its end position.
+    fn add_sweep(&mut self, entity: Entity) {
+        let transform = &self.world.transforms[&entity];
+        let sweep = Sweep {
+            from_x: transform.pos_x,
+            from_y: transform.pos_y,
+        };
+        self.world.sweeps.insert(entity, sweep);
+    }
+
+    /// Spawns a purely visual circle.
+    fn spawn_decoration(
+        &mut self,
+        transform: Transform,
+        radius: f32,
+        color: Color,
+        texture: Option<Texture>,
+        render_coords: RenderCoords,
+    ) -> Entity {
+        let entity = self.world.spawn(transform);
+        self.world.circles.insert(
+            entity,
+            Circle {
+                radius,
+                color,
+                texture,
+                render_coords,
+            },
+        );
+        entity
+    }
+
+    fn add_spaceship(&mut self) {
+        let start = self
+            .level
+            .as_ref()
+            .and_then(|level| level.ship)
+            .unwrap_or(SHIP_START);
+        let entity = self.spawn_body(
+            Transform {
+                pos_x: start.x,
+                pos_y: start.y,
+                ..Default::default()
+            },
+            Faction::Ship,
+            SHIP_RADIUS,
+            [0.5, 0.5, 0.7, 1.0],
+            Some(Texture::Ship),
+        );
+        self.world.colliders.get_mut(&entity).unwrap().shape = ship_shape();
+        self.add_sweep(entity);
+        if self.flight_model == FlightModel::Thrust {
+            self.world.headings.insert(entity, Heading::default());
+        }
+        self.spaceship_id = Some(entity);
+    }
+
+    fn add_earth(&mut self) {
+        let position = self
+            .level
+            .as_ref()
+            .and_then(|level| level.earth)
+            .unwrap_or(EARTH_POSITION);
+        let center = Transform {
+            pos_x: position.x,
+            pos_y: position.y,
+            ..Default::default()
+        };
+        let entity = self.spawn_body(
+            center.clone(),
+            Faction::Earth,
+            EARTH_RADIUS,
+            [0.3, 0.7, 0.3, 1.0],
+            Some(Texture::Earth),
+        );
+        let circle = self.world.circles.get_mut(&entity).unwrap();
+        circle.render_coords.vel_x = 0.0002;
+        circle.render_coords.vel_y = 0.0001;
+        if self.mode.has_gravity() {
+            let mass = mass(circle.radius);
+            self.world
+                .gravity_wells
+                .insert(entity, GravityWell { mass });
+        }
+        self.earth_id = Some(entity);
+
+        // atmosphere
+        self.spawn_decoration(
+            center.clone(),
+            0.108,
+            [0.0, 0.0, 0.0, 0.0],
+            None,
+            Default::default(),
+        );
+
+        // clouds
+        self.spawn_decoration(
+            center,
+            0.103,
+            [0.15, 0.15, 0.3, 0.3],
+            Some(Texture::Clouds),
+            RenderCoords {
+                vel_x: 0.0007,
+                vel_y: -0.0001,
+                ..Default::default()
+            },
+        );
+    }
+
+    fn add_placed_meteors(&mut self) {
+        let meteors = match &self.level {
+            Some(level) => level.meteors.clone(),
+            None => return,
+        };
+        for meteor in meteors {
+            let transform = Transform {
+                pos_x: meteor.x,
+                pos_y: meteor.y,
+                vel_x: meteor.vel_x,
+                vel_y: meteor.vel_y,
+                ..Default::default()
+            };
+            self.add_meteor(transform, meteor.radius, meteor.kind);
+        }
+    }
+
+    fn add_stars(&mut self) {
+        for _ in 0..STARS_COUNT {
+            let pos_x = 0.5
+                + self.rng.gen_range(0.1, 0.5) * (self.rng.gen_range(-1.0, 1.0) as f32).signum();
+            let pos_y = 0.5
+                + self.rng.gen_range(0.1, 0.5) * (self.rng.gen_range(-1.0, 1.0) as f32).signum();
+            let radius = self.rng.gen_range(STAR_MIN_SIZE, STAR_MAX_SIZE);
+            self.spawn_decoration(
+                Transform {
+                    pos_x,
+                    pos_y,
+                    ..Default::default()
+                },
+                radius,
+                [0.9, 0.9, 0.9, 0.5],
+                None,
+                Default::default(),
+            );
+        }
+    }
+
+    /// Adds a text label. It moves and expires like any other entity, but has no
+    /// effect on the game rules.
+    pub fn add_label(
+        &mut self,
+        transform: Transform,
+        label: TextLabel,
+        ttl: Option<f32>,
+    ) -> Entity {
+        let entity = self.world.spawn(transform);
+        self.world.labels.insert(entity, label);
+        if let Some(ticks_left) = ttl {
+            self.world.lifetimes.insert(entity, Lifetime { ticks_left });
+        }
+        entity
+    }
+
+    fn maybe_make_overpopulation_warning(&mut self, now: f32) {
+        if now < self.next_overpop_warning || !self.next_overpop_warning_enabled {
+            return;
+        }
+
+        self.next_overpop_warning_enabled = false;
+        self.next_overpop_warning =
+            now + seconds_to_ticks(self.balance.overpop_min_warning_interval);
+        self.events.push(SimEvent::OverpopulationWarning);
+    }
+
+    fn generate_meteor(&mut self) {
+        let min_velocity = self.balance.meteor_min_velocity;
+        let max_velocity = self.balance.meteor_max_velocity;
+
+        let meteor = Transform {
+            pos_x: 0.0,
+            pos_y: 0.0,
+            vel_x: gen_safe_range(&mut self.rng, min_velocity, max_velocity),
+            vel_y: gen_safe_range(&mut self.rng, min_velocity, max_velocity),
+            acc_x: 0.0,
+            acc_y: 0.0,
+        };
+
+        let dir: Direction = self.rng.gen();
+        let pos: f32 = self.rng.gen();
+
+        // Past full progress, endless runs only get faster: sizes and the mix
+        // of kinds stay where they ended up, while the spawn interval in `tick`
+        // keeps shrinking.
+        let progress = self.victory_progress.min(1.0);
+        let kind = pick_kind(&mut self.rng, progress);
+        let difficulty_factor = difficulty_factor(progress);
+        let radius = gen_safe_range(
+            &mut self.rng,
+            self.balance.meteor_base_min_size * difficulty_factor,
+            self.balance.meteor_base_max_size * difficulty_factor,
+        ) * kind.meteor_type().size_factor;
+        self.launch_meteor(meteor, dir, pos, radius, kind);
+    }
+
+    /// Spawns a meteor of one of the level's waves, with the wave's ranges or the
+    /// balance's where the wave has none.
+    fn generate_wave_meteor(&mut self, wave: &Wave) {
+        let balance = &self.balance;
+        let min_velocity = wave.min_velocity.unwrap_or(balance.meteor_min_velocity);
+        let max_velocity = wave.max_velocity.unwrap_or(balance.meteor_max_velocity);
+        let min_size = wave.min_size.unwrap_or(balance.meteor_base_min_size);
+        let max_size = wave.max_size.unwrap_or(balance.meteor_base_max_size);
+
+        let meteor = Transform {
+            vel_x: gen_safe_range(&mut self.rng, min_velocity, max_velocity),
+            vel_y: gen_safe_range(&mut self.rng, min_velocity, max_velocity),
+            ..Default::default()
+        };
+        let edge = *wave.edges.choose(&mut self.rng).unwrap();
+        let pos: f32 = self.rng.gen();
+        let kind = *wave.kinds.choose(&mut self.rng).unwrap();
+        let radius =
+            gen_safe_range(&mut self.rng, min_size, max_size) * kind.meteor_type().size_factor;
+        self.launch_meteor(meteor, edge.direction(), pos, radius, kind);
+    }
+
+    /// Sends every meteor of the level's waves that is due by `now`.
+    fn spawn_waves(&mut self, level: &Level, now: f32) {
+        for (index, wave) in level.waves.iter().enumerate() {
+            let start = seconds_to_ticks(wave.start);
+            let interval = seconds_to_ticks(wave.interval);
+            while self.wave_spawned[index] < wave.count
+                && now >= start + interval * self.wave_spawned[index] as f32
+            {
+                if self.wave_spawned[index] == 0 {
+                    self.events.push(SimEvent::WaveStarted(index));
+                }
+                self.wave_spawned[index] += 1;
+                self.generate_wave_meteor(wave);
+            }
+        }
+    }
+
+    /// Places a meteor just outside the edge `dir` names, `pos` of the way along
+    /// it, and turns its velocity to point into the screen.
+    fn launch_meteor(
+        &mut self,
+        mut meteor: Transform,
+        dir: Direction,
+        pos: f32,
+        radius: f32,
+        kind: MeteorKind,
+    ) {
+        match dir {
+            Direction::Up => {
+                meteor.pos_x = pos;
+                meteor.pos_y = 0.0 - radius;
+                if self.rng.gen::<f32>() > 0.5 {
+                    meteor.vel_x *= -1.0;
+                }
+            }
+            Direction::Down => {
+                meteor.pos_x = pos;
+                meteor.pos_y = 1.0 + radius;
+                meteor.vel_y *= -1.0;
+                if self.rng.gen::<f32>() > 0.5 {
+                    meteor.vel_x *= -1.0;
+                }
+            }
+            Direction::Left => {
+                meteor.pos_x = 0.0 - radius;
+                meteor.pos_y = pos;
+                if self.rng.gen::<f32>() > 0.5 {
+                    meteor.vel_y *= -1.0;
+                }
+            }
+            Direction::Right => {
+                meteor.pos_x = 1.0 + radius;
+                meteor.pos_y = pos;
+                meteor.vel_x *= -1.0;
+                if self.rng.gen::<f32>() > 0.5 {
+                    meteor.vel_y *= -1.0;
+                }
+            }
+        };
+        self.add_meteor(meteor, radius, kind);
+    }
+
+    fn add_meteor(&mut self, transform: Transform, radius: f32, kind: MeteorKind) {
+        let meteor_type = kind.meteor_type();
+        let entity = self.spawn_body(
+            transform,
+            Faction::Meteor,
+            radius,
+            [0.878, 0.603, 0.282, 1.0],
+            Some(meteor_type.texture),
+        );
+        let outline = Outline::generate(self.rng.gen(), &self.balance.meteor_shape());
+        self.world.outlines.insert(entity, outline);
+        self.add_sweep(entity);
+        self.world.burning.insert(entity, Burning);
+        self.world.meteors.insert(
+            entity,
+            Meteor {
+                kind,
+                hits_left: meteor_type.hits,
+            },
+        );
+        if let Some(rule) = meteor_type.homing {
+            self.world.homing.insert(entity, Homing { rule });
+        }
+        if let Some(rule) = meteor_type.trail {
+            let emitter = TrailEmitter {
+                rule,
+                ticks_to_next: rule.interval,
+            };
+            self.world.trail_emitters.insert(entity, emitter);
+        }
+    }
+
+    /// Leaves a patch behind a meteor that slows the ship down for a while.
+    fn add_slow_patch(&mut self, transform: Transform, radius: f32, rule: TrailRule) {
+        let entity = self.spawn_decoration(
+            transform,
+            radius,
+            [0.6, 0.85, 1.0, 0.15],
+            None,
+            Default::default(),
+        );
+        self.world.lifetimes.insert(
+            entity,
+            Lifetime {
+                ticks_left: rule.lifetime,
+            },
+        );
+        self.world.slow_fields.insert(
+            entity,
+            SlowField {
+                radius,
+                slowdown: rule.slowdown,
+            },
+        );
+    }
+
+    pub fn despawn(&mut self, entity: Entity) {
+        self.world.despawn(entity);
+        if self.spaceship_id == Some(entity) {
+            self.spaceship_id = None;
+        }
+        if self.earth_id == Some(entity) {
+            self.earth_id = None;
+        }
+    }
+
+    /// Fires a projectile from the ship in the direction of `(dx, dy)`, or where
+    /// the ship faces when that is too short to have a direction.
+    fn shoot(&mut self, dx: f32, dy: f32) {
+        let projectile_radius = self.balance.projectile_radius;
+        let projectile_speed = self.balance.projectile_speed;
+
+        if let Some(spaceship_id) = self.spaceship_id {
+            let spaceship = &self.world.transforms[&spaceship_id];
+            let pos_x = spaceship.pos_x;
+            let pos_y = spaceship.pos_y;
+            let (dx, dy) = if (dx * dx + dy * dy).sqrt() < MIN_AIM_DISTANCE {
+                self.world
+                    .headings
+                    .get(&spaceship_id)
+                    .map_or((0.0, -1.0), Heading::direction)
+            } else {
+                (dx, dy)
+            };
+            let d = (dx * dx + dy * dy).sqrt();
+
+            self.events.push(SimEvent::Shot);
+            let entity = self.spawn_body(
+                Transform {
+                    pos_x,
+                    pos_y,
+                    vel_x: projectile_speed * dx / d,
+                    vel_y: projectile_speed * dy / d,
+                    acc_x: 0.0,
+                    acc_y: 0.0,
+                },
+                Faction::Projectile,
+                projectile_radius,
+                [0.7, 0.9, 0.2, 1.0],
+                None,
+            );
+            self.world
+                .lifetimes
+                .insert(entity, Lifetime { ticks_left: 50.0 });
+            self.add_sweep(entity);
+        }
+    }
+
+    /// How far along the run is towards winning, from 0 to 1.
+    fn progress(&self, now: f32) -> f32 {
+        let level = match &self.level {
+            Some(level) => level,
+            None => return self.victory_progress + self.balance.victory_progress_tick,
+        };
+        match level.win {
+            Goal::ClearWaves => {
+                let spawned: u32 = self.wave_spawned.iter().sum();
+                let placed = level.meteors.len() as u32;
+                (spawned + placed) as f32 / level.total_meteors() as f32
+            }
+            Goal::Survive { seconds } => now / seconds_to_ticks(seconds),
+            Goal::Progress => self.victory_progress + self.balance.victory_progress_tick,
+        }
+    }
+
+    /// Clearing the waves also takes every meteor still around, fragments included.
+    fn goal_reached(&self) -> bool {
+        if self.is_endless() {
+            return false;
+        }
+        let clear_waves = matches!(
+            self.level,
+            Some(Level {
+                win: Goal::ClearWaves,
+                ..
+            })
+        );
+        self.victory_progress >= 1.0 && (!clear_waves || self.world.meteors.is_empty())
+    }
+
+    fn too_many_earth_hits(&self) -> bool {
+        let max_earth_hits = self
+            .level
+            .as_ref()
+            .and_then(|level| level.lose.max_earth_hits);
+        match max_earth_hits {
+            Some(max_earth_hits) => self.earth_hits > max_earth_hits,
+            None => false,
+        }
+    }
+
+    fn time_is_up(&self, now: f32) -> bool {
+        let time_limit = self.level.as_ref().and_then(|level| level.lose.time_limit);
+        match time_limit {
+            Some(time_limit) => now >= seconds_to_ticks(time_limit),
+            None => false,
+        }
+    }
+
+    /// Levels bring their own goals, so only the endless stream can be endless.
+    pub fn is_endless(&self) -> bool {
+        self.endless && self.level.is_none()
+    }
+
+    /// The run's score so far, or its final score once it is over.
+    pub fn score(&self) -> Score {
+        let population_million = match self.victory_result {
+            Some(GameVictoryResult::OverPopulation) => 0.0,
+            _ => self.population_million,
+        };
+        let seconds = self.ticks as f32 / TICKS_PER_SECOND as f32;
+        let balance = &self.balance;
+        Score {
+            meteors_destroyed: self.meteors_destroyed,
+            population_million,
+            seconds,
+            meteor_points: (self.meteors_destroyed as f32 * balance.score_per_meteor) as u32,
+            population_points: (population_million * balance.score_per_million) as u32,
+            time_points: (seconds * balance.score_per_second) as u32,
+        }
+    }
+
+    /// Advances the game by a single fixed step and returns what happened during it.
+    pub fn tick(&mut self, input: &TickInput) -> Vec<SimEvent> {
+        let controls = &input.controls;
+        self.ticks += 1;
+        let now = self.ticks as f32;
+
+        if let Some(balance) = &input.balance {
+            self.balance = balance.clone();
+        }
+
+        if let Some(level) = self.level.take() {
+            self.spawn_waves(&level, now);
+            self.level = Some(level);
+        } else {
+            // Not clamped like the sizes in `generate_meteor`: meteors keep coming
+            // faster for as long as an endless run lasts.
+            let meteor_spawn_interval = seconds_to_ticks(self.balance.meteor_base_spawn_interval)
+                / difficulty_factor(self.victory_progress);
+            if let Some(mut next_meteor_spawn) = self.next_meteor_spawn {
+                while now >= next_meteor_spawn {
+                    self.generate_meteor();
+                    next_meteor_spawn += meteor_spawn_interval;
+                }
+                self.next_meteor_spawn = Some(next_meteor_spawn);
+            } else {
+                self.next_meteor_spawn = Some(now + meteor_spawn_interval);
+            }
+        }
+
+        if controls.shooting && !self.was_shooting {
+            self.next_shot = 0.0;
+        }
+        self.was_shooting = controls.shooting;
+
+        if let Some(spaceship_id) = self.spaceship_id {
+            let spaceship_tr = self.world.transforms.get_mut(&spaceship_id).unwrap();
+            match self.flight_model {
+                FlightModel::Classic => steer_classic(spaceship_tr, controls, &self.balance),
+                FlightModel::Thrust => {
+                    let heading = self.world.headings.get_mut(&spaceship_id).unwrap();
+                    steer_thrust(spaceship_tr, heading, controls, &self.balance);
+                }
+            }
+            slow_system(&mut self.world, spaceship_id);
+
+            if controls.shooting && now >= self.next_shot {
+                let (dir_x, dir_y) = if controls.aim_dir_x != 0.0 || controls.aim_dir_y != 0.0 {
+                    (controls.aim_dir_x, controls.aim_dir_y)
+                } else {
+                    let spaceship = &self.world.transforms[&spaceship_id];
+                    (
+                        controls.aim_x - spaceship.pos_x,
+                        controls.aim_y - spaceship.pos_y,
+                    )
+                };
+                self.shoot(dir_x, dir_y);
+                self.next_shot = now + seconds_to_ticks(self.balance.shooting_speed);
+            }
+        }
+
+        if self.mode.has_gravity() {
+            gravity_system(&mut self.world, self.balance.gravity_constant);
+        }
+        homing_system(&mut self.world, self.spaceship_id);
+        movement_system(&mut self.world);
+        for (transform, radius, rule) in trail_system(&mut self.world) {
+            self.add_slow_patch(transform, radius, rule);
+        }
+
+        for entity in lifetime_system(&mut self.world) {
+            self.despawn(entity);
+        }
+        for entity in burning_system(&mut self.world, self.balance.meteor_destroy_radius) {
+            self.despawn(entity);
+        }
+
+        let collisions = find_collisions(&self.world);
+        let results = process_collisions(self, &collisions);
+        self.spaceship_hp -= results.ship_damage;
+        self.population_million -= results.population_damage;
+        cleanup_destroyed(self, &results.destroyed_ids);
+        add_new(self, results.created);
+
+        self.population_million *= self.balance.pop_multi_factor;
+        self.victory_progress = self.progress(now);
+
+        if self.victory_result.is_none() {
+            if self.population_million <= 0.0 {
+                self.victory_result = Some(GameVictoryResult::EveryoneDead);
+            } else if self.spaceship_hp <= 0.0 {
+                self.victory_result = Some(GameVictoryResult::ShipDestroyed);
+                if let Some(spaceship_id) = self.spaceship_id {
+                    self.despawn(spaceship_id);
+                }
+            } else if self.population_million >= self.balance.overpop_limit {
+                self.victory_result = Some(GameVictoryResult::OverPopulation);
+            } else if self.too_many_earth_hits() {
+                self.victory_result = Some(GameVictoryResult::EarthOverrun);
+            } else if self.goal_reached() {
+                self.victory_result = Some(GameVictoryResult::Victory);
+            } else if self.time_is_up(now) {
+                self.victory_result = Some(GameVictoryResult::TimeUp);
+            }
+            if let Some(victory_result) = &self.victory_result {
+                self.events.push(SimEvent::Finished(victory_result.clone()));
+            }
+        }
+
+        if self.population_million > self.balance.overpop_warning_number {
+            self.maybe_make_overpopulation_warning(now);
+        } else {
+            self.next_overpop_warning_enabled = true;
+        }
+
+        self.spaceship_hp = self.spaceship_hp.max(0.0);
+        self.population_million = self.population_million.max(0.0);
+        if !self.is_endless() {
+            self.victory_progress = self.victory_progress.min(1.0);
+        }
+
+        std::mem::take(&mut self.events)
+    }
+}
+
+fn steer_classic(spaceship_tr: &mut Transform, controls: &Controls, balance: &Balance) {
+    let axis_x = controls.axis_x();
+    if axis_x != 0.0 {
+        spaceship_tr.acc_x += balance.acc_step_x * axis_x;
+    } else {
+        spaceship_tr.acc_x =
+            spaceship_tr.acc_x.signum() * (spaceship_tr.acc_x.abs() - balance.acc_step_x)
+    }
+
+    let axis_y = controls.axis_y();
+    if axis_y != 0.0 {
+        spaceship_tr.acc_y += balance.acc_step_y * axis_y;
+    } else {
+        spaceship_tr.acc_y =
+            spaceship_tr.acc_y.signum() * (spaceship_tr.acc_y.abs() - balance.acc_step_y)
+    }
+
+    spaceship_tr.acc_x = clamp(spaceship_tr.acc_x, -balance.max_acc_x, balance.max_acc_x);
+    spaceship_tr.acc_y = clamp(spaceship_tr.acc_y, -balance.max_acc_y, balance.max_acc_y);
+    spaceship_tr.vel_x = clamp(spaceship_tr.vel_x, -balance.max_speed_x, balance.max_speed_x);
+    spaceship_tr.vel_y = clamp(spaceship_tr.vel_y, -balance.max_speed_y, balance.max_speed_y);
+}
+
+/// Left and right turn the ship, up thrusts along its heading and drag slowly
+/// bleeds off speed, which also caps it at `ship_thrust / ship_drag`.
+fn steer_thrust(
+    spaceship_tr: &mut Transform,
+    heading: &mut Heading,
+    controls: &Controls,
+    balance: &Balance,
+) {
+    heading.angle += balance.ship_turn_rate * controls.axis_x();
+    heading.angle %= std::f32::consts::PI * 2.0;
+
+    // Only pushing forward thrusts; there are no retro rockets.
+    let thrust = -controls.axis_y();
+    if thrust > 0.0 {
+        let (dir_x, dir_y) = heading.direction();
+        spaceship_tr.vel_x += balance.ship_thrust * thrust * dir_x;
+        spaceship_tr.vel_y += balance.ship_thrust * thrust * dir_y;
+    }
+    spaceship_tr.vel_x *= 1.0 - balance.ship_drag;
+    spaceship_tr.vel_y *= 1.0 - balance.ship_drag;
+}
+
+/// A polygon around the ship sprite, built from `SHIP_PROFILE` and going round
+/// the same way as the sprite is wrapped around its circle.
+fn ship_shape() -> ColliderShape {
+    let corners = SHIP_PROFILE.len() * 4;
+    let points = (0..corners)
+        .map(|i| {
+            let angle = i as f32 * 2.0 * std::f32::consts::PI / corners as f32;
+            let distance = SHIP_PROFILE[i % SHIP_PROFILE.len()];
+            (distance * angle.sin(), distance * angle.cos())
+        })
+        .collect();
+    ColliderShape::Polygon(points)
+}
+
+/// How much faster meteors come, and how much bigger they get, at `progress`.
+/// Endless runs go past a progress of 1; only the spawn rate follows them there.
+fn difficulty_factor(progress: f32) -> f32 {
+    1.0 + progress * 1.5
+}
+
+/// Converts a duration from the balance into simulation ticks.
+pub fn seconds_to_ticks(seconds: f32) -> f32 {
+    seconds * TICKS_PER_SECOND as f32
+}
+
+fn clamp(value: f32, min: f32, max: f32) -> f32 {
+    value.max(min).min(max)
+}
+
+/// Pulls every collider towards the gravity wells. Gravity changes velocity
+/// directly, so it doesn't interfere with the ship's own acceleration.
+fn gravity_system(world: &mut World, gravity_constant: f32) {
+    let wells: Vec<(f32, f32, f32, f32)> = world
+        .gravity_wells
+        .iter()
+        .map(|(well, gravity_well)| {
+            let transform = &world.transforms[well];
+            // Nothing gets closer than the surface without hitting it, so cap the
+            // pull there instead of letting it blow up near the center.
+            let surface = world.colliders.get(well).map_or(0.0, |c| c.radius);
+            (transform.pos_x, transform.pos_y, gravity_well.mass, surface)
+        })
+        .collect();
+
+    for (entity, transform) in world.transforms.iter_mut() {
+        if !world.colliders.contains_key(entity) || world.gravity_wells.contains_key(entity) {
+            continue;
+        }
+        for &(pos_x, pos_y, mass, surface) in &wells {
+            let dx = pos_x - transform.pos_x;
+            let dy = pos_y - transform.pos_y;
+            let dist = (dx * dx + dy * dy).sqrt();
+            if dist == 0.0 {
+                continue;
+            }
+            let pull = gravity_constant * mass / dist.max(surface).powi(2);
+            transform.vel_x += pull * dx / dist;
+            transform.vel_y += pull * dy / dist;
+        }
+    }
+}
+
+/// Turns homing entities towards the target, up to their top speed.
+fn homing_system(world: &mut World, target: Option<Entity>) {
+    let (target_x, target_y) = match target.and_then(|target| world.transforms.get(&target)) {
+        Some(target) => (target.pos_x, target.pos_y),
+        None => return,
+    };
+    for (entity, homing) in &world.homing {
+        let transform = world.transforms.get_mut(entity).unwrap();
+        let dx = target_x - transform.pos_x;
+        let dy = target_y - transform.pos_y;
+        let distance = (dx * dx + dy * dy).sqrt();
+        if distance == 0.0 {
+            continue;
+        }
+        transform.vel_x += homing.rule.acceleration * dx / distance;
+        transform.vel_y += homing.rule.acceleration * dy / distance;
+        let speed = (transform.vel_x * transform.vel_x + transform.vel_y * transform.vel_y).sqrt();
+        if speed > homing.rule.max_speed {
+            transform.vel_x *= homing.rule.max_speed / speed;
+            transform.vel_y *= homing.rule.max_speed / speed;
+        }
+    }
+}
+
+/// Slows the ship down while it is in a slow field. Overlapping fields don't add
+/// up; the strongest one wins.
+fn slow_system(world: &mut World, ship: Entity) {
+    let ship_transform = &world.transforms[&ship];
+    let slowdown = world
+        .slow_fields
+        .iter()
+        .filter(|(entity, field)| {
+            dist_transform(&world.transforms[entity], ship_transform) < field.radius
+        })
+        .map(|(_, field)| field.slowdown)
+        .fold(0.0, f32::max);
+    if slowdown > 0.0 {
+        let ship_transform = world.transforms.get_mut(&ship).unwrap();
+        ship_transform.vel_x *= 1.0 - slowdown;
+        ship_transform.vel_y *= 1.0 - slowdown;
+    }
+}
+
+/// Counts down trail emitters and returns the patches due this tick: where each
+/// goes, its radius and the rule it was left by.
+fn trail_system(world: &mut World) -> Vec<(Transform, f32, TrailRule)> {
+    let mut patches = vec![];
+    for (entity, emitter) in world.trail_emitters.iter_mut() {
+        emitter.ticks_to_next -= 1.0;
+        if emitter.ticks_to_next > 0.0 {
+            continue;
+        }
+        emitter.ticks_to_next += emitter.rule.interval;
+        let transform = &world.transforms[entity];
+        let radius = world.colliders.get(entity).map_or(0.0, |c| c.radius);
+        patches.push((
+            Transform {
+                pos_x: transform.pos_x,
+                pos_y: transform.pos_y,
+                ..Default::default()
+            },
+            radius * emitter.rule.radius_ratio,
+            emitter.rule,
+        ));
+    }
+    patches
+}
+
+/// Applies acceleration and velocity, wrapping anything that leaves the screen
+/// around to the other side, and scrolls circle textures.
+fn movement_system(world: &mut World) {
+    for (entity, transform) in world.transforms.iter_mut() {
+        let from_x = transform.pos_x;
+        let from_y = transform.pos_y;
+
+        transform.vel_x += transform.acc_x;
+        transform.vel_y += transform.acc_y;
+
+        transform.pos_x += transform.vel_x;
+        transform.pos_y += transform.vel_y;
+
+        let size_dist = world
+            .circles
+            .get(entity)
+            .map_or(0.0, |circle| circle.radius);
+
+        let mut wrapped = false;
+        if transform.pos_x > 1.0 + size_dist * 1.1 {
+            transform.pos_x = -size_dist;
+            wrapped = true;
+        } else if transform.pos_x < -size_dist * 1.1 {
+            transform.pos_x = 1.0 + size_dist;
+            wrapped = true;
+        }
+        if transform.pos_y > 1.0 + size_dist * 1.1 {
+            transform.pos_y = -size_dist;
+            wrapped = true;
+        } else if transform.pos_y < -size_dist * 1.1 {
+            transform.pos_y = 1.0 + size_dist;
+            wrapped = true;
+        }
+
+        if let Some(sweep) = world.sweeps.get_mut(entity) {
+            if wrapped {
+                // Came in from beyond the opposite edge rather than across the screen.
+                sweep.from_x = transform.pos_x - transform.vel_x;
+                sweep.from_y = transform.pos_y - transform.vel_y;
+            } else {
+                sweep.from_x = from_x;
+                sweep.from_y = from_y;
+            }
+        }
+    }
+
+    for circle in world.circles.values_mut() {
+        let render_coords = &mut circle.render_coords;
+        render_coords.pos_x += render_coords.vel_x;
+        render_coords.pos_y += render_coords.vel_y;
+    }
+}
+
+/// Counts down lifetimes and returns the entities whose time is up.
+fn lifetime_system(world: &mut World) -> Vec<Entity> {
+    let mut expired = vec![];
+    for (entity, lifetime) in world.lifetimes.iter_mut() {
+        lifetime.ticks_left -= 1.0;
+        if lifetime.ticks_left <= 0.0 {
+            expired.push(*entity);
+        }
+    }
+    expired
+}
+
+/// Shrinks burning entities and returns the ones that burned up.
+fn burning_system(world: &mut World, destroy_radius: f32) -> Vec<Entity> {
+    let mut burned_up = vec![];
+    for entity in world.burning.keys() {
+        if let Some(collider) = world.colliders.get_mut(entity) {
+            let decay_size_factor = get_decay_size_factor(collider.radius, destroy_radius);
+            let decay_rate = 0.0005 + decay_size_factor * 0.03;
+            collider.radius *= 1.0 - decay_rate;
+            if let Some(circle) = world.circles.get_mut(entity) {
+                circle.radius = collider.radius;
+            }
+            if collider.radius < destroy_radius {
+                burned_up.push(*entity);
+            }
+        }
+    }
+    burned_up
+}
+
+struct MeteorData {
+    transform: Transform,
+    radius: f32,
+    kind: MeteorKind,
+}
+
+struct CollisionResults {
+    created: Vec<MeteorData>,
+    destroyed_ids: Vec<Entity>,
+    ship_damage: f32,
+    population_damage: f32,
+}
+
+fn gen_safe_range(rng: &mut GameRng, first: f32, second: f32) -> f32 {
+    if first == second {
+        first
+    } else if first > second {
+        rng.gen_range(second, first)
+    } else {
+        rng.gen_range(first, second)
+    }
+}
+
+pub fn population_to_string(population: f32) -> String {
+    if population > 400.0 {
+        format!("{:.1}B", population / 1000.0)
+    } else {
+        format!("{:.0}M", population)
+    }
+}
+
+fn process_collisions(sim: &mut Simulation, collisions: &[Collision]) -> CollisionResults {
+    let mut results = CollisionResults {
+        created: Vec::new(),
+        destroyed_ids: Vec::new(),
+        ship_damage: 0.0,
+        population_damage: 0.0,
+    };
+    let mut destroyed_unique = HashSet::<Entity>::new();
+
+    for collision in collisions {
+        let first_faction = sim.world.factions[&collision.first];
+        let second_faction = sim.world.factions[&collision.second];
+        match (first_faction, second_faction) {
+            (Faction::Ship, Faction::Earth) | (Faction::Earth, Faction::Ship) => {
+                results.ship_damage = 1000.0;
+            }
+            (Faction::Ship, Faction::Meteor) | (Faction::Meteor, Faction::Ship) => {
+                let collider = if first_faction == Faction::Meteor {
+                    collision.first
+                } else {
+                    collision.second
+                };
+                results.ship_damage += sim
+                    .balance
+                    .ship_damage(sim.world.colliders[&collider].radius)
+                    * damage_factor(&sim.world, collider);
+                destroyed_unique.insert(collider);
+                sim.events.push(SimEvent::ShipHit {
+                    pos_x: collision.impact_x,
+                    pos_y: collision.impact_y,
+                });
+            }
+            (Faction::Earth, Faction::Meteor) | (Faction::Meteor, Faction::Earth) => {
+                let collider = if first_faction == Faction::Meteor {
+                    collision.first
+                } else {
+                    collision.second
+                };
+                let damage = sim
+                    .balance
+                    .earth_damage(sim.world.colliders[&collider].radius)
+                    * damage_factor(&sim.world, collider);
+                results.population_damage += damage;
+                destroyed_unique.insert(collider);
+                sim.earth_hits += 1;
+                let damage = sim.population_million.min(damage);
+                sim.events.push(SimEvent::EarthHit {
+                    pos_x: collision.impact_x,
+                    pos_y: collision.impact_y,
+                    damage,
+                });
+            }
+            (Faction::Earth, Faction::Projectile) => {
+                destroyed_unique.insert(collision.second);
+            }
+            (Faction::Projectile, Faction::Earth) => {
+                destroyed_unique.insert(collision.first);
+            }
+            (Faction::Meteor, Faction::Projectile) | (Faction::Projectile, Faction::Meteor) => {
+                let (collider, projectile) = if first_faction == Faction::Meteor {
+                    (collision.first, collision.second)
+                } else {
+                    (collision.second, collision.first)
+                };
+                destroyed_unique.insert(projectile);
+                if let Some(meteor) = sim.world.meteors.get_mut(&collider) {
+                    if meteor.hits_left > 1 {
+                        meteor.hits_left -= 1;
+                        sim.events.push(SimEvent::MeteorDented {
+                            pos_x: collision.impact_x,
+                            pos_y: collision.impact_y,
+                        });
+                        continue;
+                    }
+                }
+
+                split_meteor(sim, collider, &mut results.created);
+                sim.meteors_destroyed += 1;
+                sim.events.push(SimEvent::MeteorExploded {
+                    pos_x: collision.impact_x,
+                    pos_y: collision.impact_y,
+                });
+                destroyed_unique.insert(collider);
+            }
+            (Faction::Meteor, Faction::Meteor) => {
+                bounce_meteors(sim, collision, &mut results, &mut destroyed_unique);
+            }
+            _ => {}
+        };
+    }
+    for destroyed in destroyed_unique {
+        results.destroyed_ids.push(destroyed);
+    }
+
+    results
+}
+
+fn meteor_kind(world: &World, entity: Entity) -> MeteorKind {
+    world
+        .meteors
+        .get(&entity)
+        .map_or(MeteorKind::Rock, |meteor| meteor.kind)
+}
+
+fn damage_factor(world: &World, entity: Entity) -> f32 {
+    meteor_kind(world, entity).meteor_type().damage_factor
+}
+
+/// Breaks a meteor destroyed by a projectile into the fragments its type calls
+/// for. A single fragment flies off roughly back the way the meteor came; more
+/// burst out evenly all around.
+fn split_meteor(sim: &mut Simulation, entity: Entity, created: &mut Vec<MeteorData>) {
+    let split = meteor_kind(&sim.world, entity).meteor_type().split;
+    let transform = sim.world.transforms[&entity].clone();
+    let radius = sim.world.colliders[&entity].radius;
+
+    if split.fragments == 1 {
+        let radius_ratio = gen_safe_range(&mut sim.rng, split.min_ratio, split.max_ratio);
+        let vel_x = gen_safe_range(
+            &mut sim.rng,
+            -transform.vel_x,
+            -transform.vel_x / radius_ratio,
+        );
+        let vel_y = gen_safe_range(
+            &mut sim.rng,
+            -transform.vel_y,
+            -transform.vel_y / radius_ratio,
+        );
+        const MAX_GENERATED_VELOCITY: f32 = 0.001;
+        let meteor = MeteorData {
+            transform: Transform {
+                pos_x: transform.pos_x,
+                pos_y: transform.pos_y,
+                vel_x: vel_x.abs().min(MAX_GENERATED_VELOCITY) * vel_y.signum(),
+                vel_y: vel_y.abs().min(MAX_GENERATED_VELOCITY) * vel_x.signum(),
+                acc_x: 0.0,
+                acc_y: 0.0,
+            },
+            radius: radius * radius_ratio,
+            kind: split.kind,
+        };
+        if keeps_fragment(&sim.balance, &meteor) {
+            created.push(meteor);
+        }
+        return;
+    }
+
+    let start_angle = sim.rng.gen_range(0.0, 2.0 * std::f32::consts::PI);
+    for i in 0..split.fragments {
+        let angle = start_angle + i as f32 * 2.0 * std::f32::consts::PI / split.fragments as f32;
+        let (dir_x, dir_y) = (angle.sin(), angle.cos());
+        let speed = gen_safe_range(
+            &mut sim.rng,
+            sim.balance.meteor_min_velocity,
+            sim.balance.meteor_max_velocity,
+        );
+        let radius_ratio = gen_safe_range(&mut sim.rng, split.min_ratio, split.max_ratio);
+        let meteor = MeteorData {
+            transform: Transform {
+                pos_x: transform.pos_x + dir_x * radius * 0.5,
+                pos_y: transform.pos_y + dir_y * radius * 0.5,
+                vel_x: transform.vel_x + dir_x * speed,
+                vel_y: transform.vel_y + dir_y * speed,
+                acc_x: 0.0,
+                acc_y: 0.0,
+            },
+            radius: radius * radius_ratio,
+            kind: split.kind,
+        };
+        if keeps_fragment(&sim.balance, &meteor) {
+            created.push(meteor);
+        }
+    }
+}
+
+/// Fragments too small to see, or right at the edge of the screen, are dropped.
+fn keeps_fragment(balance: &Balance, meteor: &MeteorData) -> bool {
+    meteor.radius > balance.meteor_destroy_radius
+        && meteor.transform.pos_x.abs() > 0.02
+        && meteor.transform.pos_x.abs() < 0.98
+        && meteor.transform.pos_y.abs() > 0.02
+        && meteor.transform.pos_y.abs() < 0.98
+}
+
+/// Everything has the same density, so mass goes with area.
+fn mass(radius: f32) -> f32 {
+    radius * radius
+}
+
+/// Pushes two meteors apart with an impulse along the line between their centers
+/// and breaks a piece off each, more the harder they hit. Does nothing when they
+/// are already moving apart, e.g. while still overlapping after a bounce.
+fn bounce_meteors(
+    sim: &mut Simulation,
+    collision: &Collision,
+    results: &mut CollisionResults,
+    destroyed: &mut HashSet<Entity>,
+) {
+    let t1 = &sim.world.transforms[&collision.first];
+    let t2 = &sim.world.transforms[&collision.second];
+    let m1 = mass(sim.world.colliders[&collision.first].radius);
+    let m2 = mass(sim.world.colliders[&collision.second].radius);
+
+    let dx = t2.pos_x - t1.pos_x;
+    let dy = t2.pos_y - t1.pos_y;
+    let dist = (dx * dx + dy * dy).sqrt();
+    if dist == 0.0 {
+        return;
+    }
+    let normal = (dx / dist, dy / dist);
+    let closing_speed = (t1.vel_x - t2.vel_x) * normal.0 + (t1.vel_y - t2.vel_y) * normal.1;
+    if closing_speed <= 0.0 {
+        return;
+    }
+
+    let reduced_mass = m1 * m2 / (m1 + m2);
+    let impulse = (1.0 + sim.balance.meteor_restitution) * reduced_mass * closing_speed;
+    let impact_energy = 0.5 * reduced_mass * closing_speed * closing_speed;
+    for &(entity, mass, direction) in &[(collision.first, m1, -1.0), (collision.second, m2, 1.0)] {
+        let transform = sim.world.transforms.get_mut(&entity).unwrap();
+        transform.vel_x += direction * impulse / mass * normal.0;
+        transform.vel_y += direction * impulse / mass * normal.1;
+
+        let shattered = impact_energy / (sim.balance.meteor_fracture_energy * mass);
+        chip_meteor(
+            sim,
+            entity,
+            shattered.min(1.0),
+            normal,
+            closing_speed,
+            results,
+            destroyed,
+        );
+    }
+    sim.events.push(SimEvent::MeteorBounced);
+}
+
+/// Breaks the `shattered` share of a meteor's area off into two equal fragments.
+/// They leave sideways to the impact in opposite directions, so the pieces together
+/// keep the meteor's momentum.
+fn chip_meteor(
+    sim: &mut Simulation,
+    entity: Entity,
+    shattered: f32,
+    normal: (f32, f32),
+    closing_speed: f32,
+    results: &mut CollisionResults,
+    destroyed: &mut HashSet<Entity>,
+) {
+    let destroy_radius = sim.balance.meteor_destroy_radius;
+    let radius = sim.world.colliders[&entity].radius;
+    let core_radius = radius * (1.0 - shattered).sqrt();
+    let fragment_radius = radius * (shattered / 2.0).sqrt();
+
+    sim.world.colliders.get_mut(&entity).unwrap().radius = core_radius;
+    if let Some(circle) = sim.world.circles.get_mut(&entity) {
+        circle.radius = core_radius;
+    }
+    if core_radius < destroy_radius {
+        destroyed.insert(entity);
+    }
+    if fragment_radius < destroy_radius {
+        return;
+    }
+
+    let kind = meteor_kind(&sim.world, entity);
+    let transform = &sim.world.transforms[&entity];
+    let tangent = (-normal.1, normal.0);
+    let offset = core_radius + fragment_radius;
+    let spread = 0.5 * shattered * closing_speed;
+    for &side in &[-1.0, 1.0] {
+        results.created.push(MeteorData {
+            transform: Transform {
+                pos_x: transform.pos_x + side * tangent.0 * offset,
+                pos_y: transform.pos_y + side * tangent.1 * offset,
+                vel_x: transform.vel_x + side * tangent.0 * spread,
+                vel_y: transform.vel_y + side * tangent.1 * spread,
+                acc_x: 0.0,
+                acc_y: 0.0,
+            },
+            radius: fragment_radius,
+            kind,
+        });
+    }
+}
+
+fn cleanup_destroyed(sim: &mut Simulation, destroyed_ids: &[Entity]) {
+    for entity in destroyed_ids {
+        sim.despawn(*entity);
+    }
+}
+
+fn add_new(sim: &mut Simulation, created: Vec<MeteorData>) {
+    for meteor in created {
+        sim.add_meteor(meteor.transform, meteor.radius, meteor.kind);
+    }
+}
+
+pub fn get_decay_size_factor(radius: f32, destroy_radius: f32) -> f32 {
+    let relative_size = (radius - destroy_radius) / (0.02 - destroy_radius);
+    (0.3 - relative_size).max(0.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::level::{LoseRules, PlacedMeteor};
+
+    fn new_sim(seed: u64) -> Simulation {
+        Simulation::new(
+            seed,
+            GameMode::Classic,
+            FlightModel::Classic,
+            Balance::default(),
+            None,
+            false,
+        )
+    }
+
+    /// Input that keeps changing, so every system gets exercised.
+    fn scripted_input(tick: u32) -> TickInput {
+        let phase = tick / 90;
+        let controls = Controls {
+            left_right: match phase % 3 {
+                0 => Some(Direction::Left),
+                1 => Some(Direction::Right),
+                _ => None,
+            },
+            up_down: match phase % 4 {
+                0 => Some(Direction::Up),
+                2 => Some(Direction::Down),
+                _ => None,
+            },
+            shooting: tick % 200 < 120,
+            aim_x: (tick as f32 * 0.05).sin() * 0.5 + 0.5,
+            aim_y: (tick as f32 * 0.031).cos() * 0.5 + 0.5,
+            ..Default::default()
+        };
+        TickInput {
+            controls,
+            balance: None,
+        }
+    }
+
+    fn run(seed: u64, ticks: u32) -> Simulation {
+        let mut sim = new_sim(seed);
+        for tick in 0..ticks {
+            sim.tick(&scripted_input(tick));
+        }
+        sim
+    }
+
+    fn transform_bits(world: &World) -> Vec<(Entity, [u32; 6])> {
+        world
+            .transforms
+            .iter()
+            .map(|(&entity, t)| {
+                let bits = [
+                    t.pos_x.to_bits(),
+                    t.pos_y.to_bits(),
+                    t.vel_x.to_bits(),
+                    t.vel_y.to_bits(),
+                    t.acc_x.to_bits(),
+                    t.acc_y.to_bits(),
+                ];
+                (entity, bits)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn same_seed_and_input_give_the_same_game() {
+        let first = run(42, 3000);
+        let second = run(42, 3000);
+        assert_eq!(transform_bits(&first.world), transform_bits(&second.world));
+        assert_eq!(
+            first.population_million.to_bits(),
+            second.population_million.to_bits()
+        );
+        assert_eq!(first.spaceship_hp.to_bits(), second.spaceship_hp.to_bits());
+        assert_eq!(first.victory_result, second.victory_result);
+        assert_eq!(first.ticks, second.ticks);
+    }
+
+    #[test]
+    fn another_seed_gives_another_game() {
+        let first = run(42, 3000);
+        let other = run(43, 3000);
+        assert_ne!(transform_bits(&first.world), transform_bits(&other.world));
+    }
+
+    /// Adds a round meteor without a bumpy outline, so contact points are exact.
+    fn place_meteor(sim: &mut Simulation, x: f32, y: f32, vel: (f32, f32), radius: f32) -> Entity {
+        let transform = Transform {
+            pos_x: x,
+            pos_y: y,
+            vel_x: vel.0,
+            vel_y: vel.1,
+            ..Default::default()
+        };
+        sim.add_meteor(transform, radius, MeteorKind::Rock);
+        let entity = *sim.world.meteors.keys().last().unwrap();
+        sim.world.outlines.remove(&entity);
+        entity
+    }
+
+    #[test]
+    fn earth_hit_is_reported_where_the_meteor_touched() {
+        let mut sim = new_sim(1);
+        let top = EARTH_POSITION.y - EARTH_RADIUS;
+        // Touches the Earth two thirds into the tick and ends up well inside it.
+        place_meteor(&mut sim, EARTH_POSITION.x, top - 0.04, (0.0, 0.03), 0.02);
+        let events = sim.tick(&TickInput::default());
+
+        let (pos_x, pos_y) = events
+            .iter()
+            .find_map(|event| match *event {
+                SimEvent::EarthHit { pos_x, pos_y, .. } => Some((pos_x, pos_y)),
+                _ => None,
+            })
+            .expect("the meteor hits the Earth");
+        assert!((pos_x - EARTH_POSITION.x).abs() < 1e-4);
+        assert!((pos_y - top).abs() < 2e-3, "hit at {}", pos_y);
+    }
+
+    #[test]
+    fn fast_meteor_cannot_skip_past_the_ship() {
+        let mut sim = new_sim(1);
+        // Starts above the ship and ends below it, clear of it both times.
+        let meteor = place_meteor(
+            &mut sim,
+            SHIP_START.x,
+            SHIP_START.y - 0.05,
+            (0.0, 0.1),
+            0.005,
+        );
+        let events = sim.tick(&TickInput::default());
+
+        assert!(events
+            .iter()
+            .any(|event| matches!(event, SimEvent::ShipHit { .. })));
+        assert!(!sim.world.transforms.contains_key(&meteor));
+    }
+
+    #[test]
+    fn fast_projectile_cannot_skip_past_a_meteor() {
+        let balance = Balance {
+            projectile_speed: 0.2,
+            ..Balance::default()
+        };
+        let mut sim = Simulation::new(
+            1,
+            GameMode::Classic,
+            FlightModel::Classic,
+            balance,
+            None,
+            false,
+        );
+        let meteor = place_meteor(
+            &mut sim,
+            SHIP_START.x + 0.1,
+            SHIP_START.y,
+            (0.0, 0.0),
+            0.005,
+        );
+        sim.shoot(1.0, 0.0);
+        let events = sim.tick(&TickInput::default());
+
+        assert!(events
+            .iter()
+            .any(|event| matches!(event, SimEvent::MeteorExploded { .. })));
+        assert!(!sim.world.meteors.contains_key(&meteor));
+    }
+
+    #[test]
+    fn meteor_bounce_conserves_momentum_and_applies_restitution() {
+        let balance = Balance {
+            meteor_restitution: 0.6,
+            // Strong enough that nothing chips off, so only the two meteors move.
+            meteor_fracture_energy: 1e9,
+            ..Balance::default()
+        };
+        let mut sim = Simulation::new(
+            1,
+            GameMode::Classic,
+            FlightModel::Classic,
+            balance,
+            None,
+            false,
+        );
+        let before = [(0.005, 0.001), (-0.003, 0.0)];
+        let first = place_meteor(&mut sim, 0.3, 0.2, before[0], 0.02);
+        let second = place_meteor(&mut sim, 0.335, 0.205, before[1], 0.01);
+        let events = sim.tick(&TickInput::default());
+        assert!(events
+            .iter()
+            .any(|event| matches!(event, SimEvent::MeteorBounced)));
+        assert_eq!(sim.world.meteors.len(), 2);
+
+        let masses = [first, second].map(|entity| mass(sim.world.colliders[&entity].radius));
+        let after = [first, second].map(|entity| {
+            let transform = &sim.world.transforms[&entity];
+            (transform.vel_x, transform.vel_y)
+        });
+        let momentum = |velocities: &[(f32, f32); 2]| {
+            (
+                masses[0] * velocities[0].0 + masses[1] * velocities[1].0,
+                masses[0] * velocities[0].1 + masses[1] * velocities[1].1,
+            )
+        };
+        let (before_x, before_y) = momentum(&before);
+        let (after_x, after_y) = momentum(&after);
+        let scale = before_x.abs().max(before_y.abs());
+        assert!((before_x - after_x).abs() < scale * 1e-4);
+        assert!((before_y - after_y).abs() < scale * 1e-4);
+
+        let (first_pos, second_pos) = (
+            &sim.world.transforms[&first],
+            &sim.world.transforms[&second],
+        );
+        let dx = second_pos.pos_x - first_pos.pos_x;
+        let dy = second_pos.pos_y - first_pos.pos_y;
+        let dist = (dx * dx + dy * dy).sqrt();
+        let along_normal = |velocities: &[(f32, f32); 2]| {
+            ((velocities[1].0 - velocities[0].0) * dx + (velocities[1].1 - velocities[0].1) * dy)
+                / dist
+        };
+        let approach = -along_normal(&before);
+        let separation = along_normal(&after);
+        assert!(approach > 0.0);
+        assert!((separation - 0.6 * approach).abs() < approach * 1e-4);
+    }
+
+    #[test]
+    fn meteor_at_rest_falls_towards_the_earth_faster_and_faster() {
+        let mut sim = Simulation::new(
+            1,
+            GameMode::Gravity,
+            FlightModel::Classic,
+            Balance::default(),
+            None,
+            false,
+        );
+        let meteor = place_meteor(&mut sim, EARTH_POSITION.x, 0.2, (0.0, 0.0), 0.02);
+        let mut last_speed = 0.0;
+        for _ in 0..30 {
+            sim.tick(&TickInput::default());
+            let transform = &sim.world.transforms[&meteor];
+            assert_eq!(transform.vel_x, 0.0);
+            // Straight down, towards the Earth below it.
+            assert!(transform.vel_y > last_speed);
+            last_speed = transform.vel_y;
+        }
+        assert!(sim.world.transforms[&meteor].pos_y > 0.2);
+    }
+
+    fn steer(left_right: Option<Direction>, up_down: Option<Direction>) -> TickInput {
+        TickInput {
+            controls: Controls {
+                left_right,
+                up_down,
+                ..Default::default()
+            },
+            balance: None,
+        }
+    }
+
+    fn ship_velocity(sim: &Simulation) -> (f32, f32) {
+        let transform = &sim.world.transforms[&sim.spaceship_id.unwrap()];
+        (transform.vel_x, transform.vel_y)
+    }
+
+    /// A ship that has been pushed forward for a second. With the thrust model it
+    /// faces up and to the right.
+    fn moving_ship(flight_model: FlightModel) -> Simulation {
+        let mut sim = Simulation::new(
+            1,
+            GameMode::Classic,
+            flight_model,
+            Balance::default(),
+            None,
+            false,
+        );
+        if flight_model == FlightModel::Thrust {
+            sim.world
+                .headings
+                .get_mut(&sim.spaceship_id.unwrap())
+                .unwrap()
+                .angle = 0.8;
+        }
+        for _ in 0..60 {
+            sim.tick(&steer(None, Some(Direction::Up)));
+        }
+        sim
+    }
+
+    #[test]
+    fn thrust_ship_keeps_drifting_without_input() {
+        let mut sim = moving_ship(FlightModel::Thrust);
+        let drag = sim.balance.ship_drag;
+        let (start_x, start_y) = ship_velocity(&sim);
+        assert!(start_x > 0.0 && start_y < 0.0);
+        for _ in 0..50 {
+            let (vel_x, vel_y) = ship_velocity(&sim);
+            sim.tick(&TickInput::default());
+            // Only drag takes anything off, and never the direction.
+            let (next_x, next_y) = ship_velocity(&sim);
+            assert!((next_x - vel_x * (1.0 - drag)).abs() < 1e-9);
+            assert!((next_y - vel_y * (1.0 - drag)).abs() < 1e-9);
+        }
+        let (end_x, end_y) = ship_velocity(&sim);
+        assert!(end_x > start_x * 0.5 && end_y < start_y * 0.5);
+    }
+
+    #[test]
+    fn turning_keeps_the_velocity_under_thrust_but_not_under_classic() {
+        let mut thrust = moving_ship(FlightModel::Thrust);
+        let mut classic = moving_ship(FlightModel::Classic);
+        let (thrust_x, thrust_y) = ship_velocity(&thrust);
+        let (classic_x, _) = ship_velocity(&classic);
+        for _ in 0..60 {
+            thrust.tick(&steer(Some(Direction::Left), None));
+            classic.tick(&steer(Some(Direction::Left), None));
+        }
+
+        // The thrust ship only turned, so it still drifts the same way.
+        let (vel_x, vel_y) = ship_velocity(&thrust);
+        assert!((vel_x / vel_y - thrust_x / thrust_y).abs() < 1e-4);
+        let heading = &thrust.world.headings[&thrust.spaceship_id.unwrap()];
+        assert_ne!(heading.angle, 0.8);
+        // The classic ship is pushed to the left instead.
+        assert!(ship_velocity(&classic).0 < classic_x);
+    }
+
+    /// A level with one still meteor in a corner, far from the ship and the Earth.
+    fn level_sim(win: Goal, lose: LoseRules) -> Simulation {
+        let mut level = Level::new("Test");
+        level.win = win;
+        level.lose = lose;
+        level.meteors.push(PlacedMeteor {
+            x: 0.9,
+            y: 0.1,
+            vel_x: 0.0,
+            vel_y: 0.0,
+            radius: 0.02,
+            kind: MeteorKind::Rock,
+        });
+        Simulation::new(
+            1,
+            GameMode::Classic,
+            FlightModel::Classic,
+            Balance::default(),
+            Some(level),
+            false,
+        )
+    }
+
+    /// Ticks without input until the game ends, giving up after `max_ticks`.
+    fn play_out(sim: &mut Simulation, max_ticks: u32) -> Option<(GameVictoryResult, u64)> {
+        for _ in 0..max_ticks {
+            sim.tick(&TickInput::default());
+            if let Some(result) = &sim.victory_result {
+                return Some((result.clone(), sim.ticks));
+            }
+        }
+        None
+    }
+
+    #[test]
+    fn clearing_the_waves_wins_only_once_every_meteor_is_gone() {
+        let mut sim = level_sim(Goal::ClearWaves, LoseRules::default());
+        assert_eq!(play_out(&mut sim, 10), None);
+
+        let meteors: Vec<_> = sim.world.meteors.keys().copied().collect();
+        for meteor in meteors {
+            sim.despawn(meteor);
+        }
+        assert_eq!(
+            play_out(&mut sim, 1).map(|(result, _)| result),
+            Some(GameVictoryResult::Victory)
+        );
+    }
+
+    #[test]
+    fn surviving_wins_when_the_time_is_over() {
+        let mut sim = level_sim(Goal::Survive { seconds: 1.0 }, LoseRules::default());
+        let (result, ticks) = play_out(&mut sim, 1000).expect("the level ends");
+        assert_eq!(result, GameVictoryResult::Victory);
+        assert_eq!(ticks, u64::from(TICKS_PER_SECOND));
+    }
+
+    #[test]
+    fn running_out_of_time_loses() {
+        let lose = LoseRules {
+            time_limit: Some(1.0),
+            max_earth_hits: None,
+        };
+        let mut sim = level_sim(Goal::Survive { seconds: 5.0 }, lose);
+        let (result, ticks) = play_out(&mut sim, 1000).expect("the level ends");
+        assert_eq!(result, GameVictoryResult::TimeUp);
+        assert_eq!(ticks, u64::from(TICKS_PER_SECOND));
+    }
+
+    #[test]
+    fn one_earth_hit_more_than_allowed_loses() {
+        let top = EARTH_POSITION.y - EARTH_RADIUS;
+        for &max_earth_hits in &[0, 1] {
+            let lose = LoseRules {
+                time_limit: None,
+                max_earth_hits: Some(max_earth_hits),
+            };
+            let mut sim = level_sim(Goal::Survive { seconds: 5.0 }, lose);
+            // Small enough that the hit doesn't wipe out the population.
+            place_meteor(&mut sim, EARTH_POSITION.x, top - 0.02, (0.0, 0.03), 0.002);
+            sim.tick(&TickInput::default());
+            assert_eq!(sim.earth_hits, 1);
+            let expected = if max_earth_hits == 0 {
+                Some(GameVictoryResult::EarthOverrun)
+            } else {
+                None
+            };
+            assert_eq!(sim.victory_result, expected);
+        }
+    }
+
+    fn endless_sim() -> Simulation {
+        Simulation::new(
+            1,
+            GameMode::Classic,
+            FlightModel::Classic,
+            Balance::default(),
+            None,
+            true,
+        )
+    }
+
+    #[test]
+    fn endless_score_adds_up_meteors_people_and_time() {
+        let mut sim = endless_sim();
+        sim.meteors_destroyed = 3;
+        sim.population_million = 1234.5;
+        sim.ticks = 10 * u64::from(TICKS_PER_SECOND);
+
+        let score = sim.score();
+        assert_eq!(score.meteors_destroyed, 3);
+        assert_eq!(score.seconds, 10.0);
+        assert_eq!(score.meteor_points, 30);
+        assert_eq!(score.population_points, 123);
+        assert_eq!(score.time_points, 50);
+        assert_eq!(score.total(), 203);
+
+        // Overpopulation takes everyone with it, and their points.
+        sim.victory_result = Some(GameVictoryResult::OverPopulation);
+        let score = sim.score();
+        assert_eq!(score.population_million, 0.0);
+        assert_eq!(score.population_points, 0);
+        assert_eq!(score.total(), 80);
+    }
+
+    #[test]
+    fn endless_run_goes_on_past_full_progress() {
+        let mut sim = endless_sim();
+        sim.victory_progress = 1.5;
+        sim.tick(&TickInput::default());
+        assert!(sim.victory_progress > 1.5);
+        assert_eq!(sim.victory_result, None);
+    }
+
+    #[test]
+    fn endless_spawns_speed_up_past_full_progress_but_sizes_stop_growing() {
+        let balance = Balance {
+            victory_progress_tick: 0.002,
+            ..Balance::default()
+        };
+        let largest = balance.meteor_base_max_size
+            * difficulty_factor(1.0)
+            * MeteorKind::Explosive.meteor_type().size_factor;
+        let mut sim = Simulation::new(
+            1,
+            GameMode::Classic,
+            FlightModel::Classic,
+            balance,
+            None,
+            true,
+        );
+        // Only the spawning is under test, so nothing is allowed to end the run.
+        sim.victory_progress = 0.5;
+        sim.spaceship_hp = f32::MAX;
+        sim.world.colliders.remove(&sim.earth_id.unwrap());
+
+        // The gap to each next spawn, and the progress it was scheduled at.
+        let mut gaps = Vec::new();
+        let mut scheduled = None;
+        while sim.victory_progress < 2.5 {
+            let progress = sim.victory_progress;
+            sim.tick(&TickInput::default());
+            assert_eq!(sim.victory_result, None);
+            if let (Some(previous), Some(next)) = (scheduled, sim.next_meteor_spawn) {
+                if next != previous {
+                    gaps.push((progress, next - previous));
+                }
+            }
+            scheduled = sim.next_meteor_spawn;
+            if progress > 1.0 {
+                assert!(sim
+                    .world
+                    .meteors
+                    .keys()
+                    .all(|meteor| sim.world.colliders[meteor].radius <= largest));
+            }
+        }
+
+        assert!(gaps.windows(2).all(|pair| pair[1].1 <= pair[0].1));
+        let at_full = gaps
+            .iter()
+            .find(|(progress, _)| *progress >= 1.0)
+            .unwrap()
+            .1;
+        let last = gaps.last().unwrap();
+        assert!(last.0 > 2.0);
+        assert!(last.1 < at_full * 0.7);
+    }
+
+    #[test]
+    fn movement_adds_acceleration_then_velocity() {
+        let mut world = World::new();
+        let entity = world.spawn(Transform {
+            pos_x: 0.5,
+            pos_y: 0.5,
+            vel_x: 0.01,
+            vel_y: 0.0,
+            acc_x: 0.0,
+            acc_y: 0.002,
+        });
+        movement_system(&mut world);
+        let transform = &world.transforms[&entity];
+        assert!((transform.vel_y - 0.002).abs() < 1e-7);
+        assert!((transform.pos_x - 0.51).abs() < 1e-6);
+        assert!((transform.pos_y - 0.502).abs() < 1e-6);
+    }
+
+    #[test]
+    fn movement_wraps_at_the_edge_and_sweeps_from_beyond_it() {
+        let mut world = World::new();
+        let entity = world.spawn(Transform {
+            pos_x: 0.995,
+            pos_y: 0.5,
+            vel_x: 0.01,
+            ..Default::default()
+        });
+        world.sweeps.insert(entity, Sweep::default());
+        movement_system(&mut world);
+
+        let transform = &world.transforms[&entity];
+        assert_eq!(transform.pos_x, 0.0);
+        let sweep = &world.sweeps[&entity];
+        assert!((sweep.from_x - -0.01).abs() < 1e-6, "{}", sweep.from_x);
+        assert_eq!(sweep.from_y, 0.5);
+    }
+
+    #[test]
+    fn lifetime_runs_out_after_its_ticks() {
+        let mut world = World::new();
+        let short = world.spawn(Transform::default());
+        let long = world.spawn(Transform::default());
+        world.lifetimes.insert(short, Lifetime { ticks_left: 2.0 });
+        world.lifetimes.insert(long, Lifetime { ticks_left: 3.0 });
+
+        assert!(lifetime_system(&mut world).is_empty());
+        assert_eq!(lifetime_system(&mut world), vec![short]);
+    }
+
+    #[test]
+    fn burning_shrinks_collider_and_circle_together_until_burned_up() {
+        let mut world = World::new();
+        let entity = world.spawn(Transform::default());
+        world.colliders.insert(entity, Collider::circle(0.01));
+        world.circles.insert(
+            entity,
+            Circle {
+                radius: 0.01,
+                color: [1.0; 4],
+                texture: None,
+                render_coords: RenderCoords::default(),
+            },
+        );
+        world.burning.insert(entity, Burning);
+        // Burning needs a collider to shrink.
+        let bare = world.spawn(Transform::default());
+        world.burning.insert(bare, Burning);
+
+        assert!(burning_system(&mut world, 0.002).is_empty());
+        let radius = world.colliders[&entity].radius;
+        assert!(radius < 0.01);
+        assert_eq!(world.circles[&entity].radius, radius);
+
+        let mut ticks = 0;
+        while burning_system(&mut world, 0.002).is_empty() {
+            ticks += 1;
+            assert!(ticks < 10_000, "never burned up");
+        }
+        assert!(world.colliders[&entity].radius < 0.002);
+    }
+
+    fn projectile_velocities(sim: &Simulation) -> Vec<(f32, f32)> {
+        sim.world
+            .factions
+            .iter()
+            .filter(|(_, &faction)| faction == Faction::Projectile)
+            .map(|(entity, _)| {
+                let transform = &sim.world.transforms[entity];
+                (transform.vel_x, transform.vel_y)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn aiming_at_the_ship_itself_shoots_where_it_faces() {
+        for &flight_model in &[FlightModel::Classic, FlightModel::Thrust] {
+            let mut sim = Simulation::new(
+                1,
+                GameMode::Classic,
+                flight_model,
+                Balance::default(),
+                None,
+                false,
+            );
+            let ship = sim.spaceship_id.unwrap();
+            let heading = Heading { angle: 1.0 };
+            let expected = match flight_model {
+                FlightModel::Classic => (0.0, -1.0),
+                FlightModel::Thrust => heading.direction(),
+            };
+            if flight_model == FlightModel::Thrust {
+                sim.world.headings.insert(ship, heading);
+            }
+            sim.shoot(0.0, 0.0);
+
+            let velocities = projectile_velocities(&sim);
+            assert_eq!(velocities.len(), 1);
+            let (vel_x, vel_y) = velocities[0];
+            let speed = sim.balance.projectile_speed;
+            assert!((vel_x - expected.0 * speed).abs() < 1e-6, "{}", vel_x);
+            assert!((vel_y - expected.1 * speed).abs() < 1e-6, "{}", vel_y);
+        }
+    }
+
+    #[test]
+    fn aiming_at_the_ship_keeps_the_game_finite() {
+        let mut sim = new_sim(1);
+        let ship = &sim.world.transforms[&sim.spaceship_id.unwrap()];
+        let controls = Controls {
+            shooting: true,
+            aim_x: ship.pos_x,
+            aim_y: ship.pos_y,
+            ..Default::default()
+        };
+        let input = TickInput {
+            controls,
+            balance: None,
+        };
+        for _ in 0..300 {
+            sim.tick(&input);
+        }
+        assert!(!projectile_velocities(&sim).is_empty());
+        assert!(sim.world.transforms.values().all(|transform| {
+            transform.pos_x.is_finite()
+                && transform.pos_y.is_finite()
+                && transform.vel_x.is_finite()
+                && transform.vel_y.is_finite()
+        }));
+    }
+}
@@ -0,0 +1,261 @@
+//! The screens the game moves between, and the menu shown while paused.
+
+use ld46_keep_it_alive::simulation::GameVictoryResult;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum GameState {
+    Title,
+    LevelSelect,
+    Editor,
+    Playing,
+    Paused,
+    GameOver,
+    Victory,
+}
+
+impl GameState {
+    pub fn from_result(result: &GameVictoryResult) -> GameState {
+        match result {
+            GameVictoryResult::Victory => GameState::Victory,
+            _ => GameState::GameOver,
+        }
+    }
+
+    /// Where `event` takes the game from this state, or `None` when it stays where
+    /// it is. `testing` is set while playing the level being edited, which quitting
+    /// goes back to instead of the title screen.
+    pub fn after(self, event: StateEvent, testing: bool) -> Option<GameState> {
+        match (self, event) {
+            (GameState::Playing, StateEvent::FocusLost) => Some(GameState::Paused),
+            (GameState::Playing, StateEvent::TogglePause) => Some(GameState::Paused),
+            (GameState::Paused, StateEvent::TogglePause) => Some(GameState::Playing),
+            (GameState::Paused, StateEvent::Picked(option)) => match option {
+                PauseOption::Resume | PauseOption::Restart => Some(GameState::Playing),
+                PauseOption::Controls => None,
+                PauseOption::Quit if testing => Some(GameState::Editor),
+                PauseOption::Quit => Some(GameState::Title),
+            },
+            _ => None,
+        }
+    }
+
+    /// Whether the simulation advances. It keeps going after the game is over so
+    /// the world doesn't freeze behind the end screen.
+    pub fn is_running(self) -> bool {
+        match self {
+            GameState::Playing | GameState::GameOver | GameState::Victory => true,
+            GameState::Title | GameState::LevelSelect | GameState::Editor | GameState::Paused => {
+                false
+            }
+        }
+    }
+}
+
+/// What moves the game between the playing and paused states.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum StateEvent {
+    /// The window lost focus.
+    FocusLost,
+    /// The pause action, or backing out of the pause menu.
+    TogglePause,
+    /// An option picked in the pause menu.
+    Picked(PauseOption),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PauseOption {
+    Resume,
+    Restart,
+    /// Opens the list of bindings over the pause menu.
+    Controls,
+    /// Gives up the run and goes back to the title screen.
+    Quit,
+}
+
+impl PauseOption {
+    pub const ALL: [PauseOption; 4] = [
+        PauseOption::Resume,
+        PauseOption::Restart,
+        PauseOption::Controls,
+        PauseOption::Quit,
+    ];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            PauseOption::Resume => "Resume",
+            PauseOption::Restart => "Restart",
+            PauseOption::Controls => "Controls",
+            PauseOption::Quit => "Quit",
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct PauseMenu {
+    /// Index into `PauseOption::ALL`.
+    pub selected: usize,
+}
+
+impl PauseMenu {
+    pub fn selected_option(&self) -> PauseOption {
+        PauseOption::ALL[self.selected]
+    }
+
+    pub fn select_previous(&mut self) {
+        self.selected = (self.selected + PauseOption::ALL.len() - 1) % PauseOption::ALL.len();
+    }
+
+    pub fn select_next(&mut self) {
+        self.selected = (self.selected + 1) % PauseOption::ALL.len();
+    }
+}
+
+/// What can be picked on the level select screen.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MenuEntry {
+    /// The endless stream of meteors, won once the space age arrives.
+    Classic,
+    /// The same stream without a victory, played for a score.
+    Endless,
+    /// One of the loaded levels, by index.
+    Level(usize),
+}
+
+/// Entries in front of the loaded levels.
+const GAME_ENTRIES: [MenuEntry; 2] = [MenuEntry::Classic, MenuEntry::Endless];
+
+/// The list of games to pick from: the classic and endless games, then the loaded
+/// levels in order.
+#[derive(Default)]
+pub struct LevelMenu {
+    pub selected: usize,
+}
+
+impl LevelMenu {
+    pub fn entry(index: usize) -> MenuEntry {
+        match GAME_ENTRIES.get(index) {
+            Some(&entry) => entry,
+            None => MenuEntry::Level(index - GAME_ENTRIES.len()),
+        }
+    }
+
+    pub fn selected_entry(&self) -> MenuEntry {
+        LevelMenu::entry(self.selected)
+    }
+
+    pub fn entry_count(level_count: usize) -> usize {
+        GAME_ENTRIES.len() + level_count
+    }
+
+    pub fn select_previous(&mut self, level_count: usize) {
+        let count = LevelMenu::entry_count(level_count);
+        self.selected = (self.selected + count - 1) % count;
+    }
+
+    pub fn select_next(&mut self, level_count: usize) {
+        self.selected = (self.selected + 1) % LevelMenu::entry_count(level_count);
+    }
+
+    pub fn select_level(&mut self, index: usize) {
+        self.selected = GAME_ENTRIES.len() + index;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn losing_focus_pauses_only_a_game_in_play() {
+        assert_eq!(
+            GameState::Playing.after(StateEvent::FocusLost, false),
+            Some(GameState::Paused)
+        );
+        for &state in &[
+            GameState::Title,
+            GameState::LevelSelect,
+            GameState::Editor,
+            GameState::Paused,
+            GameState::GameOver,
+            GameState::Victory,
+        ] {
+            assert_eq!(state.after(StateEvent::FocusLost, false), None);
+        }
+    }
+
+    #[test]
+    fn pause_toggles_between_playing_and_paused() {
+        let paused = GameState::Playing.after(StateEvent::TogglePause, false);
+        assert_eq!(paused, Some(GameState::Paused));
+        let resumed = GameState::Paused.after(StateEvent::TogglePause, false);
+        assert_eq!(resumed, Some(GameState::Playing));
+        // The end screens have no pause.
+        assert_eq!(
+            GameState::GameOver.after(StateEvent::TogglePause, false),
+            None
+        );
+    }
+
+    #[test]
+    fn pause_menu_resumes_and_restarts_into_play() {
+        for &option in &[PauseOption::Resume, PauseOption::Restart] {
+            assert_eq!(
+                GameState::Paused.after(StateEvent::Picked(option), false),
+                Some(GameState::Playing)
+            );
+        }
+        // The controls open on top of the menu.
+        let controls = StateEvent::Picked(PauseOption::Controls);
+        assert_eq!(GameState::Paused.after(controls, false), None);
+    }
+
+    #[test]
+    fn quitting_from_the_pause_menu_goes_back() {
+        let quit = StateEvent::Picked(PauseOption::Quit);
+        assert_eq!(GameState::Paused.after(quit, false), Some(GameState::Title));
+        assert_eq!(GameState::Paused.after(quit, true), Some(GameState::Editor));
+    }
+
+    #[test]
+    fn pause_menu_wraps_around() {
+        let mut menu = PauseMenu::default();
+        menu.select_previous();
+        assert_eq!(menu.selected_option(), PauseOption::Quit);
+        menu.select_next();
+        assert_eq!(menu.selected_option(), PauseOption::Resume);
+        menu.select_next();
+        assert_eq!(menu.selected_option(), PauseOption::Restart);
+    }
+
+    #[test]
+    fn level_entries_come_after_the_games() {
+        assert_eq!(LevelMenu::entry(0), MenuEntry::Classic);
+        assert_eq!(LevelMenu::entry(1), MenuEntry::Endless);
+        assert_eq!(LevelMenu::entry(2), MenuEntry::Level(0));
+        assert_eq!(LevelMenu::entry(4), MenuEntry::Level(2));
+
+        let mut menu = LevelMenu::default();
+        menu.select_level(1);
+        assert_eq!(menu.selected_entry(), MenuEntry::Level(1));
+    }
+
+    #[test]
+    fn level_menu_wraps_around() {
+        let mut menu = LevelMenu::default();
+        menu.select_previous(3);
+        assert_eq!(menu.selected_entry(), MenuEntry::Level(2));
+        menu.select_next(3);
+        assert_eq!(menu.selected_entry(), MenuEntry::Classic);
+    }
+
+    #[test]
+    fn level_menu_without_levels_wraps_between_the_games() {
+        let mut menu = LevelMenu::default();
+        menu.select_next(0);
+        assert_eq!(menu.selected_entry(), MenuEntry::Endless);
+        menu.select_next(0);
+        assert_eq!(menu.selected_entry(), MenuEntry::Classic);
+        menu.select_previous(0);
+        assert_eq!(menu.selected_entry(), MenuEntry::Endless);
+    }
+}